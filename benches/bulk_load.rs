@@ -0,0 +1,69 @@
+/*
+Compares Graph::bulk_load against repeated Graph::insert on an
+N-Triples file, and fails unless bulk_load is the faster of the two;
+1,000,000 generated triples unless a path is given:
+  cargo bench --bench bulk_load [-- path/to/file.nt]
+*/
+use std::{
+  fs::File,
+  io::{BufReader, BufWriter, Write},
+  path::PathBuf,
+  time::{Duration, Instant},
+};
+use OjiDB::Graph;
+
+const TRIPLES: usize = 1_000_000;
+const RUNS: usize = 3;
+
+fn main() {
+  let path = match std::env::args().skip(1).find(|arg| !arg.starts_with("--")) {
+    Some(path) => PathBuf::from(path),
+    None => generate(),
+  };
+  let start = Instant::now();
+  let file = BufReader::new(File::open(&path).expect("Couldn't open N-Triples file"));
+  let loaded = Graph::read_ntriples(file).expect("Couldn't parse N-Triples file");
+  println!("read_ntriples (parse + bulk_load): {:?}", start.elapsed());
+  let triples: Vec<(String, String, String)> = loaded.into_iter().collect();
+  println!("{} triples from {}", triples.len(), path.display());
+
+  let mut inserted = Graph::new();
+  let insert_time = best_of(|| {
+    inserted = Graph::new();
+    for triple in triples.iter().cloned() {
+      inserted.insert(triple);
+    }
+  });
+  println!("repeated insert: {:?}", insert_time);
+
+  let mut bulk = Graph::new();
+  let bulk_time = best_of(|| bulk = Graph::bulk_load(&triples));
+  println!("bulk_load:       {:?}", bulk_time);
+
+  assert_eq!(inserted, bulk, "bulk_load built a different Graph");
+  let speedup = insert_time.as_secs_f64() / bulk_time.as_secs_f64();
+  println!("bulk_load is {:.2}x faster than repeated insert", speedup);
+  assert!(bulk_time < insert_time, "bulk_load was no faster than repeated insert");
+}
+
+/* The fastest of a few runs, so one slow run doesn't decide the comparison */
+fn best_of<F: FnMut()>(mut f: F) -> Duration {
+  (0..RUNS).map(|_| {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+  }).min().unwrap()
+}
+
+fn generate() -> PathBuf {
+  let path = std::env::temp_dir().join("oji_bulk_load.nt");
+  if path.exists() {
+    return path
+  }
+  let mut out = BufWriter::new(File::create(&path).expect("Couldn't create N-Triples file"));
+  for i in 0..TRIPLES {
+    writeln!(out, "<http://ex.org/person/{}> <http://ex.org/p{}> \"value {}\" .",
+             i / 10, i % 7, i).expect("Couldn't write N-Triples file");
+  }
+  path
+}
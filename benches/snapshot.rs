@@ -0,0 +1,35 @@
+/*
+Compares binary snapshots with TripleStore JSON documents
+for the same Graph, by size and by time to load:
+  cargo bench --bench snapshot
+*/
+use std::time::Instant;
+use OjiDB::Graph;
+
+const TRIPLES: usize = 200_000;
+
+fn main() {
+  let mut g = Graph::new();
+  for i in 0..TRIPLES {
+    g.insert((format!("http://ex.org/person/{}", i / 10), format!("http://ex.org/p{}", i % 7), format!("value {}", i)));
+  }
+  let json = g.json().expect("Couldn't write JSON");
+  let snapshot = g.to_snapshot();
+  println!("{} triples", TRIPLES);
+  println!("json:     {:>10} bytes", json.len());
+  println!("snapshot: {:>10} bytes", snapshot.len());
+
+  let start = Instant::now();
+  let from_json = Graph::from_json(&json).expect("Couldn't read JSON");
+  let json_time = start.elapsed();
+  println!("from_json:     {:?}", json_time);
+
+  let start = Instant::now();
+  let from_snapshot = Graph::from_snapshot(&snapshot).expect("Couldn't read snapshot");
+  let snapshot_time = start.elapsed();
+  println!("from_snapshot: {:?}", snapshot_time);
+
+  assert_eq!(from_json, from_snapshot, "Snapshot loaded a different Graph");
+  println!("from_snapshot is {:.2}x faster than from_json",
+           json_time.as_secs_f64() / snapshot_time.as_secs_f64());
+}
@@ -0,0 +1,55 @@
+/*
+Compares pattern scans on a SharedGraph snapshot before and after
+maintenance sorts it, and fails unless the sorted segment answers
+them faster than the hash indexes; 500,000 generated triples:
+  cargo bench --bench sorted_scans
+*/
+use std::time::{Duration, Instant};
+use OjiDB::{Graph, GraphSnapshot, SharedGraph};
+
+const TRIPLES: usize = 500_000;
+const RUNS: usize = 3;
+
+type QueryTriple = (Option<String>, Option<String>, Option<String>);
+
+fn main() {
+  let mut g = Graph::new();
+  for i in 0..TRIPLES {
+    g.insert((format!("http://ex.org/person/{}", i / 10), format!("http://ex.org/p{}", i % 7), format!("value {}", i)));
+  }
+  let mut patterns: Vec<QueryTriple> = (0..TRIPLES / 10).step_by(7)
+                                                        .map(|s| (Some(format!("http://ex.org/person/{}", s)), None, None))
+                                                        .collect();
+  patterns.extend((0..7).map(|p| (None, Some(format!("http://ex.org/p{}", p)), None)));
+  let shared = SharedGraph::from(g);
+
+  let hashed = shared.snapshot();
+  let hash_time = best_of(|| scan(&hashed, &patterns));
+  println!("hash indexes:   {:?}", hash_time);
+
+  let start = Instant::now();
+  let stats = shared.compact();
+  println!("sorted {} triples in {:?}", stats.triples_sorted, start.elapsed());
+  let sorted = shared.snapshot();
+  assert!(sorted.is_sorted(), "compact() didn't sort the Graph");
+  let sorted_time = best_of(|| scan(&sorted, &patterns));
+  println!("sorted segment: {:?}", sorted_time);
+
+  assert_eq!(scan(&hashed, &patterns), scan(&sorted, &patterns), "The sorted segment found different triples");
+  let speedup = hash_time.as_secs_f64() / sorted_time.as_secs_f64();
+  println!("sorted scans are {:.2}x faster", speedup);
+  assert!(sorted_time < hash_time, "Sorted scans were no faster than the hash indexes");
+}
+
+fn scan(snapshot: &GraphSnapshot, patterns: &[QueryTriple]) -> usize {
+  patterns.iter().map(|qt| snapshot.get_triple(qt).len()).sum()
+}
+
+/* The fastest of a few runs, so one slow run doesn't decide the comparison */
+fn best_of<F: FnMut() -> usize>(mut f: F) -> Duration {
+  (0..RUNS).map(|_| {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+  }).min().unwrap()
+}
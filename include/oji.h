@@ -0,0 +1,166 @@
+#ifndef OJI_H
+#define OJI_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+/*
+ * C interface to OjiDB.
+ *
+ * Terms are NUL-terminated UTF-8 strings in Oji's usual form: IRIs and
+ * blank nodes as their text, literals either plain or quoted as
+ * "lex"@lang / "lex"^^<datatype>.
+ *
+ * Functions returning OjiStatus leave a message for oji_last_error()
+ * on the calling thread when they fail. A graph may be read from many
+ * threads at once, but must not be read while it is being modified.
+ * The contracts below are generated from the /// docs in src/util/Ffi.rs.
+ */
+
+typedef enum OjiStatus {
+  OJI_STATUS_OK = 0,
+  OJI_STATUS_NULL_ARGUMENT = 1,
+  /*
+   Not UTF-8, or a term containing a NUL byte
+   */
+  OJI_STATUS_INVALID_STRING = 2,
+  OJI_STATUS_PARSE_ERROR = 3,
+  OJI_STATUS_QUERY_ERROR = 4,
+  OJI_STATUS_IO_ERROR = 5,
+  OJI_STATUS_SERIALIZATION_ERROR = 6,
+  OJI_STATUS_CONSTRAINT_ERROR = 7,
+  /*
+   A bug in Oji; a graph it was changing may be left half-changed
+   */
+  OJI_STATUS_PANIC = 8,
+} OjiStatus;
+
+typedef struct OjiCursor OjiCursor;
+
+typedef struct OjiGraph OjiGraph;
+
+/*
+ Called once per matching triple; the strings are only valid for the
+ duration of the call. Returning non-zero stops the iteration.
+ */
+typedef int (*OjiTripleCallback)(const char *s, const char *p, const char *o, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Message describing the last failure on this thread, or NULL.
+ Borrowed: valid until the next failing call on the same thread.
+ */
+const char *oji_last_error(void);
+
+/*
+ Frees a string returned by oji_graph_json or oji_graph_ntriples,
+ which must not be used afterwards. NULL is ignored.
+ */
+void oji_string_free(char *s);
+
+/*
+ A new, empty graph, owned by the caller until it's passed to
+ oji_graph_free. NULL only if creating it panicked.
+ */
+struct OjiGraph *oji_graph_new(void);
+
+/*
+ Frees a graph from oji_graph_new; neither it nor any string it
+ lent out may be used afterwards. Cursors stay valid. NULL is ignored.
+ */
+void oji_graph_free(struct OjiGraph *graph);
+
+/*
+ Number of triples, 0 for NULL.
+ */
+size_t oji_graph_len(const struct OjiGraph *graph);
+
+/*
+ Inserts the triple s p o. The terms are copied, so the caller
+ keeps ownership of them.
+ */
+enum OjiStatus oji_graph_insert(struct OjiGraph *graph,
+                                const char *s,
+                                const char *p,
+                                const char *o);
+
+/*
+ Removes the triple s p o, if it's there. The caller keeps
+ ownership of the terms.
+ */
+enum OjiStatus oji_graph_remove(struct OjiGraph *graph,
+                                const char *s,
+                                const char *p,
+                                const char *o);
+
+/*
+ Applies a SPARQL Update request; on failure the graph is left unchanged.
+ */
+enum OjiStatus oji_graph_update(struct OjiGraph *graph, const char *request);
+
+/*
+ Calls callback for every triple matching the pattern, where a
+ NULL s, p or o matches anything. user_data is passed through
+ untouched. The graph must not be changed from inside callback.
+ */
+enum OjiStatus oji_graph_match(const struct OjiGraph *graph,
+                               const char *s,
+                               const char *p,
+                               const char *o,
+                               OjiTripleCallback callback,
+                               void *user_data);
+
+/*
+ Runs a query and stores a cursor over its results in *cursor.
+ vars holds n_vars variable names ("$name"), conds holds n_conds
+ patterns as 3 * n_conds consecutive terms; variables in the
+ patterns must be among vars. The cursor copies the results, so
+ the graph may change or be freed while it is in use.
+ The caller owns the cursor and releases it with oji_cursor_free;
+ *cursor is left alone on failure.
+ */
+enum OjiStatus oji_graph_query(const struct OjiGraph *graph,
+                               const char *const *vars,
+                               size_t n_vars,
+                               const char *const *conds,
+                               size_t n_conds,
+                               struct OjiCursor **cursor);
+
+/*
+ Moves to the next result; false once they're exhausted, or for
+ NULL. Call before the first oji_cursor_get.
+ */
+bool oji_cursor_next(struct OjiCursor *cursor);
+
+/*
+ Value of var ("name" or "$name") in the current result, or NULL if
+ it's unbound. Borrowed: valid until the cursor is freed.
+ */
+const char *oji_cursor_get(const struct OjiCursor *cursor, const char *var);
+
+/*
+ Frees a cursor from oji_graph_query, along with every value
+ oji_cursor_get lent out. NULL is ignored.
+ */
+void oji_cursor_free(struct OjiCursor *cursor);
+
+/*
+ The graph as an N-Triples document, or NULL on failure.
+ The caller owns it and frees it with oji_string_free.
+ */
+char *oji_graph_ntriples(const struct OjiGraph *graph);
+
+/*
+ The graph as a TripleStore JSON document, or NULL on failure.
+ The caller owns it and frees it with oji_string_free.
+ */
+char *oji_graph_json(const struct OjiGraph *graph);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* OJI_H */
@@ -0,0 +1,71 @@
+const { test } = require('node:test');
+const assert = require('node:assert');
+const { Graph } = require('../pkg/OjiDB.js');
+
+function sorted(triples) {
+  return triples.map(t => t.join(' ')).sort();
+}
+
+test('insert, remove and replace', () => {
+  const g = new Graph();
+  g.insert(['Gabe', 'likes', 'Rust']);
+  g.insert(['James', 'likes', 'Java']);
+  assert.strictEqual(g.size, 2);
+  g.replace(['Gabe', 'likes', 'Rust'], ['Gabe', 'likes', 'C++']);
+  g.remove(['James', 'likes', 'Java']);
+  assert.deepStrictEqual(g.triples(), [['Gabe', 'likes', 'C++']]);
+});
+
+test('rejects things that are not triples', () => {
+  const g = new Graph();
+  assert.throws(() => g.insert(['Gabe', 'likes']), Error);
+  assert.throws(() => g.insert('Gabe likes Rust'), Error);
+  assert.strictEqual(g.size, 0);
+});
+
+test('json round trip', () => {
+  const g = new Graph();
+  g.insert(['Gabe', 'likes', 'Rust']);
+  g.insert(['Gabe', 'likes', 'C++']);
+  const copy = Graph.fromJson(g.json());
+  assert.deepStrictEqual(sorted(copy.triples()), sorted(g.triples()));
+
+  const other = new Graph();
+  other.insertJson(g.json());
+  other.insert(['James', 'likes', 'Java']);
+  other.removeJson(g.json());
+  assert.deepStrictEqual(other.triples(), [['James', 'likes', 'Java']]);
+  assert.throws(() => Graph.fromJson('{ not json'), /Parse error/);
+});
+
+test('insertJson scopes blank nodes to each document', () => {
+  const g = new Graph();
+  const doc = JSON.stringify([['_:b', 'likes', 'Rust']]);
+  g.insertJson(doc);
+  g.insertJson(doc);
+  assert.strictEqual(g.size, 2);
+  const subjects = new Set(g.triples().map(([s]) => s));
+  assert.strictEqual(subjects.size, 2);
+  assert.ok([...subjects].every(s => s.startsWith('_:')));
+  assert.throws(() => g.insertJson('[["_:c", "likes"]]'), /expected \[s, p, o\]/);
+  assert.strictEqual(g.size, 2);
+});
+
+test('query results are plain objects', () => {
+  const g = new Graph();
+  g.insert(['Gabe', 'likes', 'Rust']);
+  g.insert(['James', 'likes', 'Rust']);
+  g.insert(['James', 'likes', 'Java']);
+  const rows = g.query(['$name'], [['$name', 'likes', 'Rust']]);
+  assert.deepStrictEqual(rows.map(r => r.name).sort(), ['Gabe', 'James']);
+  assert.strictEqual(Object.getPrototypeOf(rows[0]), Object.prototype);
+  assert.throws(() => g.query([], [['$name', 'likes', 'Rust']]), /Undeclared variable/);
+});
+
+test('ask and update', () => {
+  const g = new Graph();
+  g.update('INSERT DATA { Gabe likes Rust }');
+  assert.strictEqual(g.ask([['Gabe', 'likes', 'Rust']]), true);
+  assert.strictEqual(g.ask([['Gabe', 'likes', 'Java']]), false);
+  assert.throws(() => g.update('INSERT NONSENSE'), Error);
+});
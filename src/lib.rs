@@ -4,12 +4,49 @@
 mod util;
 
 pub use util::Graph as Graph;
+pub use util::Dataset as Dataset;
+pub use util::GraphDiff as GraphDiff;
+pub use util::MappedGraph as MappedGraph;
+pub use util::ObservedGraph as ObservedGraph;
+pub use util::GraphChange as GraphChange;
+pub use util::SubscriptionId as SubscriptionId;
+pub use util::PatternMatch as PatternMatch;
+pub use util::SharedGraph as SharedGraph;
+pub use util::GraphSnapshot as GraphSnapshot;
+pub use util::CompactionStats as CompactionStats;
+pub use util::MaintenanceHandle as MaintenanceHandle;
 pub use util::TripleStore as TripleStore;
 pub use util::OjiQuery as OjiQuery;
 pub use util::OjiQueryUnit as OjiQueryUnit;
 pub use util::OjiResult as OjiResult;
 pub use util::OjiResultUnit as OjiResultUnit;
 pub use util::OjiResultCollection as OjiResultCollection;
+pub use util::OjiUpdate as OjiUpdate;
+pub use util::OjiError as OjiError;
+pub use util::IonFormat as IonFormat;
+#[cfg(feature = "wasm")]
+pub use util::JsGraph as JsGraph;
+
+/* Helpers the test modules below share through `use super::*` */
+#[cfg(test)]
+fn t(s: &str, p: &str, o: &str) -> (String, String, String) {
+  (s.to_string(), p.to_string(), o.to_string())
+}
+/* A few hundred triples with blank nodes, escapes, a language tag and a likes cycle, in under 256 terms */
+#[cfg(test)]
+fn sample() -> Graph {
+  let mut g = Graph::new();
+  for i in 0..200 {
+    g.insert((format!("http://ex.org/person/{}", i % 20), format!("http://ex.org/p{}", i % 7), format!("value {}", i % 50)));
+  }
+  g.insert(t("Gabe", "likes", "Rust"));
+  g.insert(t("James", "likes", "Rust"));
+  g.insert(t("James", "likes", "Java"));
+  g.insert(t("Rust", "likes", "Gabe"));
+  g.insert(t("_:b0", "http://ex.org/note", "line\none 'single' \"double\" \\ tab\t bell\u{7} ünïcödé"));
+  g.insert(t("_:b0", "http://ex.org/says", "\"chat\"@fr"));
+  g
+}
 
 #[cfg(test)]
 mod manual_tests {
@@ -23,7 +60,7 @@ mod manual_tests {
     g.insert(("Harry".into(), "is not".into(), "cool".into()));
     let q = OjiQuery::new().from(&g)
                            .select(&["$name"])
-                           .filter(&[("$name", "is", "cool")])
+                           .filter(&[("$name", "is", "cool")]).unwrap()
                            .fetch();
     println!("{:#?}", q);
   }
@@ -187,7 +224,7 @@ mod serde {
   fn into_json() {
     let mut t = TripleStore::new();
     t.insert(("Gabe".into(), "likes".into(), "Rust".into()));
-    let json = t.json();
+    let json = t.json().unwrap();
     let expected_json = String::from("{\"Gabe\":{\"likes\":[\"Rust\"]}}");
     assert_eq!(json, expected_json);
   }
@@ -210,3 +247,2098 @@ mod serde {
     assert_eq!(t_store, expected_t_store);
   }
 }
+
+#[cfg(test)]
+mod query_forms {
+  use super::*;
+  fn people() -> Graph {
+    let mut g = Graph::new();
+    g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    g.insert(("Gabe".into(), "is".into(), "cool".into()));
+    g.insert(("James".into(), "likes".into(), "Java".into()));
+    g.insert(("Harry".into(), "likes".into(), "Gabe".into()));
+    g
+  }
+  #[test]
+  fn construct() {
+    let g = people();
+    let c = OjiQuery::new().from(&g)
+                           .select(&["$name", "$lang"])
+                           .filter(&[("$name", "likes", "$lang")]).unwrap()
+                           .construct(&[("$lang", "liked by", "$name")]);
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Rust".into(), "liked by".into(), "Gabe".into()));
+    expected_g.insert(("Java".into(), "liked by".into(), "James".into()));
+    expected_g.insert(("Gabe".into(), "liked by".into(), "Harry".into()));
+    assert_eq!(c, expected_g);
+  }
+  #[test]
+  fn construct_mints_blank_nodes_per_solution() {
+    let g = people();
+    let c = OjiQuery::new().from(&g)
+                           .select(&["$name", "$lang"])
+                           .filter(&[("$name", "likes", "$lang")]).unwrap()
+                           .construct(&[("_:n", "http://ex.org/p", "$lang"), ("_:n", "http://ex.org/by", "$name")]);
+    assert_eq!(c.iter().count(), 6);
+    assert_eq!(c.blank_nodes().len(), 3);
+    for (s, _, _) in c.iter() {
+      assert_eq!(c.get_triple(&(Some(s), None, None)).len(), 2);
+    }
+  }
+  #[test]
+  fn construct_joins_conditions() {
+    let g = people();
+    let c = OjiQuery::new().from(&g)
+                           .select(&["$name"])
+                           .filter(&[("$name", "likes", "Rust"),
+                                     ("$name", "is", "cool")]).unwrap()
+                           .construct(&[("$name", "is", "rustacean"),
+                                        ("$name", "knows", "$unbound")]);
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Gabe".into(), "is".into(), "rustacean".into()));
+    assert_eq!(c, expected_g);
+  }
+  #[test]
+  fn describe_resource() {
+    let g = people();
+    let d = OjiQuery::new().from(&g).describe(&["Gabe"]);
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    expected_g.insert(("Gabe".into(), "is".into(), "cool".into()));
+    expected_g.insert(("Harry".into(), "likes".into(), "Gabe".into()));
+    assert_eq!(d, expected_g);
+  }
+  #[test]
+  fn describe_bindings() {
+    let g = people();
+    let d = OjiQuery::new().from(&g)
+                           .select(&["$name"])
+                           .filter(&[("$name", "likes", "Java")]).unwrap()
+                           .describe();
+    let mut expected_g = Graph::new();
+    expected_g.insert(("James".into(), "likes".into(), "Java".into()));
+    assert_eq!(d, expected_g);
+  }
+}
+
+#[cfg(test)]
+mod ask {
+  use super::*;
+  fn roles() -> Graph {
+    let mut g = Graph::new();
+    g.insert(("Gabe".into(), "has role".into(), "admin".into()));
+    g.insert(("admin".into(), "can".into(), "delete".into()));
+    g.insert(("James".into(), "likes".into(), "James".into()));
+    g
+  }
+  #[test]
+  fn contains_pattern() {
+    let g = roles();
+    assert!(g.contains_pattern(&(Some("Gabe".into()), None, None)));
+    assert!(g.contains_pattern(&(None, Some("has role".into()), None)));
+    assert!(g.contains_pattern(&(Some("Gabe".into()), None, Some("admin".into()))));
+    assert!(g.contains_pattern(&(None, None, Some("delete".into()))));
+    assert!(g.contains_pattern(&(None, None, None)));
+    assert!(!g.contains_pattern(&(Some("James".into()), Some("has role".into()), None)));
+    assert!(!Graph::new().contains_pattern(&(None, None, None)));
+  }
+  #[test]
+  fn ask_single() {
+    let g = roles();
+    assert!(OjiQuery::new().from(&g).ask(&[("Gabe", "has role", "$role")]));
+    assert!(!OjiQuery::new().from(&g).ask(&[("Harry", "has role", "$role")]));
+  }
+  #[test]
+  fn ask_joined() {
+    let g = roles();
+    assert!(OjiQuery::new().from(&g).ask(&[("$user", "has role", "$role"),
+                                             ("$role", "can", "delete")]));
+    assert!(!OjiQuery::new().from(&g).ask(&[("$user", "has role", "$role"),
+                                              ("$role", "can", "create")]));
+  }
+  #[test]
+  fn ask_repeated_var() {
+    let g = roles();
+    assert!(OjiQuery::new().from(&g).ask(&[("$x", "likes", "$x")]));
+    assert!(!OjiQuery::new().from(&g).ask(&[("$x", "has role", "$x")]));
+  }
+  #[test]
+  fn ask_compiled_query() {
+    let g = roles();
+    let q = OjiQuery::new().from(&g)
+                           .select(&["$user"])
+                           .filter(&[("$user", "has role", "admin")]).unwrap();
+    assert!(q.ask());
+  }
+  /* Counts the matches listed, to check ask only lists what it must */
+  #[derive(Debug)]
+  struct Counting(Graph, std::cell::Cell<usize>);
+  impl PatternMatch for Counting {
+    fn get_triple(&self, qt: &(Option<String>, Option<String>, Option<String>)) -> Vec<(String, String, String)> {
+      self.1.set(self.1.get() + 1);
+      self.0.get_triple(qt)
+    }
+    fn contains_pattern(&self, qt: &(Option<String>, Option<String>, Option<String>)) -> bool {
+      self.0.contains_pattern(qt)
+    }
+  }
+  #[test]
+  fn ask_probes_each_condition() {
+    let g = Counting(roles(), std::cell::Cell::new(0));
+    assert!(OjiQuery::new().from(&g).ask(&[("$user", "has role", "$role"),
+                                             ("$x", "likes", "$x"),
+                                             ("admin", "can", "$what")]));
+    assert!(!OjiQuery::new().from(&g).ask(&[("$user", "has role", "$role"),
+                                              ("admin", "can", "create")]));
+    /* Only the repeated variable needed its matches listed */
+    assert_eq!(g.1.get(), 1);
+    assert!(OjiQuery::new().from(&g).ask(&[("$user", "has role", "$role"),
+                                             ("$role", "can", "delete")]));
+    assert_eq!(g.1.get(), 2);
+  }
+}
+
+#[cfg(test)]
+mod update {
+  use super::*;
+  fn people() -> Graph {
+    let mut g = Graph::new();
+    g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    g.insert(("James".into(), "likes".into(), "Java".into()));
+    g
+  }
+  #[test]
+  fn insert_and_delete_data() {
+    let mut g = people();
+    g.update("INSERT DATA { <Matt> <likes> \"JS\" ; <is> <cool> . } ;
+              DELETE DATA { James likes Java }").unwrap();
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    expected_g.insert(("Matt".into(), "likes".into(), "JS".into()));
+    expected_g.insert(("Matt".into(), "is".into(), "cool".into()));
+    assert_eq!(g, expected_g);
+  }
+  #[test]
+  fn delete_insert_where() {
+    let mut g = people();
+    g.update("DELETE { ?who likes ?lang } INSERT { ?lang likedBy ?who } WHERE { ?who likes ?lang }").unwrap();
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Rust".into(), "likedBy".into(), "Gabe".into()));
+    expected_g.insert(("Java".into(), "likedBy".into(), "James".into()));
+    assert_eq!(g, expected_g);
+  }
+  #[test]
+  fn delete_where() {
+    let mut g = people();
+    g.update("DELETE WHERE { Gabe likes ?lang }").unwrap();
+    let mut expected_g = Graph::new();
+    expected_g.insert(("James".into(), "likes".into(), "Java".into()));
+    assert_eq!(g, expected_g);
+  }
+  #[test]
+  fn clear() {
+    let mut g = people();
+    g.update("CLEAR DEFAULT").unwrap();
+    assert_eq!(g, Graph::new());
+  }
+  #[test]
+  fn load() {
+    let path = std::env::temp_dir().join("oji_update_load.json");
+    std::fs::write(&path, "{\"Matt\":{\"likes\":[\"JS\"]}}").unwrap();
+    let mut g = people();
+    g.update(&format!("LOAD <file://{}>", path.display())).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(g.contains_pattern(&(Some("Matt".into()), Some("likes".into()), Some("JS".into()))));
+    assert_eq!(g.iter().count(), 3);
+  }
+  #[test]
+  fn failed_request_is_rolled_back() {
+    let mut g = people();
+    let res = g.update("INSERT DATA { Matt likes JS } ;
+                        DELETE DATA { Gabe likes Rust } ;
+                        LOAD <file:///this/file/does/not/exist.json>");
+    assert!(res.is_err());
+    assert_eq!(g, people());
+  }
+  #[test]
+  fn malformed_request_changes_nothing() {
+    let mut g = people();
+    assert!(g.update("INSERT DATA { Matt likes JS } ; DELETE DATA { ?x likes JS }").is_err());
+    assert!(g.update("INSERT DATA { Matt likes }").is_err());
+    assert_eq!(g, people());
+  }
+  #[test]
+  fn quoted_literals_stay_literals() {
+    let mut g = Graph::new();
+    g.update("INSERT DATA { <http://ex.org/s> <http://ex.org/p> \"http://ex.org/x\" , '_:b0' }").unwrap();
+    let objects: Vec<String> = g.iter().map(|(_, _, o)| o).collect();
+    assert!(objects.contains(&"\"http://ex.org/x\"".to_string()), "{:?}", objects);
+    assert!(objects.contains(&"\"_:b0\"".to_string()), "{:?}", objects);
+    assert!(g.blank_nodes().is_empty());
+  }
+  #[test]
+  fn language_tags_and_datatypes() {
+    let mut g = Graph::new();
+    g.update("INSERT DATA { <http://ex.org/s> <http://ex.org/p> \"chat\"@en-GB , \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> }").unwrap();
+    assert!(g.contains_pattern(&(None, None, Some("\"chat\"@en-GB".to_string()))));
+    assert!(g.contains_pattern(&(None, None, Some("\"1\"^^<http://www.w3.org/2001/XMLSchema#integer>".to_string()))));
+    assert!(matches!(g.update("INSERT DATA { <s> <p> \"x\"^ <dt> }"), Err(OjiError::Parse(_))));
+    assert!(matches!(g.update("INSERT DATA { <s> <p> \"x\"@ }"), Err(OjiError::Parse(_))));
+  }
+  #[test]
+  fn a_is_rdf_type() {
+    let mut g = Graph::new();
+    g.update("INSERT DATA { <http://ex.org/gabe> a <http://ex.org/Person> }").unwrap();
+    assert!(g.contains_pattern(&(None, Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#type".to_string()), Some("http://ex.org/Person".to_string()))));
+  }
+  #[test]
+  fn prefixed_names() {
+    let mut g = Graph::new();
+    g.update("PREFIX ex: <http://ex.org/>
+              PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+              INSERT DATA { ex:gabe ex:age \"30\"^^xsd:integer } ;
+              PREFIX : <http://other.org/>
+              INSERT DATA { :a ex:p _:b }").unwrap();
+    assert!(g.contains_pattern(&(Some("http://ex.org/gabe".to_string()), Some("http://ex.org/age".to_string()),
+                                 Some("\"30\"^^<http://www.w3.org/2001/XMLSchema#integer>".to_string()))));
+    assert!(g.contains_pattern(&(Some("http://other.org/a".to_string()), Some("http://ex.org/p".to_string()), Some("_:b".to_string()))));
+    assert!(matches!(g.update("INSERT DATA { nope:a <p> <o> }"), Err(OjiError::Parse(_))));
+    assert!(matches!(g.update("PREFIX ex <http://ex.org/> INSERT DATA { <s> <p> <o> }"), Err(OjiError::Parse(_))));
+  }
+  #[test]
+  fn numbers() {
+    let mut g = Graph::new();
+    g.update("INSERT DATA { <a> <p> 1.5 . <a> <p> -2 . <a> <p> .5e-3 . <a> <q> 7. }").unwrap();
+    for n in ["1.5", "-2", ".5e-3", "7"].iter() {
+      assert!(g.contains_pattern(&(Some("a".into()), None, Some(n.to_string()))), "{}", n);
+    }
+    assert_eq!(g.iter().count(), 4);
+  }
+  #[test]
+  fn load_only_reads_files() {
+    let mut g = people();
+    assert!(matches!(g.update("LOAD <http://example.org/data.json>"), Err(OjiError::Constraint(_))));
+    g.update("LOAD SILENT <http://example.org/data.json>").unwrap();
+    assert!(matches!(g.update("LOAD <file:///data.json> INTO GRAPH <g>"), Err(OjiError::Constraint(_))));
+    g.update("LOAD SILENT <file:///data.json> INTO GRAPH <g>").unwrap();
+    assert_eq!(g, people());
+  }
+}
+
+#[cfg(test)]
+mod result_formats {
+  use super::*;
+  fn results<'a>(g: &'a Graph) -> OjiResultCollection<'a> {
+    OjiQuery::new().from(g)
+                   .select(&["$who", "$what"])
+                   .filter(&[("$who", "http://ex.org/says", "$what")]).unwrap()
+                   .fetch()
+  }
+  fn graph() -> Graph {
+    let mut g = Graph::new();
+    g.insert(("http://ex.org/Gabe".into(), "http://ex.org/says".into(), "\"bonjour\"@fr".into()));
+    g
+  }
+  #[test]
+  fn json() {
+    let g = graph();
+    let expected_json = "{\"head\":{\"vars\":[\"who\",\"what\"]},\"results\":{\"bindings\":[{\
+                         \"what\":{\"type\":\"literal\",\"value\":\"bonjour\",\"xml:lang\":\"fr\"},\
+                         \"who\":{\"type\":\"uri\",\"value\":\"http://ex.org/Gabe\"}}]}}";
+    assert_eq!(results(&g).json(), expected_json);
+  }
+  #[test]
+  fn prefixed_names_are_not_iris() {
+    let mut g = Graph::new();
+    g.insert(("urn:isbn:0451450523".into(), "http://ex.org/says".into(), "rdf:type".into()));
+    let json = results(&g).json();
+    assert!(json.contains("\"what\":{\"type\":\"literal\",\"value\":\"rdf:type\"}"), "{}", json);
+    assert!(json.contains("\"who\":{\"type\":\"uri\",\"value\":\"urn:isbn:0451450523\"}"), "{}", json);
+  }
+  #[test]
+  fn csv_and_tsv() {
+    let mut g = Graph::new();
+    g.insert(("_:b0".into(), "http://ex.org/says".into(), "hi, \"you\"".into()));
+    let rc = results(&g);
+    assert_eq!(rc.csv(), "who,what\r\n_:b0,\"hi, \"\"you\"\"\"\r\n");
+    assert_eq!(rc.tsv(), "?who\t?what\n_:b0\t\"hi, \\\"you\\\"\"\n");
+  }
+  #[test]
+  fn json_round_trip() {
+    let g = graph();
+    let rc = results(&g);
+    let parsed = OjiResultCollection::from_json(&rc.json()).unwrap();
+    assert_eq!(parsed.query.vars(), rc.query.vars());
+    assert_eq!(parsed.results[0].get_var("who"), rc.results[0].get_var("who"));
+    assert_eq!(parsed.results[0].get_var("what"), rc.results[0].get_var("what"));
+  }
+  #[test]
+  fn xml_round_trip() {
+    let g = graph();
+    let rc = results(&g);
+    let xml = rc.xml();
+    assert!(xml.contains("<literal xml:lang=\"fr\">bonjour</literal>"));
+    let parsed = OjiResultCollection::from_xml(&xml).unwrap();
+    assert_eq!(parsed.query.vars(), rc.query.vars());
+    assert_eq!(parsed.results[0].get_var("who"), rc.results[0].get_var("who"));
+    assert_eq!(parsed.results[0].get_var("what"), rc.results[0].get_var("what"));
+  }
+  #[test]
+  fn malformed_documents() {
+    assert!(OjiResultCollection::from_json("{\"head\":{}}").is_err());
+    assert!(OjiResultCollection::from_json("not json").is_err());
+    assert!(OjiResultCollection::from_xml("<sparql><head></sparql>").is_err());
+    assert!(OjiResultCollection::from_xml("<rdf/>").is_err());
+    let head = r#""head": {"vars": ["x"]}"#;
+    for bad in [r#"{"head": {"vars": ["x", 1]}, "results": {"bindings": []}}"#.to_string(),
+                format!(r#"{{{}, "results": {{"bindings": [{{"x": {{"type": "literal", "value": 30}}}}]}}}}"#, head),
+                format!(r#"{{{}, "results": {{"bindings": [{{"x": {{"type": "literal", "value": "a", "xml:lang": 1}}}}]}}}}"#, head)].iter() {
+      assert!(matches!(OjiResultCollection::from_json(bad), Err(OjiError::Parse(_))), "{}", bad);
+    }
+  }
+}
+
+#[cfg(test)]
+mod typed_results {
+  use super::*;
+  use ::serde::Deserialize;
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Person {
+    name: String,
+    age: i64,
+    nickname: Option<String>,
+  }
+  fn ages() -> Graph {
+    let mut g = Graph::new();
+    g.insert(("Gabe".into(), "age".into(), "\"21\"^^<http://www.w3.org/2001/XMLSchema#integer>".into()));
+    g.insert(("James".into(), "age".into(), "twenty".into()));
+    g
+  }
+  #[test]
+  fn get_typed() {
+    let g = ages();
+    let rc = OjiQuery::new().from(&g)
+                            .select(&["$name", "$age"])
+                            .filter(&[("Gabe", "age", "$age")]).unwrap()
+                            .fetch();
+    assert_eq!(rc.results[0].get::<i64>("age").unwrap(), 21);
+    assert_eq!(rc.results[0].get::<String>("age").unwrap(), String::from("21"));
+    assert!(rc.results[0].get::<i64>("name").is_err());
+  }
+  #[test]
+  fn get_invalid() {
+    let g = ages();
+    let rc = OjiQuery::new().from(&g)
+                            .select(&["$age"])
+                            .filter(&[("James", "age", "$age")]).unwrap()
+                            .fetch();
+    assert!(rc.results[0].get::<i64>("age").is_err());
+  }
+  #[test]
+  fn deserialize_rows() {
+    let mut g = ages();
+    g.remove(&("James".into(), "age".into(), "twenty".into()));
+    g.insert(("James".into(), "age".into(), "20".into()));
+    let rc = OjiQuery::new().from(&g)
+                            .select(&["$name", "$age"])
+                            .filter(&[("$name", "age", "$age")]).unwrap()
+                            .fetch();
+    let mut people = rc.deserialize::<Person>().unwrap();
+    people.sort_by_key(|p| p.age);
+    assert_eq!(people, vec![Person { name: "James".into(), age: 20, nickname: None },
+                            Person { name: "Gabe".into(), age: 21, nickname: None }]);
+  }
+  #[test]
+  fn deserialize_invalid_row() {
+    let g = ages();
+    let rc = OjiQuery::new().from(&g)
+                            .select(&["$name", "$age"])
+                            .filter(&[("$name", "age", "$age")]).unwrap()
+                            .fetch();
+    assert!(rc.deserialize::<Person>().is_err());
+  }
+}
+
+#[cfg(test)]
+mod errors {
+  use super::*;
+  fn graph() -> Graph {
+    let mut g = Graph::new();
+    g.insert(("Gabe".into(), "age".into(), "twenty".into()));
+    g
+  }
+  #[test]
+  fn undeclared_variable() {
+    let g = graph();
+    let q = OjiQuery::new().from(&g)
+                           .select(&["$name"])
+                           .filter(&[("$name", "age", "$age")]);
+    assert!(matches!(q, Err(OjiError::Query(_))));
+  }
+  #[test]
+  fn parse() {
+    let mut g = graph();
+    assert!(matches!(g.update("INSERT DATA { Gabe likes"), Err(OjiError::Parse(_))));
+    assert!(matches!(TripleStore::from_json("{\"Gabe\":"), Err(OjiError::Parse(_))));
+    assert!(matches!(OjiResultCollection::from_xml("<sparql>"), Err(OjiError::Parse(_))));
+  }
+  #[test]
+  fn io() {
+    let mut g = graph();
+    assert!(matches!(g.update("LOAD <file:///this/file/does/not/exist.json>"), Err(OjiError::Io(_))));
+  }
+  #[test]
+  fn serialization() {
+    let g = graph();
+    let rc = OjiQuery::new().from(&g)
+                            .select(&["$age"])
+                            .filter(&[("Gabe", "age", "$age")]).unwrap()
+                            .fetch();
+    assert!(matches!(rc.results[0].get::<i64>("age"), Err(OjiError::Serialization(_))));
+    assert!(matches!(Graph::from_json("{\"Gabe\":{\"age\":20}}"), Err(OjiError::Serialization(_))));
+  }
+  #[test]
+  fn constraint() {
+    let mut g = graph();
+    assert!(matches!(g.update("CLEAR GRAPH <http://ex.org/nope>"), Err(OjiError::Constraint(_))));
+  }
+  #[test]
+  fn get_val_in_and_out_of_range() {
+    let mut r = OjiResult::new();
+    r.add_val(OjiResultUnit::Val(String::from("Gabe")));
+    assert_eq!(r.get_val(0), Some(String::from("Gabe")));
+    assert_eq!(r.get_val(1), None);
+  }
+  #[test]
+  fn fetch_without_conditions() {
+    let g = graph();
+    let rc = OjiQuery::new().from(&g).fetch();
+    assert!(rc.results.is_empty());
+  }
+}
+
+#[cfg(test)]
+mod shared_graph {
+  use super::*;
+  use std::thread;
+  #[test]
+  fn is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SharedGraph>();
+  }
+  #[test]
+  fn writes_are_visible_to_clones() {
+    let g = SharedGraph::new();
+    let handle = g.clone();
+    handle.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    handle.update("INSERT DATA { James likes Java }").unwrap();
+    assert_eq!(g.snapshot().iter().count(), 2);
+    g.replace(&("Gabe".into(), "likes".into(), "Rust".into()),
+              ("Gabe".into(), "likes".into(), "C++".into()));
+    g.remove(&("James".into(), "likes".into(), "Java".into()));
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Gabe".into(), "likes".into(), "C++".into()));
+    assert_eq!(*handle.snapshot(), expected_g);
+  }
+  #[test]
+  fn readers_never_see_half_applied_writes() {
+    let g = SharedGraph::new();
+    let writer = {
+      let g = g.clone();
+      thread::spawn(move || {
+        for i in 0..500 {
+          g.insert((format!("s{}", i), "p".into(), format!("o{}", i)));
+        }
+      })
+    };
+    let readers: Vec<_> = (0..4).map(|_| {
+      let g = g.clone();
+      thread::spawn(move || {
+        for _ in 0..100 {
+          let graph = g.snapshot();
+          let found = OjiQuery::new().from(&graph)
+                                     .select(&["$s", "$o"])
+                                     .filter(&[("$s", "p", "$o")]).unwrap()
+                                     .fetch();
+          let n = found.results.len();
+          assert_eq!(graph.spo.iter().count(), n);
+          assert_eq!(graph.pos.iter().count(), n);
+          assert_eq!(graph.osp.iter().count(), n);
+        }
+      })
+    }).collect();
+    writer.join().unwrap();
+    for reader in readers {
+      reader.join().unwrap();
+    }
+    assert_eq!(g.snapshot().iter().count(), 500);
+  }
+  #[test]
+  fn panicking_write_poisons() {
+    let g = SharedGraph::new();
+    g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    let before = g.snapshot();
+    let writer = g.clone();
+    let res = thread::spawn(move || writer.write(|graph| {
+      graph.insert(("James".into(), "likes".into(), "Java".into()));
+      panic!("half way");
+    })).join();
+    assert!(res.is_err());
+    assert!(std::panic::catch_unwind(|| g.snapshot()).is_err());
+    assert!(std::panic::catch_unwind(|| g.insert(("Matt".into(), "likes".into(), "JS".into()))).is_err());
+    assert_eq!(before.iter().count(), 1);
+  }
+  #[test]
+  fn failed_writes_keep_the_version() {
+    let g = SharedGraph::new();
+    g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    assert!(g.update("INSERT DATA { James likes Java } ; CLEAR GRAPH <http://ex.org/nope>").is_err());
+    let res: Result<(), &str> = g.try_write(|_| Err("no"));
+    assert!(res.is_err());
+    assert_eq!(g.version(), 1);
+    assert_eq!(g.snapshot().iter().count(), 1);
+    g.try_write(|graph| graph.update("INSERT DATA { James likes Java }")).unwrap();
+    assert_eq!(g.version(), 2);
+  }
+  #[test]
+  fn snapshots_are_point_in_time() {
+    let g = SharedGraph::new();
+    g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    let before = g.snapshot();
+    g.insert(("James".into(), "likes".into(), "Java".into()));
+    g.remove(&("Gabe".into(), "likes".into(), "Rust".into()));
+    let after = g.snapshot();
+    assert!(before.version() < after.version());
+    assert_eq!(before.json().unwrap(), "{\"Gabe\":{\"likes\":[\"Rust\"]}}");
+    assert_eq!(after.json().unwrap(), "{\"James\":{\"likes\":[\"Java\"]}}");
+    let found = OjiQuery::new().from(&before).ask(&[("$who", "likes", "Java")]);
+    assert!(!found);
+  }
+  #[test]
+  fn snapshot_survives_concurrent_writes() {
+    let g = SharedGraph::new();
+    for i in 0..100 {
+      g.insert((format!("s{}", i), "p".into(), "o".into()));
+    }
+    let snap = g.snapshot();
+    let writer = {
+      let g = g.clone();
+      thread::spawn(move || {
+        for i in 100..200 {
+          g.insert((format!("s{}", i), "p".into(), "o".into()));
+        }
+      })
+    };
+    for _ in 0..50 {
+      assert_eq!(snap.iter().count(), 100);
+    }
+    writer.join().unwrap();
+    assert_eq!(snap.iter().count(), 100);
+    assert_eq!(g.snapshot().iter().count(), 200);
+  }
+}
+
+#[cfg(test)]
+mod bulk_load {
+  use super::*;
+  fn triples() -> Vec<(String, String, String)> {
+    (0..300).map(|i| (format!("http://ex.org/s{}", i % 17),
+                      format!("http://ex.org/p{}", i % 5),
+                      format!("value {}", i % 101)))
+            .collect()
+  }
+  #[test]
+  fn matches_repeated_insert() {
+    let triples = triples();
+    let mut expected_g = Graph::new();
+    for triple in triples.iter().cloned() {
+      expected_g.insert(triple);
+    }
+    for threads in &[1, 2, 3, 8] {
+      assert_eq!(Graph::bulk_load_with_threads(&triples, *threads), expected_g);
+    }
+    assert_eq!(Graph::bulk_load(&triples), expected_g);
+  }
+  #[test]
+  fn ntriples_round_trip() {
+    let mut g = Graph::from(triples().into_iter());
+    g.insert(("_:b0".into(), "http://ex.org/says".into(), "\"chat\"@fr".into()));
+    g.insert(("_:b0".into(), "http://ex.org/age".into(), "\"1\"^^<http://www.w3.org/2001/XMLSchema#int>".into()));
+    g.insert(("_:b0".into(), "http://ex.org/note".into(), "line\none \"quoted\"".into()));
+    g.insert(("_:b0".into(), "http://ex.org/page".into(), "\"http://not.an.iri\"".into()));
+    assert_eq!(Graph::from_ntriples(&g.ntriples()).unwrap(), g);
+    let reader = std::io::Cursor::new(g.ntriples());
+    assert_eq!(Graph::read_ntriples(reader).unwrap(), g);
+  }
+  #[test]
+  fn ntriples_parse() {
+    let doc = "# comment\n\
+               <http://ex.org/Gabe> <http://ex.org/likes> \"Rust\" .\n\
+               \n\
+               _:b1 <http://ex.org/likes> <http://ex.org/Gabe>.\n";
+    let mut expected_g = Graph::new();
+    expected_g.insert(("http://ex.org/Gabe".into(), "http://ex.org/likes".into(), "Rust".into()));
+    expected_g.insert(("_:b1".into(), "http://ex.org/likes".into(), "http://ex.org/Gabe".into()));
+    assert_eq!(Graph::from_ntriples(doc).unwrap(), expected_g);
+    assert!(matches!(Graph::from_ntriples("<http://ex.org/Gabe> <http://ex.org/likes> \"Rust\""),
+                     Err(OjiError::Parse(_))));
+  }
+}
+
+#[cfg(test)]
+mod compaction {
+  use super::*;
+  use std::time::Duration;
+  fn grown_and_shrunk() -> Graph {
+    let mut g = Graph::new();
+    for i in 0..500 {
+      g.insert((format!("s{}", i % 10), format!("p{}", i % 7), format!("o{}", i)));
+    }
+    for i in 10..500 {
+      g.remove(&(format!("s{}", i % 10), format!("p{}", i % 7), format!("o{}", i)));
+    }
+    g
+  }
+  #[test]
+  fn reclaims_space_and_keeps_triples() {
+    let mut g = grown_and_shrunk();
+    let before = g.clone();
+    let expected = g.reclaimable();
+    let stats = g.compact();
+    assert!(stats.slots_reclaimed > 0);
+    assert!(stats.bytes_reclaimed > 0);
+    assert_eq!(stats, expected);
+    assert_eq!(g, before);
+    assert_eq!(g.reclaimable(), CompactionStats::default());
+    assert_eq!(g.compact().slots_reclaimed, 0);
+  }
+  #[test]
+  fn inserts_leave_nothing_to_reclaim() {
+    let mut g = Graph::new();
+    for i in 0..500 {
+      g.insert((format!("s{}", i % 10), format!("p{}", i % 7), format!("o{}", i)));
+    }
+    assert_eq!(g.reclaimable(), CompactionStats::default());
+    let shared = SharedGraph::from(g);
+    let reader = shared.snapshot();
+    assert_eq!(shared.try_compact(), Some(CompactionStats::default()));
+    /* Nothing was copied or swapped in */
+    assert!(std::ptr::eq(&*reader, &*shared.snapshot()));
+  }
+  #[test]
+  fn shared_graph_compacts_without_changing_version() {
+    let g = SharedGraph::from(grown_and_shrunk());
+    let reader = g.snapshot();
+    let stats = g.compact();
+    assert!(stats.slots_reclaimed > 0);
+    assert_eq!(g.version(), reader.version());
+    assert_eq!(*g.snapshot(), *reader);
+  }
+  #[test]
+  fn snapshots_scan_the_sorted_segment() {
+    let g = sample();
+    let shared = SharedGraph::from(g.clone());
+    assert!(!shared.snapshot().is_sorted());
+    let stats = shared.compact();
+    assert_eq!(stats.triples_sorted, g.iter().count());
+    let sorted = shared.snapshot();
+    assert!(sorted.is_sorted());
+    assert_eq!(sorted.version(), 0);
+    let term = |t: &str| Some(t.to_string());
+    let sort = |mut triples: Vec<(String, String, String)>| { triples.sort(); triples };
+    for qt in [(None, None, None), (term("James"), None, None), (None, term("likes"), None),
+               (None, None, term("Rust")), (term("Gabe"), None, term("Rust")), (term("Nobody"), None, None)].iter() {
+      assert_eq!(sort(PatternMatch::get_triple(&sorted, qt)), sort(g.get_triple(qt)), "{:?}", qt);
+    }
+    assert!(OjiQuery::new().from(&sorted).ask(&[("$x", "likes", "$y"), ("$y", "likes", "$x")]));
+    assert_eq!(shared.try_sort(), Some(CompactionStats::default()));
+    /* A write retires the segment; snapshots already taken keep it */
+    shared.insert(t("Gabe", "likes", "Go"));
+    assert!(!shared.snapshot().is_sorted());
+    assert!(sorted.is_sorted());
+    assert_eq!(PatternMatch::get_triple(&shared.snapshot(), &(term("Gabe"), None, None)).len(), 2);
+    assert_eq!(shared.try_sort().unwrap().triples_sorted, g.iter().count() + 1);
+    assert_eq!(PatternMatch::get_triple(&shared.snapshot(), &(term("Gabe"), None, None)).len(), 2);
+  }
+  #[test]
+  fn background_maintenance() {
+    let g = SharedGraph::from(grown_and_shrunk());
+    let expected_g = (*g.snapshot()).clone();
+    let maintenance = g.start_maintenance(Duration::from_millis(1));
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while maintenance.stats().slots_reclaimed == 0 || !g.snapshot().is_sorted() {
+      assert!(std::time::Instant::now() < deadline, "Background maintenance never compacted and sorted");
+      assert_eq!(*g.snapshot(), expected_g);
+      std::thread::sleep(Duration::from_millis(1));
+    }
+    g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    let stats = maintenance.stop();
+    assert!(stats.bytes_reclaimed > 0);
+    assert!(stats.triples_sorted >= expected_g.iter().count());
+    assert_eq!(g.snapshot().iter().count(), expected_g.iter().count() + 1);
+  }
+}
+
+#[cfg(test)]
+mod ffi {
+  use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+    ptr,
+  };
+  /* Called through the C ABI, exactly as include/oji.h declares them */
+  enum OjiGraph {}
+  enum OjiCursor {}
+  extern "C" {
+    fn oji_last_error() -> *const c_char;
+    fn oji_string_free(s: *mut c_char);
+    fn oji_graph_new() -> *mut OjiGraph;
+    fn oji_graph_free(graph: *mut OjiGraph);
+    fn oji_graph_len(graph: *const OjiGraph) -> usize;
+    fn oji_graph_insert(graph: *mut OjiGraph, s: *const c_char, p: *const c_char, o: *const c_char) -> c_int;
+    fn oji_graph_remove(graph: *mut OjiGraph, s: *const c_char, p: *const c_char, o: *const c_char) -> c_int;
+    fn oji_graph_update(graph: *mut OjiGraph, request: *const c_char) -> c_int;
+    fn oji_graph_match(graph: *const OjiGraph, s: *const c_char, p: *const c_char, o: *const c_char,
+                       callback: extern "C" fn(*const c_char, *const c_char, *const c_char, *mut c_void) -> c_int,
+                       user_data: *mut c_void) -> c_int;
+    fn oji_graph_query(graph: *const OjiGraph, vars: *const *const c_char, n_vars: usize,
+                       conds: *const *const c_char, n_conds: usize, cursor: *mut *mut OjiCursor) -> c_int;
+    fn oji_cursor_next(cursor: *mut OjiCursor) -> bool;
+    fn oji_cursor_get(cursor: *const OjiCursor, var: *const c_char) -> *const c_char;
+    fn oji_cursor_free(cursor: *mut OjiCursor);
+    fn oji_graph_ntriples(graph: *const OjiGraph) -> *mut c_char;
+  }
+  fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+  }
+  unsafe fn insert(g: *mut OjiGraph, s: &str, p: &str, o: &str) -> c_int {
+    oji_graph_insert(g, c(s).as_ptr(), c(p).as_ptr(), c(o).as_ptr())
+  }
+  extern "C" fn collect(s: *const c_char, _: *const c_char, _: *const c_char, found: *mut c_void) -> c_int {
+    let found = unsafe { &mut *(found as *mut Vec<String>) };
+    found.push(unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string());
+    (found.len() == 2) as c_int
+  }
+  #[test]
+  fn crud_and_match() {
+    unsafe {
+      let g = oji_graph_new();
+      assert_eq!(insert(g, "Gabe", "likes", "Rust"), 0);
+      assert_eq!(insert(g, "James", "likes", "Rust"), 0);
+      assert_eq!(insert(g, "Sue", "likes", "Rust"), 0);
+      assert_eq!(insert(g, "Gabe", "likes", "C++"), 0);
+      assert_eq!(oji_graph_remove(g, c("Gabe").as_ptr(), c("likes").as_ptr(), c("C++").as_ptr()), 0);
+      assert_eq!(oji_graph_len(g), 3);
+      let mut found: Vec<String> = Vec::new();
+      let status = oji_graph_match(g, ptr::null(), c("likes").as_ptr(), c("Rust").as_ptr(),
+                                   collect, &mut found as *mut Vec<String> as *mut c_void);
+      assert_eq!(status, 0);
+      assert_eq!(found.len(), 2);
+      let doc = oji_graph_ntriples(g);
+      assert_eq!(CStr::from_ptr(doc).to_str().unwrap().lines().count(), 3);
+      oji_string_free(doc);
+      oji_graph_free(g);
+    }
+  }
+  #[test]
+  fn query_cursor() {
+    unsafe {
+      let g = oji_graph_new();
+      insert(g, "Gabe", "likes", "Rust");
+      insert(g, "James", "likes", "Java");
+      let vars = [c("$name")];
+      let conds = [c("$name"), c("likes"), c("Rust")];
+      let var_ptrs: Vec<*const c_char> = vars.iter().map(|v| v.as_ptr()).collect();
+      let cond_ptrs: Vec<*const c_char> = conds.iter().map(|v| v.as_ptr()).collect();
+      let mut cursor = ptr::null_mut();
+      assert_eq!(oji_graph_query(g, var_ptrs.as_ptr(), 1, cond_ptrs.as_ptr(), 1, &mut cursor), 0);
+      /* The cursor owns its results */
+      oji_graph_free(g);
+      let mut names = Vec::new();
+      while oji_cursor_next(cursor) {
+        names.push(CStr::from_ptr(oji_cursor_get(cursor, c("name").as_ptr())).to_str().unwrap().to_string());
+        assert!(oji_cursor_get(cursor, c("$lang").as_ptr()).is_null());
+      }
+      assert_eq!(names, vec!["Gabe".to_string()]);
+      oji_cursor_free(cursor);
+    }
+  }
+  #[test]
+  fn errors() {
+    unsafe {
+      let g = oji_graph_new();
+      assert_eq!(oji_graph_insert(g, ptr::null(), c("likes").as_ptr(), c("Rust").as_ptr()), 1);
+      assert_eq!(CStr::from_ptr(oji_last_error()).to_str().unwrap(), "subject is NULL");
+      assert_eq!(oji_graph_update(g, c("INSERT DATA { Gabe likes }").as_ptr()), 3);
+      let (name, who, likes, rust) = (c("$name"), c("$who"), c("likes"), c("Rust"));
+      let vars = [name.as_ptr()];
+      let conds = [who.as_ptr(), likes.as_ptr(), rust.as_ptr()];
+      let mut cursor = ptr::null_mut();
+      assert_eq!(oji_graph_query(g, vars.as_ptr(), 1, conds.as_ptr(), 1, &mut cursor), 4);
+      assert!(cursor.is_null());
+      assert_eq!(oji_graph_len(g), 0);
+      oji_graph_free(g);
+    }
+  }
+  #[test]
+  fn panics_become_statuses() {
+    use crate::util::Ffi::{guarded, OjiStatus};
+    assert_eq!(guarded(OjiStatus::Panic, || OjiStatus::Ok), OjiStatus::Ok);
+    assert_eq!(guarded(OjiStatus::Panic, || -> OjiStatus { panic!("boom") }), OjiStatus::Panic);
+    assert_eq!(unsafe { CStr::from_ptr(oji_last_error()) }.to_str().unwrap(), "Oji panicked: boom");
+    assert!(guarded(ptr::null::<c_char>(), || panic!("{} went wrong", "something")).is_null());
+    assert_eq!(unsafe { CStr::from_ptr(oji_last_error()) }.to_str().unwrap(), "Oji panicked: something went wrong");
+  }
+  #[test]
+  fn header_declares_every_export() {
+    let header = include_str!("../include/oji.h");
+    let exports: Vec<&str> = include_str!("util/Ffi.rs")
+      .lines()
+      .filter_map(|l| l.split("extern \"C\" fn ").nth(1))
+      .filter_map(|l| l.split('(').next())
+      .collect();
+    assert!(exports.len() > 10);
+    for name in exports {
+      assert!(header.contains(&format!(" {}(", name)) || header.contains(&format!("*{}(", name)),
+              "{} missing from include/oji.h", name);
+    }
+    /* The contracts are written once, on the exports, and cbindgen copies them over */
+    for doc in include_str!("util/Ffi.rs").lines().filter_map(|l| l.trim().strip_prefix("/// ")) {
+      assert!(header.contains(doc), "include/oji.h is stale, regenerate it with cbindgen: {:?}", doc);
+    }
+  }
+}
+
+#[cfg(test)]
+mod ion {
+  use super::*;
+  #[test]
+  fn binary_round_trip() {
+    let g = sample();
+    let json_g = Graph::from_json(&g.json().unwrap()).unwrap();
+    assert_eq!(Graph::from_ion(&g.ion(IonFormat::Binary)).unwrap(), json_g);
+    let mut doc = Vec::new();
+    g.write_ion(&mut doc, IonFormat::Binary).unwrap();
+    assert_eq!(Graph::read_ion(std::io::Cursor::new(doc)).unwrap(), g);
+  }
+  #[test]
+  fn text_round_trip() {
+    let g = sample();
+    let doc = g.ion(IonFormat::Text);
+    assert!(doc.starts_with(b"$ion_1_0"));
+    assert_eq!(Graph::from_ion(&doc).unwrap(), Graph::from_json(&g.json().unwrap()).unwrap());
+  }
+  #[test]
+  fn binary_layout() {
+    let g = Graph::from(vec![("a".to_string(), "b".to_string(), "c".to_string())].into_iter());
+    assert_eq!(g.ion(IonFormat::Binary), vec![
+      0xE0, 0x01, 0x00, 0xEA,
+      /* $ion_symbol_table::{ symbols: ["a", "b"] } */
+      0xE9, 0x81, 0x83, 0xD6, 0x87, 0xB4, 0x81, 0x61, 0x81, 0x62,
+      /* { a: { b: ["c"] } } */
+      0xD6, 0x8A, 0xD4, 0x8B, 0xB2, 0x81, 0x63,
+    ]);
+    assert_eq!(Graph::new().ion(IonFormat::Binary), vec![0xE0, 0x01, 0x00, 0xEA, 0xD0]);
+  }
+  #[test]
+  fn reads_hand_written_text() {
+    let doc = "$ion_1_0 // a comment\n\
+               { Gabe: { likes: [\"Rust\", 'C++'] }, /* another */ 'James': { \"likes\": [Java] } }\n\
+               { Gabe: { likes: [\"Go\"] } }";
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Gabe".into(), "likes".into(), "Rust".into()));
+    expected_g.insert(("Gabe".into(), "likes".into(), "C++".into()));
+    expected_g.insert(("Gabe".into(), "likes".into(), "Go".into()));
+    expected_g.insert(("James".into(), "likes".into(), "Java".into()));
+    assert_eq!(Graph::from_ion(doc.as_bytes()).unwrap(), expected_g);
+  }
+  #[test]
+  fn malformed_documents() {
+    assert!(matches!(Graph::from_ion(b"{ Gabe: { likes: \"Rust\" } }"), Err(OjiError::Parse(_))));
+    assert!(matches!(Graph::from_ion(b"{ Gabe: { likes: [\"Rust\" } }"), Err(OjiError::Parse(_))));
+    let mut doc = sample().ion(IonFormat::Binary);
+    doc.truncate(doc.len() - 3);
+    assert!(matches!(Graph::from_ion(&doc), Err(OjiError::Parse(_))));
+    /* Symbol 42 was never declared */
+    assert!(matches!(Graph::from_ion(&[0xE0, 0x01, 0x00, 0xEA, 0xD6, 0xAA, 0xD4, 0x8B, 0xB2, 0x81, 0x63]),
+                     Err(OjiError::Parse(_))));
+    /* An annotation claiming to be usize::MAX bytes long */
+    let mut doc = vec![0xE0, 0x01, 0x00, 0xEA, 0xEE, 0x8B, 0x01];
+    doc.extend([0x7F; 8].iter());
+    doc.extend([0xFF, 0x80].iter());
+    assert!(matches!(Graph::from_ion(&doc), Err(OjiError::Parse(_))));
+  }
+  #[test]
+  fn text_keywords() {
+    let doc = "$ion_1_0 { Gabe: { likes: [null, null.string, 'null', \"true\"], knows: null } }";
+    let mut expected_g = Graph::new();
+    expected_g.insert(("Gabe".into(), "likes".into(), "null".into()));
+    expected_g.insert(("Gabe".into(), "likes".into(), "true".into()));
+    assert_eq!(Graph::from_ion(doc.as_bytes()).unwrap(), expected_g);
+    assert!(matches!(Graph::from_ion(b"{ Gabe: { likes: [true] } }"), Err(OjiError::Parse(_))));
+    /* Only the whole symbol is a version marker */
+    assert!(matches!(Graph::from_ion(b"$ion_1_0x { Gabe: { likes: [\"Rust\"] } }"), Err(OjiError::Parse(_))));
+  }
+}
+
+#[cfg(test)]
+mod snapshot {
+  use super::*;
+  #[test]
+  fn round_trip() {
+    let g = sample();
+    assert_eq!(Graph::from_snapshot(&g.to_snapshot()).unwrap(), Graph::from_json(&g.json().unwrap()).unwrap());
+    let mut doc = Vec::new();
+    g.write_snapshot(&mut doc).unwrap();
+    assert_eq!(Graph::read_snapshot(std::io::Cursor::new(doc)).unwrap(), g);
+    assert_eq!(Graph::from_snapshot(&Graph::new().to_snapshot()).unwrap(), Graph::new());
+  }
+  #[test]
+  fn equal_graphs_give_identical_snapshots() {
+    let g = sample();
+    let mut triples: Vec<_> = g.clone().into_iter().collect();
+    triples.reverse();
+    assert_eq!(Graph::from(triples.into_iter()).to_snapshot(), g.to_snapshot());
+    assert!(g.to_snapshot().len() < g.json().unwrap().len());
+  }
+  #[test]
+  fn detects_corruption() {
+    let doc = sample().to_snapshot();
+    for i in [20, doc.len() / 2, doc.len() - 20].iter() {
+      let mut corrupted = doc.clone();
+      corrupted[*i] ^= 0x40;
+      assert!(matches!(Graph::from_snapshot(&corrupted), Err(OjiError::Parse(_))));
+    }
+    assert!(matches!(Graph::from_snapshot(&doc[..doc.len() - 1]), Err(OjiError::Parse(_))));
+    assert!(matches!(Graph::from_snapshot(b"{\"Gabe\":{}}"), Err(OjiError::Parse(_))));
+  }
+  #[test]
+  fn versions() {
+    let doc = sample().to_snapshot();
+    assert_eq!(&doc[..6], b"OJIS\x02\x00");
+    assert_eq!(Graph::upgrade_snapshot(&doc).unwrap(), doc);
+    let mut future = doc.clone();
+    future[4] = 0xFF;
+    let e = Graph::from_snapshot(&future).unwrap_err();
+    assert!(e.to_string().contains("newer"), "{}", e);
+    assert!(Graph::upgrade_snapshot(&future).is_err());
+    let mut zero = doc.clone();
+    zero[4] = 0;
+    let e = Graph::from_snapshot(&zero).unwrap_err();
+    assert!(e.to_string().contains("invalid"), "{}", e);
+  }
+  #[test]
+  fn upgrades_version_1() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshot");
+    let old = std::fs::read(dir.join("v1.ojis")).unwrap();
+    let expected_g = Graph::from_ntriples(&std::fs::read_to_string(dir.join("v1.nt")).unwrap()).unwrap();
+    assert_eq!(&old[..6], b"OJIS\x01\x00");
+    assert_eq!(Graph::from_snapshot(&old).unwrap(), expected_g);
+    let upgraded = Graph::upgrade_snapshot(&old).unwrap();
+    assert_eq!(&upgraded[..6], b"OJIS\x02\x00");
+    assert_eq!(upgraded, expected_g.to_snapshot());
+    assert!(upgraded.len() < old.len());
+    assert_eq!(Graph::from_snapshot(&upgraded).unwrap(), expected_g);
+  }
+}
+
+#[cfg(test)]
+mod mapped_graph {
+  use super::*;
+  use std::fs::File;
+  fn mapped(g: &Graph, name: &str) -> MappedGraph {
+    let path = std::env::temp_dir().join(format!("oji_mapped_{}_{}.ojim", name, std::process::id()));
+    g.write_mapped(File::create(&path).unwrap()).unwrap();
+    MappedGraph::open(&path).unwrap()
+  }
+  fn sorted(mut triples: Vec<(String, String, String)>) -> Vec<(String, String, String)> {
+    triples.sort();
+    triples
+  }
+  #[test]
+  fn matches_every_pattern_like_graph() {
+    let g = sample();
+    let m = mapped(&g, "patterns");
+    assert_eq!(m.len(), g.iter().count());
+    assert_eq!(m.to_graph(), g);
+    let term = |t: &str| Some(t.to_string());
+    let patterns = [
+      (None, None, None),
+      (term("James"), None, None),
+      (None, term("likes"), None),
+      (None, None, term("Rust")),
+      (term("James"), term("likes"), None),
+      (term("Gabe"), None, term("Rust")),
+      (None, term("likes"), term("Rust")),
+      (term("James"), term("likes"), term("Java")),
+      (term("James"), term("likes"), term("Go")),
+      (term("Nobody"), None, None),
+    ];
+    for qt in patterns.iter() {
+      assert_eq!(sorted(m.get_triple(qt)), sorted(g.get_triple(qt)), "{:?}", qt);
+      assert_eq!(m.contains_pattern(qt), g.contains_pattern(qt), "{:?}", qt);
+    }
+  }
+  #[test]
+  fn runs_queries() {
+    let g = sample();
+    let m = mapped(&g, "queries");
+    let names = |rc: OjiResultCollection| {
+      let mut names: Vec<String> = rc.results.iter().filter_map(|r| r.get_var("name")).collect();
+      names.sort();
+      names
+    };
+    let from_graph = OjiQuery::new().from(&g).select(&["$name"]).filter(&[("$name", "likes", "Rust")]).unwrap().fetch();
+    let from_mapped = OjiQuery::new().from(&m).select(&["$name"]).filter(&[("$name", "likes", "Rust")]).unwrap().fetch();
+    assert_eq!(names(from_mapped), names(from_graph));
+    assert!(OjiQuery::new().from(&m).ask(&[("$x", "likes", "$y"), ("$y", "likes", "$x")]));
+    assert_eq!(OjiQuery::new().from(&m).describe(&["Rust"]), OjiQuery::new().from(&g).describe(&["Rust"]));
+  }
+  #[test]
+  fn empty_and_malformed_files() {
+    let m = mapped(&Graph::new(), "empty");
+    assert!(m.is_empty());
+    assert!(m.get_triple(&(None, None, None)).is_empty());
+    let path = std::env::temp_dir().join(format!("oji_mapped_bad_{}.ojim", std::process::id()));
+    let mut doc = Vec::new();
+    sample().write_mapped(&mut doc).unwrap();
+    std::fs::write(&path, &doc[..doc.len() - 4]).unwrap();
+    assert!(matches!(MappedGraph::open(&path), Err(OjiError::Parse(_))));
+    std::fs::write(&path, sample().ntriples()).unwrap();
+    assert!(matches!(MappedGraph::open(&path), Err(OjiError::Parse(_))));
+    assert!(matches!(MappedGraph::open(path.with_extension("missing")), Err(OjiError::Io(_))));
+  }
+  #[test]
+  fn corrupt_tables() {
+    let path = std::env::temp_dir().join(format!("oji_mapped_corrupt_{}.ojim", std::process::id()));
+    let mut doc = Vec::new();
+    sample().write_mapped(&mut doc).unwrap();
+    let triples = sample().iter().count();
+    let spo = doc.len() - 3 * 12 * triples;
+    let corruptions: [&dyn Fn(&mut Vec<u8>); 4] = [
+      /* The second term's offset past the end of the blob */
+      &|doc| doc[32..40].copy_from_slice(&u64::MAX.to_le_bytes()),
+      /* A term id with no term */
+      &|doc| { let end = doc.len(); doc[end - 4..].copy_from_slice(&u32::MAX.to_le_bytes()) },
+      /* Triples out of order */
+      &|doc| for i in 0..12 { doc.swap(spo + i, spo + 12 + i) },
+      /* A term that isn't UTF-8; the sample has under 256 terms, so its count is one byte */
+      &|doc| { let blob = 24 + 8 * (doc[8] as usize + 1); doc[blob] = 0xFF },
+    ];
+    for corrupt in corruptions.iter() {
+      let mut bad = doc.clone();
+      corrupt(&mut bad);
+      std::fs::write(&path, &bad).unwrap();
+      assert!(matches!(MappedGraph::open(&path), Err(OjiError::Parse(_))));
+    }
+    std::fs::remove_file(&path).unwrap();
+  }
+}
+
+#[cfg(test)]
+mod jsonld {
+  use super::*;
+  const CONTEXT: &str = r#"{
+    "@context": {
+      "ex": "http://ex.org/",
+      "schema": "http://schema.org/",
+      "name": "schema:name",
+      "knows": { "@id": "schema:knows", "@type": "@id" },
+      "born": { "@id": "schema:birthDate", "@type": "http://www.w3.org/2001/XMLSchema#date" },
+      "nick": { "@id": "schema:alternateName", "@language": "en" }
+    }
+  }"#;
+  #[test]
+  fn expands_with_local_context() {
+    let doc = r#"{
+      "@id": "ex:alice",
+      "@type": "schema:Person",
+      "name": "Alice",
+      "nick": "Al",
+      "born": "1990-01-01",
+      "knows": ["ex:bob", "ex:carol"],
+      "schema:age": 30,
+      "ex:height": 1.7,
+      "ex:member": true,
+      "unmapped": "dropped"
+    }"#;
+    let g = Graph::from_jsonld(doc, Some(CONTEXT)).unwrap();
+    let expected = Graph::from(vec![
+      t("http://ex.org/alice", "http://www.w3.org/1999/02/22-rdf-syntax-ns#type", "http://schema.org/Person"),
+      t("http://ex.org/alice", "http://schema.org/name", "Alice"),
+      t("http://ex.org/alice", "http://schema.org/alternateName", "\"Al\"@en"),
+      t("http://ex.org/alice", "http://schema.org/birthDate", "\"1990-01-01\"^^<http://www.w3.org/2001/XMLSchema#date>"),
+      t("http://ex.org/alice", "http://schema.org/knows", "http://ex.org/bob"),
+      t("http://ex.org/alice", "http://schema.org/knows", "http://ex.org/carol"),
+      t("http://ex.org/alice", "http://schema.org/age", "\"30\"^^<http://www.w3.org/2001/XMLSchema#integer>"),
+      t("http://ex.org/alice", "http://ex.org/height", "\"1.7E0\"^^<http://www.w3.org/2001/XMLSchema#double>"),
+      t("http://ex.org/alice", "http://ex.org/member", "\"true\"^^<http://www.w3.org/2001/XMLSchema#boolean>"),
+    ].into_iter());
+    assert_eq!(g, expected);
+  }
+  #[test]
+  fn nested_nodes_lists_and_graphs() {
+    let doc = r##"{
+      "@context": { "@vocab": "http://ex.org/", "@base": "http://ex.org/people/" },
+      "@graph": [
+        { "@id": "alice", "address": { "city": "Paris" } },
+        { "@id": "#bob", "tags": { "@list": ["a", "b"] }, "@reverse": { "parent": { "@id": "carol" } } }
+      ]
+    }"##;
+    let g = Graph::from_jsonld(doc, None).unwrap();
+    assert_eq!(g.iter().count(), 8);
+    let address = g.get_triple(&(Some("http://ex.org/people/alice".into()), Some("http://ex.org/address".into()), None));
+    assert_eq!(address.len(), 1);
+    assert!(address[0].2.starts_with("_:"));
+    assert!(g.contains_pattern(&(Some(address[0].2.clone()), Some("http://ex.org/city".into()), Some("Paris".into()))));
+    assert!(g.contains_pattern(&(Some("http://ex.org/people/carol".into()),
+                                 Some("http://ex.org/parent".into()),
+                                 Some("http://ex.org/people/#bob".into()))));
+    let firsts = g.get_triple(&(None, Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#first".into()), None));
+    let mut items: Vec<String> = firsts.into_iter().map(|t| t.2).collect();
+    items.sort();
+    assert_eq!(items, vec!["a", "b"]);
+  }
+  #[test]
+  fn compacts_and_round_trips() {
+    let doc = r#"[
+      { "@id": "ex:alice", "@type": "schema:Person", "name": "Alice", "nick": "Al",
+        "knows": "ex:bob", "schema:description": { "@value": "Hallo", "@language": "de" } },
+      { "@id": "ex:bob", "name": "Bob", "born": "1991-02-03" }
+    ]"#;
+    let g = Graph::from_jsonld(doc, Some(CONTEXT)).unwrap();
+    let out = g.jsonld(CONTEXT).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let alice = &value["@graph"][0];
+    assert_eq!(alice["@id"], "ex:alice");
+    assert_eq!(alice["@type"], "schema:Person");
+    assert_eq!(alice["name"], "Alice");
+    assert_eq!(alice["nick"], "Al");
+    assert_eq!(alice["knows"], "ex:bob");
+    assert_eq!(alice["schema:description"], serde_json::json!({ "@value": "Hallo", "@language": "de" }));
+    assert_eq!(value["@graph"][1]["born"], "1991-02-03");
+    assert_eq!(Graph::from_jsonld(&out, None).unwrap(), g);
+  }
+  #[test]
+  fn single_node_is_not_wrapped() {
+    let g = Graph::from(vec![t("http://ex.org/a", "http://schema.org/name", "A")].into_iter());
+    let value: serde_json::Value = serde_json::from_str(&g.jsonld(CONTEXT).unwrap()).unwrap();
+    assert_eq!(value["@id"], "ex:a");
+    assert_eq!(value["name"], "A");
+    assert!(value.get("@graph").is_none());
+  }
+  #[test]
+  fn sets_keep_every_item() {
+    let doc = r#"{ "@id": "http://ex.org/a", "http://ex.org/p": { "@set": ["x", ["y"], { "@set": "z" }] } }"#;
+    let g = Graph::from_jsonld(doc, None).unwrap();
+    let expected = Graph::from(vec![
+      t("http://ex.org/a", "http://ex.org/p", "x"),
+      t("http://ex.org/a", "http://ex.org/p", "y"),
+      t("http://ex.org/a", "http://ex.org/p", "z"),
+    ].into_iter());
+    assert_eq!(g, expected);
+  }
+  #[test]
+  fn language_tags_keep_their_case() {
+    let turtle = Graph::from_turtle(r#"
+      <http://ex.org/a> <http://ex.org/p> "x"@en-GB .
+      <http://ex.org/a> <http://ex.org/q> "y"@en-GB .
+      <http://ex.org/a> <http://ex.org/r> "z"@en-GB .
+    "#, None).unwrap();
+    let doc = r#"{
+      "@context": { "@language": "en-GB", "ex": "http://ex.org/", "q": { "@id": "ex:q", "@language": "en-GB" } },
+      "@id": "ex:a",
+      "ex:p": "x",
+      "q": "y",
+      "ex:r": { "@value": "z", "@language": "en-GB" }
+    }"#;
+    assert_eq!(Graph::from_jsonld(doc, None).unwrap(), turtle);
+    let out = turtle.jsonld(r#"{ "@context": { "ex": "http://ex.org/" } }"#).unwrap();
+    assert!(out.contains("en-GB"), "{}", out);
+    assert_eq!(Graph::from_turtle(&Graph::from_jsonld(&out, None).unwrap().turtle(), None).unwrap(), turtle);
+  }
+  #[test]
+  fn rejects_what_it_cannot_load() {
+    let remote = r#"{ "@context": "https://schema.org/", "name": "x" }"#;
+    assert!(matches!(Graph::from_jsonld(remote, None), Err(OjiError::Parse(_))));
+    let cyclic = r#"{ "@context": { "a": "b:x", "b": "a:y" } }"#;
+    assert!(matches!(Graph::from_jsonld(cyclic, None), Err(OjiError::Parse(_))));
+    assert!(Graph::from_jsonld("[1]", None).is_err());
+    assert!(Graph::from_jsonld("{", None).is_err());
+  }
+}
+
+#[cfg(test)]
+mod rdf_xml {
+  use super::*;
+  use std::{fs, path::Path};
+  const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+  const MF: &str = "http://www.w3.org/2001/sw/DataAccess/tests/test-manifest#";
+  const RDFT: &str = "http://www.w3.org/ns/rdftest#";
+  /*
+    Runs every entry of dir/manifest.ttl, a W3C test manifest whose
+    IRIs resolve against base, and returns how many ran. Each action
+    is read with its own IRI as the base, as the W3C suite expects
+  */
+  fn run_manifest(dir: &Path, base: &str) -> usize {
+    let iri = format!("{}manifest.ttl", base);
+    let manifest = Graph::from_turtle(&fs::read_to_string(dir.join("manifest.ttl")).unwrap(), Some(&iri)).unwrap();
+    let one = |s: &str, p: String| manifest.get_triple(&(Some(s.to_string()), Some(p), None)).pop().map(|t| t.2);
+    let local = |iri: &str| dir.join(iri.strip_prefix(base).unwrap_or_else(|| panic!("{} is outside {}", iri, base)));
+    let (mut run, mut failures) = (0, Vec::new());
+    let mut list = one(&iri, format!("{}entries", MF)).expect("manifest has no mf:entries");
+    while list != format!("{}nil", RDF) {
+      let entry = one(&list, format!("{}first", RDF)).unwrap();
+      list = one(&list, format!("{}rest", RDF)).unwrap();
+      if one(&entry, format!("{}approval", RDFT)) == Some(format!("{}Rejected", RDFT)) {
+        continue
+      }
+      let name = one(&entry, format!("{}name", MF)).unwrap_or_else(|| entry.clone());
+      let action = one(&entry, format!("{}action", MF)).unwrap();
+      let parsed = Graph::from_rdfxml(&fs::read_to_string(local(&action)).unwrap(), Some(&action));
+      let kind = one(&entry, format!("{}type", RDF)).unwrap();
+      match (kind.strip_prefix(RDFT), parsed) {
+        (Some("TestXMLNegativeSyntax"), Err(OjiError::Parse(_))) => {},
+        (Some("TestXMLNegativeSyntax"), Err(e)) => failures.push(format!("{} failed with {} rather than a parse error", name, e)),
+        (Some("TestXMLNegativeSyntax"), Ok(g)) => failures.push(format!("{} should be rejected, parsed to\n{}", name, g.ntriples())),
+        (Some("TestXMLEval"), Ok(g)) => {
+          let result = local(&one(&entry, format!("{}result", MF)).unwrap());
+          let expected = Graph::from_ntriples(&fs::read_to_string(result).unwrap()).unwrap();
+          if !g.isomorphic(&expected) {
+            failures.push(format!("{} parsed to\n{}", name, g.ntriples()));
+          }
+        },
+        (Some("TestXMLEval"), Err(e)) => failures.push(format!("{}: {}", name, e)),
+        _ => panic!("{} has a test type the harness doesn't know: {}", name, kind),
+      }
+      run += 1;
+    }
+    assert!(failures.is_empty(), "{} of {} failed:\n{}", failures.len(), run, failures.join("\n"));
+    run
+  }
+  #[test]
+  fn fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/rdf-xml");
+    let cases = fs::read_dir(&dir).unwrap()
+                                  .filter(|e| e.as_ref().unwrap().path().extension().is_some_and(|e| e == "rdf"))
+                                  .count();
+    assert!(cases > 0);
+    assert_eq!(run_manifest(&dir, "http://example.org/tests/"), cases, "every case should be in manifest.ttl");
+  }
+  /* tests/rdf-xml/fetch-w3c.sh vendors the suite into tests/rdf-xml/w3c */
+  #[test]
+  fn w3c_suite() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/rdf-xml/w3c");
+    if !dir.join("manifest.ttl").exists() {
+      eprintln!("tests/rdf-xml/w3c is missing, run tests/rdf-xml/fetch-w3c.sh to vendor the W3C suite");
+      return
+    }
+    assert!(run_manifest(&dir, "https://w3c.github.io/rdf-tests/rdf/rdf11/rdf-xml/") > 100);
+  }
+  #[test]
+  fn round_trip() {
+    let g = Graph::from_ntriples(r#"
+      <http://ex.org/alice> <http://xmlns.com/foaf/0.1/name> "Alice" .
+      <http://ex.org/alice> <http://xmlns.com/foaf/0.1/knows> _:bob .
+      <http://ex.org/alice> <http://ex.org/vocab#note> "1 < 2 & \"quoted\""@en .
+      <http://ex.org/alice> <http://ex.org/vocab#age> "30"^^<http://www.w3.org/2001/XMLSchema#integer> .
+      <http://ex.org/alice> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://xmlns.com/foaf/0.1/Person> .
+      _:bob <http://xmlns.com/foaf/0.1/name> "Bob" .
+    "#).unwrap();
+    let doc = g.rdfxml().unwrap();
+    assert!(doc.contains("xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\""));
+    assert!(doc.contains("<rdf:type rdf:resource=\"http://xmlns.com/foaf/0.1/Person\"/>"));
+    assert_eq!(Graph::from_rdfxml(&doc, None).unwrap(), g);
+    assert_eq!(Graph::read_rdfxml(doc.as_bytes(), None).unwrap(), g);
+  }
+  #[test]
+  fn language_tags_keep_their_case() {
+    let turtle = Graph::from_turtle(r#"
+      <http://ex.org/a> <http://ex.org/p> "x"@en-GB .
+      <http://ex.org/a> <http://ex.org/q> "y"@en-GB .
+    "#, None).unwrap();
+    let doc = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:eg="http://ex.org/" xml:lang="en-GB">
+                   <rdf:Description rdf:about="http://ex.org/a" eg:q="y"><eg:p>x</eg:p></rdf:Description>
+                 </rdf:RDF>"#;
+    assert_eq!(Graph::from_rdfxml(doc, None).unwrap(), turtle);
+    let out = turtle.rdfxml().unwrap();
+    assert!(out.contains("xml:lang=\"en-GB\""), "{}", out);
+    assert_eq!(Graph::from_turtle(&Graph::from_rdfxml(&out, None).unwrap().turtle(), None).unwrap(), turtle);
+  }
+  #[test]
+  fn unwritable_predicates() {
+    let g = Graph::from(vec![("http://ex.org/a".to_string(), "http://ex.org/1".to_string(), "b".to_string())].into_iter());
+    assert!(matches!(g.rdfxml(), Err(OjiError::Serialization(_))));
+  }
+  #[test]
+  fn resolves_references() {
+    let base = "http://a/b/c/d;p?q";
+    for (reference, expected) in [("g", "http://a/b/c/g"), ("./g", "http://a/b/c/g"), ("g/", "http://a/b/c/g/"),
+                                  ("/g", "http://a/g"), ("//g", "http://g"), ("?y", "http://a/b/c/d;p?y"),
+                                  ("#s", "http://a/b/c/d;p?q#s"), ("", "http://a/b/c/d;p?q"), (".", "http://a/b/c/"),
+                                  ("..", "http://a/b/"), ("../g", "http://a/b/g"), ("../../../g", "http://a/g"),
+                                  ("g;x=1/../y", "http://a/b/c/y"), ("g:h", "g:h")].iter() {
+      let doc = format!(r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:eg="http://ex.org/">
+                             <rdf:Description rdf:about="http://ex.org/s"><eg:p rdf:resource="{}"/></rdf:Description>
+                           </rdf:RDF>"#, reference);
+      let g = Graph::from_rdfxml(&doc, Some(base)).unwrap();
+      assert_eq!(g.iter().next().unwrap().2, *expected, "{}", reference);
+    }
+  }
+}
+
+#[cfg(test)]
+mod trig {
+  use super::*;
+  const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+  #[test]
+  fn reads_named_and_default_graphs() {
+    let doc = r#"
+      @prefix ex: <http://ex.org/> .
+      PREFIX foaf: <http://xmlns.com/foaf/0.1/>
+      # statements outside a block are the default graph
+      ex:alice foaf:name "Alice" .
+      { ex:bob foaf:name "Bob" }
+      ex:g1 { ex:alice foaf:knows ex:bob ; a foaf:Person . }
+      GRAPH <http://ex.org/g2> {
+        ex:bob foaf:age 42 ; ex:height 1.8 ; ex:ratio 2e3 ; ex:member true .
+        ex:bob ex:note """two
+lines with "quotes\"""" , 'single'@en-GB
+      }
+      _:g { _:x ex:in _:g }
+    "#;
+    let ds = Dataset::from_trig(doc, None).unwrap();
+    assert_eq!(ds.names(), vec!["_:g", "http://ex.org/g1", "http://ex.org/g2"]);
+    assert_eq!(ds.default, Graph::from(vec![
+      t("http://ex.org/alice", "http://xmlns.com/foaf/0.1/name", "Alice"),
+      t("http://ex.org/bob", "http://xmlns.com/foaf/0.1/name", "Bob"),
+    ].into_iter()));
+    let g1 = ds.graph(Some("http://ex.org/g1")).unwrap();
+    assert!(g1.contains_pattern(&(Some("http://ex.org/alice".into()),
+                                  Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#type".into()),
+                                  Some("http://xmlns.com/foaf/0.1/Person".into()))));
+    let g2 = ds.graph(Some("http://ex.org/g2")).unwrap();
+    let objects = |p: &str| -> Vec<String> {
+      let mut os: Vec<String> = g2.get_triple(&(None, Some(p.to_string()), None)).into_iter().map(|t| t.2).collect();
+      os.sort();
+      os
+    };
+    assert_eq!(objects("http://xmlns.com/foaf/0.1/age"), vec![format!("\"42\"^^<{}integer>", XSD)]);
+    assert_eq!(objects("http://ex.org/height"), vec![format!("\"1.8\"^^<{}decimal>", XSD)]);
+    assert_eq!(objects("http://ex.org/ratio"), vec![format!("\"2e3\"^^<{}double>", XSD)]);
+    assert_eq!(objects("http://ex.org/member"), vec![format!("\"true\"^^<{}boolean>", XSD)]);
+    assert_eq!(objects("http://ex.org/note"), vec!["\"single\"@en-GB".to_string(), "two\nlines with \"quotes\"".to_string()]);
+    assert!(ds.graph(Some("_:g")).unwrap().contains_pattern(&(Some("_:x".into()), None, Some("_:g".into()))));
+    assert_eq!(ds.len(), 11);
+  }
+  #[test]
+  fn blank_nodes_and_collections() {
+    let doc = r#"
+      @base <http://ex.org/base/> .
+      @prefix : <#> .
+      <doc> { [ :name "anon" ] :list ( 1 <two> [ :three 3 ] ) ; :empty () . }
+    "#;
+    let ds = Dataset::from_trig(doc, None).unwrap();
+    let g = ds.graph(Some("http://ex.org/base/doc")).unwrap();
+    let list = g.get_triple(&(None, Some("http://ex.org/base/#list".into()), None));
+    assert_eq!(list.len(), 1);
+    assert!(list[0].0.starts_with("_:") && list[0].2.starts_with("_:"));
+    assert!(g.contains_pattern(&(Some(list[0].0.clone()), Some("http://ex.org/base/#name".into()), Some("anon".into()))));
+    assert_eq!(g.get_triple(&(None, Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#first".into()), None)).len(), 3);
+    assert!(g.contains_pattern(&(None, Some("http://ex.org/base/#empty".into()),
+                                 Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#nil".into()))));
+    assert_eq!(g.iter().count(), 10);
+  }
+  #[test]
+  fn fresh_blank_nodes_avoid_document_labels() {
+    let g = Graph::from_turtle("_:b0 <http://ex.org/p> [ <http://ex.org/q> _:b1 ] .", None).unwrap();
+    let nodes: std::collections::HashSet<String> = g.iter().flat_map(|t| vec![t.0, t.2]).collect();
+    assert_eq!(nodes.len(), 3);
+  }
+  #[test]
+  fn round_trip() {
+    let mut ds = Dataset::new();
+    ds.insert(("http://ex.org/a".into(), "http://ex.org/p".into(), "tab\there \"quoted\"".into(), None));
+    ds.insert(("http://ex.org/a".into(), "http://www.w3.org/1999/02/22-rdf-syntax-ns#type".into(), "http://ex.org/T".into(), None));
+    ds.insert(("_:b".into(), "http://ex.org/p".into(), "\"chat\"@fr".into(), Some("http://ex.org/g".into())));
+    ds.insert(("http://ex.org/a".into(), "http://ex.org/p".into(), format!("\"7\"^^<{}integer>", XSD), Some("_:g".into())));
+    ds.insert(("http://ex.org/a".into(), "http://ex.org/q".into(), "http://ex.org/b".into(), Some("_:g".into())));
+    let doc = ds.trig();
+    assert!(doc.contains("http://ex.org/g> {"));
+    assert_eq!(Dataset::from_trig(&doc, None).unwrap(), ds);
+    assert_eq!(Dataset::read_trig(doc.as_bytes(), None).unwrap(), ds);
+    assert_eq!(Graph::from_turtle(&ds.default.turtle(), None).unwrap(), ds.default);
+  }
+  #[test]
+  fn removing_the_last_triple_drops_the_graph() {
+    let mut ds = Dataset::from_trig("<http://ex.org/g> { <http://ex.org/a> <http://ex.org/p> 'x' }", None).unwrap();
+    assert!(!ds.is_empty());
+    ds.remove(&("http://ex.org/a".into(), "http://ex.org/p".into(), "x".into(), Some("http://ex.org/g".into())));
+    assert!(ds.is_empty());
+    assert!(ds.graph(Some("http://ex.org/g")).is_none());
+  }
+  #[test]
+  fn graph_mut_leaves_no_empty_graphs() {
+    let mut ds = Dataset::new();
+    ds.graph_mut(Some("http://ex.org/g"));
+    assert!(ds.is_empty());
+    assert!(ds.names().is_empty());
+    assert!(ds.graph(Some("http://ex.org/g")).is_none());
+    assert_eq!(ds.trig(), "");
+    assert_eq!(ds, Dataset::new());
+  }
+  #[test]
+  fn malformed_documents() {
+    for doc in ["<http://ex.org/g> { <http://ex.org/a> <http://ex.org/p> 'x' ",
+                "\n\nex:a ex:p ex:o .",
+                "<http://ex.org/a> <http://ex.org/p> \"open .",
+                "<http://ex.org/a> <http://ex.org/p> 'bad \\q escape' .",
+                "{ <http://ex.org/a> <http://ex.org/p> 'x' <http://ex.org/b> <http://ex.org/p> 'y' }"].iter() {
+      assert!(matches!(Dataset::from_trig(doc, None), Err(OjiError::Parse(_))), "{}", doc);
+    }
+    match Dataset::from_trig("\n\nex:a ex:p ex:o .", None) {
+      Err(OjiError::Parse(msg)) => assert!(msg.starts_with("line 3"), "{}", msg),
+      other => panic!("{:?}", other),
+    }
+  }
+}
+
+#[cfg(test)]
+mod json_stream {
+  use super::*;
+  use std::io::{self, Read};
+  /* A flat array of n triples, produced a piece at a time and never held whole */
+  struct Generated {
+    n: usize,
+    next: usize,
+    pending: Vec<u8>,
+  }
+  impl Read for Generated {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      if self.pending.is_empty() {
+        self.pending = match self.next {
+          0 => b"[".to_vec(),
+          i if i <= self.n => format!("{}[\"http://ex.org/s{}\",\"http://ex.org/p\",\"o{}\"]",
+                                      if i > 1 { "," } else { "" }, i % 1000, i).into_bytes(),
+          i if i == self.n + 1 => b"]".to_vec(),
+          _ => return Ok(0),
+        };
+        self.next += 1;
+      }
+      let len = buf.len().min(self.pending.len());
+      buf[..len].copy_from_slice(&self.pending[..len]);
+      self.pending.drain(..len);
+      Ok(len)
+    }
+  }
+  #[test]
+  fn nested_shape() {
+    let mut g = Graph::new();
+    g.insert(("http://ex.org/a".into(), "http://ex.org/p".into(), "x".into()));
+    g.insert(("http://ex.org/a".into(), "http://ex.org/q".into(), "y".into()));
+    g.insert(("http://ex.org/b".into(), "http://ex.org/p".into(), "z".into()));
+    let doc = g.json().unwrap();
+    assert_eq!(Graph::read_json(doc.as_bytes()).unwrap(), g);
+    assert_eq!(TripleStore::read_json(doc.as_bytes()).unwrap(), g.spo);
+  }
+  #[test]
+  fn flat_shape() {
+    let doc = r#"[["http://ex.org/a", "http://ex.org/p", "x"], ["http://ex.org/b", "http://ex.org/p", "y"]]"#;
+    let mut g = Graph::new();
+    assert_eq!(g.insert_json_stream(doc.as_bytes()).unwrap(), 2);
+    assert!(g.contains_pattern(&(Some("http://ex.org/b".into()), None, Some("y".into()))));
+  }
+  #[test]
+  fn streams_large_documents() {
+    let mut g = Graph::new();
+    let read = g.insert_json_stream(Generated { n: 50_000, next: 0, pending: Vec::new() }).unwrap();
+    assert_eq!(read, 50_000);
+    assert_eq!(g.iter().count(), 50_000);
+    assert_eq!(g.get_triple(&(Some("http://ex.org/s7".into()), None, None)).len(), 50);
+  }
+  #[test]
+  fn malformed_documents() {
+    for doc in ["{\"s\": [\"o\"]}", "[[\"s\", \"p\"]]", "\"s\"", "{\"s\": {\"p\": [1]}}"].iter() {
+      assert!(matches!(Graph::read_json(doc.as_bytes()), Err(OjiError::Serialization(_))), "{}", doc);
+    }
+    for doc in ["[[\"s\", \"p\", \"o\"]] trailing", "{\"s\": {\"p\": [\"o\""].iter() {
+      assert!(matches!(Graph::read_json(doc.as_bytes()), Err(OjiError::Parse(_))), "{}", doc);
+    }
+    /* What came before the error is kept */
+    let mut g = Graph::new();
+    assert!(g.insert_json_stream(&b"[[\"s\", \"p\", \"o\"], [\"s\"]]"[..]).is_err());
+    assert_eq!(g.iter().count(), 1);
+  }
+}
+
+#[cfg(test)]
+mod json_import {
+  use super::*;
+  fn expected() -> Graph {
+    Graph::from(vec![t("Gabe", "likes", "Rust"), t("Gabe", "likes", "C"), t("Oji", "is", "fast")].into_iter())
+  }
+  #[test]
+  fn insert_json_takes_any_into_iterator() {
+    let mut g = Graph::new();
+    g.insert_json::<TripleStore>("{\"Gabe\":{\"likes\":[\"Rust\",\"C\"]},\"Oji\":{\"is\":[\"fast\"]}}").unwrap();
+    assert_eq!(g, expected());
+    let mut g = Graph::new();
+    g.insert_json::<Vec<(String, String, String)>>("[[\"Gabe\",\"likes\",\"Rust\"],[\"Gabe\",\"likes\",\"C\"],[\"Oji\",\"is\",\"fast\"]]").unwrap();
+    assert_eq!(g, expected());
+    g.remove_json::<Vec<(String, String, String)>>("[[\"Oji\",\"is\",\"fast\"]]").unwrap();
+    assert_eq!(g.iter().count(), 2);
+  }
+  #[test]
+  fn detects_each_shape() {
+    for doc in ["{\"Gabe\": {\"likes\": [\"Rust\", \"C\"]}, \"Oji\": {\"is\": [\"fast\"]}}",
+                "[[\"Gabe\", \"likes\", \"Rust\"], [\"Gabe\", \"likes\", \"C\"], [\"Oji\", \"is\", \"fast\"]]",
+                "[{\"s\": \"Gabe\", \"p\": \"likes\", \"o\": \"Rust\"}, {\"o\": \"C\", \"p\": \"likes\", \"s\": \"Gabe\"},
+                  {\"s\": \"Oji\", \"p\": \"is\", \"o\": \"fast\"}]",
+                "[[\"Gabe\", \"likes\", \"Rust\"], {\"s\": \"Gabe\", \"p\": \"likes\", \"o\": \"C\"}, [\"Oji\", \"is\", \"fast\"]]"].iter() {
+      let mut g = Graph::new();
+      assert_eq!(g.import_json(doc).unwrap(), 3, "{}", doc);
+      assert_eq!(g, expected(), "{}", doc);
+    }
+  }
+  #[test]
+  fn explains_shape_mismatches() {
+    let cases = [("[[\"Gabe\", \"likes\"]]", "has 2 terms"),
+                 ("[[\"a\", \"b\", \"c\", \"d\"]]", "more than 3 terms"),
+                 ("[{\"s\": \"Gabe\", \"p\": \"likes\"}]", "missing 'o'"),
+                 ("[{\"s\": \"a\", \"p\": \"b\", \"o\": \"c\", \"g\": \"d\"}]", "unknown key 'g'"),
+                 ("[{\"s\": \"a\", \"s\": \"b\"}]", "duplicate key 's'"),
+                 ("\"Gabe\"", "a map of subjects, or an array"),
+                 ("{\"Gabe\": [\"Rust\"]}", "a map of predicates"),
+                 ("[[\"a\", \"b\", 3]]", "expected a string")];
+    for (doc, message) in cases.iter() {
+      match Graph::new().import_json(doc) {
+        Err(OjiError::Serialization(msg)) => assert!(msg.contains(message), "{}: {}", doc, msg),
+        other => panic!("{}: {:?}", doc, other),
+      }
+    }
+    assert!(matches!(Graph::new().import_json("[[\"a\", "), Err(OjiError::Parse(_))));
+  }
+  #[test]
+  fn empty_branches_are_dropped() {
+    for doc in ["{\"s\": {\"p\": []}}", "{\"s\": {}}", "{\"s\": {\"p\": []}, \"t\": {\"q\": [\"o\"]}}"].iter() {
+      let g = Graph::from_json(doc).unwrap();
+      assert!(g.iter().count() <= 1, "{}", doc);
+      assert_eq!(Graph::from_json(&g.json().unwrap()).unwrap(), g);
+      assert_eq!(TripleStore::from_json(doc).unwrap().iter().count(), g.iter().count());
+    }
+  }
+  #[test]
+  fn import_is_all_or_nothing() {
+    let mut g = Graph::new();
+    assert!(g.import_json("[[\"s\", \"p\", \"o\"], [\"s\", \"p\"]]").is_err());
+    assert!(g.iter().next().is_none());
+    assert!(g.import_json("{\"s\": {\"p\": [\"o\"]}, \"t\": 3}").is_err());
+    assert!(g.iter().next().is_none());
+  }
+}
+
+#[cfg(test)]
+mod blank_nodes {
+  use super::*;
+  const DOC: &str = "_:b0 <http://ex.org/knows> _:b1 .\n_:b1 <http://ex.org/name> \"Ann\" .\n";
+  #[test]
+  fn documents_keep_their_own_blank_nodes() {
+    let mut g = Graph::new();
+    g.insert_ntriples(DOC).unwrap();
+    g.insert_ntriples(DOC).unwrap();
+    assert_eq!(g.iter().count(), 4);
+    assert_eq!(g.blank_nodes().len(), 4);
+    let mut merged = Graph::from_ntriples(DOC).unwrap();
+    merged.merge(Graph::from_ntriples(DOC).unwrap());
+    assert!(merged.isomorphic(&g));
+    /* Nothing clashes, so the labels are kept */
+    let mut g = Graph::from_ntriples(DOC).unwrap();
+    g.insert_turtle("_:x <http://ex.org/name> \"Bo\" .", None).unwrap();
+    assert!(g.contains_pattern(&(Some("_:x".to_string()), None, Some("Bo".to_string()))));
+    assert_eq!(g.blank_nodes().len(), 3);
+  }
+  #[test]
+  fn jsonld_documents_are_scoped() {
+    let doc = r#"{"@id": "_:b0", "http://ex.org/name": "Ann"}"#;
+    let mut g = Graph::new();
+    g.insert_jsonld(doc, None).unwrap();
+    g.insert_jsonld(doc, None).unwrap();
+    assert_eq!(g.blank_nodes().len(), 2);
+  }
+  #[test]
+  fn json_documents_are_scoped() {
+    let doc = r#"[["_:b0", "http://ex.org/knows", "_:b1"], ["_:b1", "http://ex.org/name", "Ann"]]"#;
+    let mut imported = Graph::new();
+    imported.import_json(doc).unwrap();
+    imported.import_json(doc).unwrap();
+    let mut streamed = Graph::new();
+    streamed.insert_json_stream(doc.as_bytes()).unwrap();
+    streamed.insert_json_stream(doc.as_bytes()).unwrap();
+    let mut inserted = Graph::new();
+    inserted.insert_json::<Vec<(String, String, String)>>(doc).unwrap();
+    inserted.insert_json::<Vec<(String, String, String)>>(doc).unwrap();
+    let expected = Graph::from_ntriples(&format!("{}{}", DOC, DOC.replace("_:b", "_:c"))).unwrap();
+    for g in [&imported, &streamed, &inserted] {
+      assert_eq!(g.blank_nodes().len(), 4);
+      assert!(g.isomorphic(&expected));
+    }
+    /* A label a fresh one took earlier in the stream is renamed too */
+    let mut g = Graph::from_ntriples(DOC).unwrap();
+    g.insert_json_stream(r#"[["_:b0", "http://ex.org/p", "_:b2"], ["_:b2", "http://ex.org/p", "x"]]"#.as_bytes()).unwrap();
+    assert_eq!(g.blank_nodes().len(), 4);
+  }
+  #[test]
+  fn loaded_documents_are_scoped() {
+    let path = std::env::temp_dir().join(format!("oji_scoped_load_{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"_:b0": {"http://ex.org/name": ["Ann"]}}"#).unwrap();
+    let mut g = Graph::new();
+    let load = format!("LOAD <file://{}>", path.display());
+    g.update(&load).unwrap();
+    g.update(&load).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(g.blank_nodes().len(), 2);
+  }
+  #[test]
+  fn skolemize_round_trip() {
+    let g = Graph::from_ntriples(DOC).unwrap();
+    let skolem = g.skolemize("http://oji.example/");
+    assert!(skolem.blank_nodes().is_empty());
+    assert!(skolem.iter().all(|(s, _, _)| s.starts_with("http://oji.example/.well-known/genid/")));
+    assert_eq!(skolem.deskolemize("http://oji.example"), g);
+    assert!(skolem.ntriples().contains("<http://oji.example/.well-known/genid/b0>"));
+  }
+  #[test]
+  fn isomorphism_up_to_renaming() {
+    let g = Graph::from_ntriples(DOC).unwrap();
+    let renamed = Graph::from_ntriples(&DOC.replace("_:b0", "_:z").replace("_:b1", "_:y")).unwrap();
+    assert!(g.isomorphic(&renamed));
+    assert_ne!(g, renamed);
+    let other = Graph::from_ntriples(&DOC.replace("Ann", "Bo")).unwrap();
+    assert!(!g.isomorphic(&other));
+    /* Every node looks alike locally: a 6-cycle against two 3-cycles */
+    let cycle = |edges: &[(u8, u8)]| {
+      let doc: String = edges.iter().map(|(a, b)| format!("_:n{} <http://ex.org/next> _:n{} .\n", a, b)).collect();
+      Graph::from_ntriples(&doc).unwrap()
+    };
+    let six = cycle(&[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+    let threes = cycle(&[(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]);
+    assert!(!six.isomorphic(&threes));
+    assert!(six.isomorphic(&cycle(&[(5, 3), (3, 1), (1, 4), (4, 0), (0, 2), (2, 5)])));
+  }
+}
+
+#[cfg(test)]
+mod canonical {
+  use super::*;
+  const UNIQUE: &str = "<http://example.com/#p> <http://example.com/#q> _:e0 .
+<http://example.com/#p> <http://example.com/#r> _:e1 .
+_:e0 <http://example.com/#s> <http://example.com/#u> .
+_:e1 <http://example.com/#t> <http://example.com/#u> .
+";
+  const CYCLE: &str = "_:e0 <http://example.org/vocab#next> _:e1 .
+_:e0 <http://example.org/vocab#prev> _:e2 .
+_:e1 <http://example.org/vocab#next> _:e2 .
+_:e1 <http://example.org/vocab#prev> _:e0 .
+_:e2 <http://example.org/vocab#next> _:e0 .
+_:e2 <http://example.org/vocab#prev> _:e1 .
+";
+  #[test]
+  fn spec_examples() {
+    let g = Graph::from_ntriples(UNIQUE).unwrap();
+    assert_eq!(g.canonical_nquads().unwrap(), UNIQUE.replace("_:e", "_:c14n"));
+    let g = Graph::from_ntriples(CYCLE).unwrap();
+    assert_eq!(g.canonical_nquads().unwrap(), "_:c14n0 <http://example.org/vocab#next> _:c14n2 .
+_:c14n0 <http://example.org/vocab#prev> _:c14n1 .
+_:c14n1 <http://example.org/vocab#next> _:c14n0 .
+_:c14n1 <http://example.org/vocab#prev> _:c14n2 .
+_:c14n2 <http://example.org/vocab#next> _:c14n1 .
+_:c14n2 <http://example.org/vocab#prev> _:c14n0 .
+");
+    /* The hash is plain SHA-256 of the document, so the empty graph's is the empty string's */
+    assert_eq!(Graph::new().canonical_hash().unwrap(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+  }
+  #[test]
+  fn hash_ignores_blank_node_labels() {
+    let g = Graph::from_ntriples(CYCLE).unwrap();
+    let renamed = Graph::from_ntriples(&CYCLE.replace("_:e0", "_:x").replace("_:e1", "_:e0").replace("_:x", "_:e1")).unwrap();
+    assert_ne!(g, renamed);
+    assert_eq!(g.canonical_hash().unwrap(), renamed.canonical_hash().unwrap());
+    assert_eq!(g.canonical_nquads().unwrap(), renamed.canonical_nquads().unwrap());
+    let other = Graph::from_ntriples(&CYCLE.replace("#prev> _:e2", "#prev> _:e1")).unwrap();
+    assert_ne!(g.canonical_hash().unwrap(), other.canonical_hash().unwrap());
+    /* Only the N-Degree step can tell a 6-cycle from two 3-cycles */
+    let cycle = |edges: &[(u8, u8)]| {
+      let doc: String = edges.iter().map(|(a, b)| format!("_:n{} <http://ex.org/next> _:n{} .\n", a, b)).collect();
+      Graph::from_ntriples(&doc).unwrap().canonical_hash().unwrap()
+    };
+    let six = cycle(&[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]);
+    assert_ne!(six, cycle(&[(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)]));
+    assert_eq!(six, cycle(&[(5, 3), (3, 1), (1, 4), (4, 0), (0, 2), (2, 5)]));
+  }
+  #[test]
+  fn canonical_terms() {
+    let g = Graph::from_ntriples(r#"
+      _:a <http://ex.org/p> "tab\tand\u0001"^^<http://www.w3.org/2001/XMLSchema#string> .
+      _:a <http://ex.org/p> "chat"@fr .
+      _:a <http://ex.org/p> "1"^^<http://www.w3.org/2001/XMLSchema#integer> .
+    "#).unwrap();
+    assert_eq!(g.canonical_nquads().unwrap(), r#"_:c14n0 <http://ex.org/p> "1"^^<http://www.w3.org/2001/XMLSchema#integer> .
+_:c14n0 <http://ex.org/p> "chat"@fr .
+_:c14n0 <http://ex.org/p> "tab\tand\u0001" .
+"#);
+  }
+  #[test]
+  fn datasets() {
+    let a = Dataset::from_trig("_:g { _:s <http://ex.org/p> _:o } <http://ex.org/g> { _:s <http://ex.org/p> <http://ex.org/o> }", None).unwrap();
+    let b = Dataset::from_trig("_:x { _:y <http://ex.org/p> _:z } <http://ex.org/g> { _:y <http://ex.org/p> <http://ex.org/o> }", None).unwrap();
+    assert_eq!(a.canonical_nquads().unwrap(), b.canonical_nquads().unwrap());
+    assert_eq!(a.canonical_hash().unwrap(), b.canonical_hash().unwrap());
+    assert_eq!(a.canonical_nquads().unwrap().lines().count(), 2);
+    /* Moving a triple into another graph changes the hash */
+    let c = Dataset::from_trig("{ _:y <http://ex.org/p> _:z } <http://ex.org/g> { _:y <http://ex.org/p> <http://ex.org/o> }", None).unwrap();
+    assert_ne!(a.canonical_hash().unwrap(), c.canonical_hash().unwrap());
+  }
+}
+
+#[cfg(test)]
+mod graph_diff {
+  use super::*;
+  fn graphs() -> (Graph, Graph) {
+    let a = Graph::from(vec![t("http://ex.org/gabe", "http://ex.org/likes", "Rust"),
+                             t("http://ex.org/gabe", "http://ex.org/likes", "C")].into_iter());
+    let b = Graph::from(vec![t("http://ex.org/gabe", "http://ex.org/likes", "Rust"),
+                             t("http://ex.org/gabe", "http://ex.org/name", "\"Gabe\"@en")].into_iter());
+    (a, b)
+  }
+  #[test]
+  fn diff_and_apply() {
+    let (a, b) = graphs();
+    let diff = a.diff(&b);
+    assert_eq!(diff.added, vec![t("http://ex.org/gabe", "http://ex.org/name", "\"Gabe\"@en")]);
+    assert_eq!(diff.removed, vec![t("http://ex.org/gabe", "http://ex.org/likes", "C")]);
+    assert_eq!(diff.len(), 2);
+    let mut replica = a.clone();
+    replica.apply(&diff).unwrap();
+    assert_eq!(replica, b);
+    replica.apply(&diff.inverse()).unwrap();
+    assert_eq!(replica, a);
+    assert!(a.diff(&a.clone()).is_empty());
+  }
+  #[test]
+  fn conflicts_leave_the_graph_alone() {
+    let (a, b) = graphs();
+    let diff = a.diff(&b);
+    let mut replica = b.clone();
+    match replica.apply(&diff) {
+      Err(OjiError::Constraint(msg)) => assert!(msg.contains("<http://ex.org/likes> \"C\" is not in the Graph"), "{}", msg),
+      other => panic!("{:?}", other),
+    }
+    replica.insert(t("http://ex.org/gabe", "http://ex.org/likes", "C"));
+    let before = replica.clone();
+    assert!(matches!(replica.apply(&diff), Err(OjiError::Constraint(_))));
+    assert_eq!(replica, before);
+  }
+  #[test]
+  fn patch_round_trip() {
+    let (a, b) = graphs();
+    let diff = a.diff(&b);
+    assert_eq!(diff.patch(), "TX .
+D <http://ex.org/gabe> <http://ex.org/likes> \"C\" .
+A <http://ex.org/gabe> <http://ex.org/name> \"Gabe\"@en .
+TC .
+");
+    assert_eq!(GraphDiff::from_patch(&diff.patch()).unwrap(), diff);
+    let mut replica = a.clone();
+    replica.apply_patch(&diff.patch()).unwrap();
+    assert_eq!(replica, b);
+    /* Shipped to another process and back */
+    let shared = SharedGraph::from(a);
+    shared.apply(&GraphDiff::from_patch(&diff.patch()).unwrap()).unwrap();
+    assert_eq!(*shared.snapshot(), b);
+    assert_eq!(shared.version(), 1);
+  }
+  #[test]
+  fn transactions() {
+    let diff = GraphDiff::from_patch("
+      H id <urn:uuid:0f1e2d3c> .
+      # One committed, one aborted, and rows outside a transaction
+      TX .
+      A <http://ex.org/a> <http://ex.org/p> \"1\" .
+      A <http://ex.org/a> <http://ex.org/p> \"2\" .
+      TC .
+      TX .
+      A <http://ex.org/a> <http://ex.org/p> \"3\" .
+      TA .
+      D <http://ex.org/a> <http://ex.org/p> \"2\" .
+      D _:b0 <http://ex.org/p> \"4\" .
+    ").unwrap();
+    assert_eq!(diff.added, vec![t("http://ex.org/a", "http://ex.org/p", "1")]);
+    assert_eq!(diff.removed, vec![t("_:b0", "http://ex.org/p", "4")]);
+  }
+  #[test]
+  fn malformed_patches() {
+    let cases = [("TX .\nA <http://ex.org/a> <http://ex.org/p> \"1\" .", "never committed"),
+                 ("TC .", "line 1: Commit without a transaction"),
+                 ("TX .\nTX .", "line 2: Transaction started inside another"),
+                 ("X <http://ex.org/a> .", "Unknown row 'X'"),
+                 ("A <http://ex.org/a> <http://ex.org/p> <http://ex.org/o> <http://ex.org/g> .", "named graphs"),
+                 ("A <http://ex.org/a> <http://ex.org/p> \"1\"", "Expected '.'"),
+                 ("D <http://ex.org/a> <http://ex.org/p> .", "Expected a term")];
+    for (patch, message) in cases.iter() {
+      match GraphDiff::from_patch(patch) {
+        Err(OjiError::Parse(msg)) => assert!(msg.contains(message), "{}: {}", patch, msg),
+        other => panic!("{}: {:?}", patch, other),
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod set_algebra {
+  use super::*;
+  use std::collections::HashSet;
+  type Triples = HashSet<(String, String, String)>;
+  fn triples(spec: &[(u8, u8, u8)]) -> Triples {
+    spec.iter().map(|(s, p, o)| (format!("http://ex.org/s{}", s), format!("http://ex.org/p{}", p), format!("o{}", o))).collect()
+  }
+  fn graph(t: &Triples) -> Graph {
+    Graph::from(t.iter().cloned())
+  }
+  /* Overlapping at every level: shared heads, shared mids, and whole branches only one side has */
+  fn sides() -> (Triples, Triples) {
+    (triples(&[(1, 1, 1), (1, 1, 2), (1, 2, 1), (2, 1, 1), (3, 3, 3)]),
+     triples(&[(1, 1, 2), (1, 1, 3), (1, 3, 1), (2, 1, 1), (4, 4, 4)]))
+  }
+  #[test]
+  fn matches_set_semantics() {
+    let (a, b) = sides();
+    let (ga, gb) = (graph(&a), graph(&b));
+    /* Equality with a freshly built Graph also checks all three orderings, and that no branch was left empty */
+    assert_eq!(ga.union(&gb), graph(&a.union(&b).cloned().collect()));
+    assert_eq!(ga.intersection(&gb), graph(&a.intersection(&b).cloned().collect()));
+    assert_eq!(ga.difference(&gb), graph(&a.difference(&b).cloned().collect()));
+    assert_eq!(gb.difference(&ga), graph(&b.difference(&a).cloned().collect()));
+    assert_eq!(ga.union(&gb), gb.union(&ga));
+    assert_eq!(ga.intersection(&gb), gb.intersection(&ga));
+  }
+  #[test]
+  fn in_place() {
+    let (a, b) = sides();
+    let (ga, gb) = (graph(&a), graph(&b));
+    let mut g = ga.clone();
+    g.union_with(&gb);
+    assert_eq!(g, ga.union(&gb));
+    let mut g = ga.clone();
+    g.intersect_with(&gb);
+    assert_eq!(g, ga.intersection(&gb));
+    let mut g = ga.clone();
+    g.difference_with(&gb);
+    assert_eq!(g, ga.difference(&gb));
+    g.difference_with(&ga);
+    assert_eq!(g, Graph::new());
+    /* Queries through pos and osp still see the result */
+    let mut g = ga.clone();
+    g.intersect_with(&gb);
+    assert!(g.contains_pattern(&(None, Some("http://ex.org/p1".to_string()), Some("o2".to_string()))));
+    assert!(!g.contains_pattern(&(None, None, Some("o3".to_string()))));
+  }
+  #[test]
+  fn empty_sides() {
+    let (a, _) = sides();
+    let g = graph(&a);
+    assert_eq!(g.union(&Graph::new()), g);
+    assert_eq!(Graph::new().union(&g), g);
+    assert_eq!(g.intersection(&Graph::new()), Graph::new());
+    assert_eq!(g.difference(&Graph::new()), g);
+    assert_eq!(Graph::new().difference(&g), Graph::new());
+  }
+}
+
+#[cfg(test)]
+mod subscriptions {
+  use super::*;
+  use std::sync::{Arc, Mutex};
+  const TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+  #[test]
+  fn track_gives_the_net_change() {
+    let mut g = Graph::new();
+    g.insert(t("http://ex.org/gabe", "http://ex.org/likes", "C"));
+    let ((), changes) = g.track(|g| {
+      g.insert(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"));
+      /* Neither of these changes anything */
+      g.insert(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"));
+      g.remove(&t("http://ex.org/oji", TYPE, "http://ex.org/Person"));
+      /* And these two cancel out */
+      g.insert(t("http://ex.org/gabe", "http://ex.org/likes", "Go"));
+      g.remove(&t("http://ex.org/gabe", "http://ex.org/likes", "Go"));
+      g.replace(&t("http://ex.org/gabe", "http://ex.org/likes", "C"), t("http://ex.org/gabe", "http://ex.org/likes", "Rust"));
+    });
+    /* Removals first, then insertions, each sorted */
+    assert_eq!(changes, vec![GraphChange::Removed(t("http://ex.org/gabe", "http://ex.org/likes", "C")),
+                             GraphChange::Inserted(t("http://ex.org/gabe", "http://ex.org/likes", "Rust")),
+                             GraphChange::Inserted(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"))]);
+    /* Other Graphs changed along the way aren't counted */
+    let (_, changes) = g.track(|_| Graph::new().insert(t("http://ex.org/a", "http://ex.org/p", "1")));
+    assert!(changes.is_empty());
+    /* Tracking inside tracking: the outer call sees the inner's changes too */
+    let (inner, outer) = g.track(|g| g.track(|g| g.insert(t("http://ex.org/a", "http://ex.org/p", "1"))).1);
+    assert_eq!(inner, outer);
+    assert_eq!(outer.len(), 1);
+  }
+  #[test]
+  fn tracks_updates_diffs_and_set_algebra() {
+    let mut g = Graph::new();
+    let failing = "INSERT DATA { <http://ex.org/a> <http://ex.org/p> <http://ex.org/b> } ; CLEAR GRAPH <http://ex.org/nope>";
+    let (result, changes) = g.track(|g| g.update(failing));
+    assert!(result.is_err());
+    assert!(changes.is_empty());
+    let (_, changes) = g.track(|g| g.update("INSERT DATA { <http://ex.org/a> <http://ex.org/p> \"1\" , \"2\" }"));
+    assert_eq!(changes.len(), 2);
+    let other = Graph::from(vec![t("http://ex.org/a", "http://ex.org/p", "2"), t("http://ex.org/a", "http://ex.org/p", "3")].into_iter());
+    let (_, changes) = g.track(|g| g.union_with(&other));
+    assert_eq!(changes, vec![GraphChange::Inserted(t("http://ex.org/a", "http://ex.org/p", "3"))]);
+    let (_, changes) = g.track(|g| g.difference_with(&other));
+    assert_eq!(changes.len(), 2);
+    let (_, changes) = g.track(|g| g.intersect_with(&other));
+    assert_eq!(changes, vec![GraphChange::Removed(t("http://ex.org/a", "http://ex.org/p", "1"))]);
+    let diff = g.diff(&other);
+    let (_, changes) = g.track(|g| g.apply(&diff).unwrap());
+    assert_eq!(changes.len(), 2);
+    assert_eq!(g, other);
+  }
+  #[test]
+  fn callbacks_see_matching_changes() {
+    let shared = SharedGraph::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&seen);
+    let id = shared.subscribe((None, Some(TYPE.to_string()), None), move |change| log.lock().unwrap().push(change.clone()));
+    assert_eq!(shared.version(), 0);
+    shared.insert(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"));
+    shared.insert(t("http://ex.org/gabe", "http://ex.org/name", "Gabe"));
+    shared.insert(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"));
+    shared.remove(&t("http://ex.org/gabe", TYPE, "http://ex.org/Person"));
+    assert_eq!(*seen.lock().unwrap(), vec![GraphChange::Inserted(t("http://ex.org/gabe", TYPE, "http://ex.org/Person")),
+                                           GraphChange::Removed(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"))]);
+    assert!(shared.unsubscribe(id));
+    assert!(!shared.unsubscribe(id));
+    shared.insert(t("http://ex.org/oji", TYPE, "http://ex.org/Database"));
+    assert_eq!(seen.lock().unwrap().len(), 2);
+  }
+  #[test]
+  fn channels() {
+    let shared = SharedGraph::new();
+    let (id, changes) = shared.subscribe_channel((Some("http://ex.org/gabe".to_string()), None, None));
+    /* A live snapshot makes the write copy the Graph first */
+    let before = shared.snapshot();
+    shared.write(|g| g.extend(vec![t("http://ex.org/gabe", "http://ex.org/likes", "Rust"),
+                                   t("http://ex.org/oji", "http://ex.org/is", "fast"),
+                                   t("http://ex.org/gabe", "http://ex.org/likes", "C")]));
+    assert_eq!(before.iter().count(), 0);
+    let got: Vec<GraphChange> = changes.try_iter().collect();
+    assert_eq!(got.len(), 2);
+    assert!(got.iter().all(|c| matches!(c, GraphChange::Inserted((s, _, _)) if s == "http://ex.org/gabe")));
+    assert!(shared.update("CLEAR GRAPH <http://ex.org/nope>").is_err());
+    shared.compact();
+    assert!(changes.try_recv().is_err());
+    /* The subscription goes with the Receiver */
+    drop(changes);
+    shared.insert(t("http://ex.org/gabe", "http://ex.org/likes", "Go"));
+    assert!(!shared.unsubscribe(id));
+  }
+  #[test]
+  fn callbacks_can_read_the_new_version() {
+    let shared = SharedGraph::new();
+    let reader = shared.clone();
+    let counts = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&counts);
+    shared.subscribe((None, None, None), move |_| log.lock().unwrap().push(reader.snapshot().iter().count()));
+    shared.insert(t("http://ex.org/a", "http://ex.org/p", "1"));
+    shared.insert(t("http://ex.org/a", "http://ex.org/p", "2"));
+    assert_eq!(*counts.lock().unwrap(), vec![1, 2]);
+  }
+  #[test]
+  fn observed_graphs_tell_each_mutation() {
+    let mut g = ObservedGraph::new();
+    let types = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&types);
+    g.subscribe((None, Some(TYPE.to_string()), None), move |change| log.lock().unwrap().push(change.clone()));
+    let (_, all) = g.subscribe_channel((None, None, None));
+    g.insert(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"));
+    g.insert(t("http://ex.org/gabe", "http://ex.org/likes", "C"));
+    g.insert(t("http://ex.org/gabe", "http://ex.org/likes", "C"));
+    assert!(g.update("INSERT DATA { <http://ex.org/a> <http://ex.org/p> 'x' } ; LOAD <http://ex.org/nowhere>").is_err());
+    g.remove(&t("http://ex.org/gabe", TYPE, "http://ex.org/Person"));
+    assert_eq!(*types.lock().unwrap(), vec![GraphChange::Inserted(t("http://ex.org/gabe", TYPE, "http://ex.org/Person")),
+                                           GraphChange::Removed(t("http://ex.org/gabe", TYPE, "http://ex.org/Person"))]);
+    assert_eq!(all.try_iter().count(), 3);
+    assert_eq!(g.iter().count(), 1);
+    assert_eq!(g.into_inner().iter().count(), 1);
+  }
+  #[test]
+  fn callbacks_can_subscribe_and_unsubscribe() {
+    use std::{sync::mpsc, thread, time::Duration};
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+      let shared = SharedGraph::new();
+      let heard = Arc::new(Mutex::new(Vec::new()));
+      /* Subscribes a logger the first time it's told anything, then unsubscribes itself */
+      let (handle, log) = (shared.clone(), Arc::clone(&heard));
+      let me = Arc::new(Mutex::new(None));
+      let id = Arc::clone(&me);
+      let first = shared.subscribe((None, None, None), move |_| {
+        let log = Arc::clone(&log);
+        handle.subscribe((None, None, None), move |change| log.lock().unwrap().push(change.clone()));
+        assert!(handle.unsubscribe(id.lock().unwrap().take().unwrap()));
+      });
+      *me.lock().unwrap() = Some(first);
+      shared.insert(t("http://ex.org/a", "http://ex.org/p", "1"));
+      shared.insert(t("http://ex.org/a", "http://ex.org/p", "2"));
+      assert!(!shared.unsubscribe(first));
+      done.send(heard.lock().unwrap().clone()).unwrap();
+    });
+    /* The logger hears from the next write on, and only one was subscribed */
+    assert_eq!(finished.recv_timeout(Duration::from_secs(10)),
+               Ok(vec![GraphChange::Inserted(t("http://ex.org/a", "http://ex.org/p", "2"))]));
+  }
+  #[test]
+  fn write_closures_are_compared_not_hooked() {
+    /* A Graph's own mutations aren't recorded anywhere; track works the change out afterwards */
+    let mut g = Graph::new();
+    let (_, changes) = g.track(|g| g.extend(vec![t("http://ex.org/a", "http://ex.org/p", "1"),
+                                                 t("http://ex.org/a", "http://ex.org/p", "1")]));
+    assert_eq!(changes, vec![GraphChange::Inserted(t("http://ex.org/a", "http://ex.org/p", "1"))]);
+    let mut observed = ObservedGraph::from(g);
+    let (_, all) = observed.subscribe_channel((None, None, None));
+    observed.write(|g| g.spo.insert(t("http://ex.org/b", "http://ex.org/p", "2")));
+    observed.replace(&t("http://ex.org/a", "http://ex.org/p", "1"), t("http://ex.org/a", "http://ex.org/p", "3"));
+    assert_eq!(all.try_iter().collect::<Vec<_>>(),
+               vec![GraphChange::Inserted(t("http://ex.org/b", "http://ex.org/p", "2")),
+                    GraphChange::Removed(t("http://ex.org/a", "http://ex.org/p", "1")),
+                    GraphChange::Inserted(t("http://ex.org/a", "http://ex.org/p", "3"))]);
+  }
+  #[test]
+  fn slow_callbacks_dont_deadlock_writers() {
+    use std::{sync::mpsc, thread, time::Duration};
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+      let shared = SharedGraph::new();
+      let reader = shared.clone();
+      shared.subscribe((None, None, None), move |_| {
+        thread::sleep(Duration::from_millis(50));
+        reader.snapshot();
+      });
+      /* A callback may write too; its changes are told after the current batch */
+      let writer = shared.clone();
+      shared.subscribe((None, None, Some("1".to_string())), move |_| writer.insert(t("http://ex.org/b", "http://ex.org/p", "echo")));
+      let other = shared.clone();
+      let inserting = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        other.insert(t("http://ex.org/a", "http://ex.org/p", "2"));
+      });
+      shared.insert(t("http://ex.org/a", "http://ex.org/p", "1"));
+      inserting.join().unwrap();
+      done.send(shared.snapshot().iter().count()).unwrap();
+    });
+    assert_eq!(finished.recv_timeout(Duration::from_secs(10)), Ok(3));
+  }
+}
@@ -0,0 +1,288 @@
+use std::{
+  collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+  hash::{Hash, Hasher},
+};
+use super::{
+  Graph::Graph,
+  super::Term::{self, TermKind},
+};
+
+type Triple = (String, String, String);
+
+/*
+Blank nodes are Terms like "_:b0", whose label only means something
+within the document or Graph it came from. So:
+  relabel_apart renames a document's labels away from those a Graph
+    already uses before the two are merged, and Apart does the same
+    for a document streamed in a triple at a time,
+  skolemize/deskolemize swap them for IRIs under /.well-known/genid/
+    and back, for exporting to systems that can't hold blank nodes,
+  isomorphic compares Graphs with blank nodes up to renaming.
+*/
+pub fn labels<'a, I>(triples: I) -> HashSet<String>
+  where I: IntoIterator<Item=&'a Triple> {
+  triples.into_iter()
+         .flat_map(|(s, _, o)| vec![s, o])
+         .filter(|t| Term::kind(t) == TermKind::BlankNode)
+         .cloned()
+         .collect()
+}
+/* The next "_:bN" label from next on that isn't taken */
+pub fn fresh<F: Fn(&str) -> bool>(next: &mut usize, taken: F) -> String {
+  loop {
+    let label = format!("_:b{}", next);
+    *next += 1;
+    if !taken(&label) {
+      return label
+    }
+  }
+}
+/*
+Labels in triples that existing already uses are swapped for fresh ones;
+the rest are kept, so nothing changes when nothing collides
+*/
+pub fn relabel_apart(existing: &HashSet<String>, triples: Vec<Triple>) -> Vec<Triple> {
+  let incoming = labels(triples.iter());
+  let mut clashes: Vec<&String> = incoming.iter().filter(|l| existing.contains(*l)).collect();
+  if clashes.is_empty() {
+    return triples
+  }
+  clashes.sort();
+  let mut next = 0;
+  let mut renamed = HashMap::new();
+  for label in clashes {
+    let fresh = fresh(&mut next, |l| existing.contains(l) || incoming.contains(l));
+    renamed.insert(label.clone(), fresh);
+  }
+  let rename = |t: String| renamed.get(&t).cloned().unwrap_or(t);
+  triples.into_iter().map(|(s, p, o)| (rename(s), p, rename(o))).collect()
+}
+/*
+relabel_apart for a stream, which can't look ahead: each label is settled
+the first time it's seen, and a later label that a fresh one took is
+renamed in turn
+*/
+pub struct Apart {
+  existing: HashSet<String>,
+  renamed: HashMap<String, String>,
+  taken: HashSet<String>,
+  next: usize,
+}
+impl Apart {
+  pub fn new(existing: HashSet<String>) -> Self {
+    Apart { existing, renamed: HashMap::new(), taken: HashSet::new(), next: 0 }
+  }
+  pub fn triple(&mut self, (s, p, o): Triple) -> Triple {
+    (self.term(s), p, self.term(o))
+  }
+  fn term(&mut self, term: String) -> String {
+    if Term::kind(&term) != TermKind::BlankNode {
+      return term
+    }
+    if let Some(label) = self.renamed.get(&term) {
+      return label.clone()
+    }
+    let label = if self.existing.contains(&term) || self.taken.contains(&term) {
+      let (existing, taken) = (&self.existing, &self.taken);
+      fresh(&mut self.next, |l| existing.contains(l) || taken.contains(l))
+    } else {
+      term.clone()
+    };
+    self.taken.insert(label.clone());
+    self.renamed.insert(term, label.clone());
+    label
+  }
+}
+
+/* Skolem IRIs, as RDF 1.1 suggests: <authority>/.well-known/genid/<label> */
+fn genid(authority: &str) -> String {
+  format!("{}/.well-known/genid/", authority.trim_end_matches('/'))
+}
+pub fn skolemize(graph: &Graph, authority: &str) -> Graph {
+  let genid = genid(authority);
+  let skolem = |t: String| match Term::kind(&t) {
+    TermKind::BlankNode => format!("{}{}", genid, encode(&t[2..])),
+    _ => t,
+  };
+  Graph::from(graph.iter().map(|(s, p, o)| (skolem(s), p, skolem(o))))
+}
+pub fn deskolemize(graph: &Graph, authority: &str) -> Graph {
+  let genid = genid(authority);
+  let blank = |t: String| match t.strip_prefix(genid.as_str()) {
+    Some(label) if !label.is_empty() => format!("_:{}", decode(label)),
+    _ => t,
+  };
+  Graph::from(graph.iter().map(|(s, p, o)| (blank(s), p, blank(o))))
+}
+/* Percent-encoding for anything outside the unreserved characters */
+fn encode(label: &str) -> String {
+  let mut encoded = String::with_capacity(label.len());
+  for b in label.bytes() {
+    match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(b as char),
+      _ => encoded.push_str(&format!("%{:02X}", b)),
+    }
+  }
+  encoded
+}
+fn decode(label: &str) -> String {
+  let bytes = label.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let hex = label.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+    match (bytes[i], hex) {
+      (b'%', Some(b)) => {
+        decoded.push(b);
+        i += 3;
+      },
+      (b, _) => {
+        decoded.push(b);
+        i += 1;
+      },
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/*
+Ground triples must match exactly. Blank nodes are then coloured by
+repeatedly hashing their neighbourhoods until the colouring settles,
+and a mapping is searched for only between nodes of the same colour,
+checking each triple as soon as both its blank nodes are mapped.
+*/
+pub fn isomorphic(a: &Graph, b: &Graph) -> bool {
+  let (a, b): (Vec<Triple>, Vec<Triple>) = (a.iter().collect(), b.iter().collect());
+  if a.len() != b.len() {
+    return false
+  }
+  let is_blank = |t: &String| Term::kind(t) == TermKind::BlankNode;
+  let (a_ground, a_blank): (Vec<&Triple>, Vec<&Triple>) = a.iter().partition(|(s, _, o)| !is_blank(s) && !is_blank(o));
+  let (b_ground, b_blank): (Vec<&Triple>, Vec<&Triple>) = b.iter().partition(|(s, _, o)| !is_blank(s) && !is_blank(o));
+  if a_ground.len() != b_ground.len() {
+    return false
+  }
+  let b_ground: HashSet<&Triple> = b_ground.into_iter().collect();
+  if !a_ground.iter().all(|t| b_ground.contains(t)) {
+    return false
+  }
+  let (a_colours, b_colours) = (colour(&a_blank), colour(&b_blank));
+  let histogram = |colours: &HashMap<&String, u64>| {
+    let mut h: BTreeMap<u64, usize> = BTreeMap::new();
+    for c in colours.values() {
+      *h.entry(*c).or_default() += 1;
+    }
+    h
+  };
+  if histogram(&a_colours) != histogram(&b_colours) {
+    return false
+  }
+  /* Most constrained first: nodes whose colour is rarest */
+  let sizes = histogram(&a_colours);
+  let mut order: Vec<&String> = a_colours.keys().copied().collect();
+  order.sort_by_key(|n| (sizes[&a_colours[n]], a_colours[n], n.to_string()));
+  let mut around: HashMap<&String, Vec<&Triple>> = HashMap::new();
+  for t in a_blank.iter() {
+    for node in [&t.0, &t.2].iter().filter(|n| is_blank(n)) {
+      around.entry(node).or_default().push(t);
+    }
+  }
+  let search = Search {
+    around,
+    b_blank: b_blank.iter().copied().collect(),
+    a_colours: &a_colours,
+    b_colours: &b_colours,
+    order,
+  };
+  search.extend(0, &mut HashMap::new(), &mut HashSet::new())
+}
+/* (0 if the node is the subject else 1, predicate, other end's colour, other end if ground) */
+type Edge<'a> = (u8, &'a String, u64, Option<&'a String>);
+fn colour<'a>(triples: &[&'a Triple]) -> HashMap<&'a String, u64> {
+  let is_blank = |t: &String| Term::kind(t) == TermKind::BlankNode;
+  let mut colours: HashMap<&String, u64> = HashMap::new();
+  for (s, _, o) in triples.iter() {
+    for t in [s, o].iter() {
+      if is_blank(t) {
+        colours.insert(t, 0);
+      }
+    }
+  }
+  let mut classes = 1;
+  /* Each round can only split classes, so at most one per node */
+  for _ in 0..colours.len() {
+    let mut edges: HashMap<&String, Vec<Edge>> = HashMap::new();
+    for (s, p, o) in triples.iter() {
+      let seen = |t: &'a String| match colours.get(t) {
+        Some(c) => (*c, None),
+        None => (0, Some(t)),
+      };
+      if is_blank(s) {
+        let (c, ground) = seen(o);
+        edges.entry(s).or_default().push((0, p, c, ground));
+      }
+      if is_blank(o) {
+        let (c, ground) = seen(s);
+        edges.entry(o).or_default().push((1, p, c, ground));
+      }
+    }
+    let mut next = HashMap::new();
+    for (node, mut neighbourhood) in edges {
+      neighbourhood.sort();
+      let mut hasher = DefaultHasher::new();
+      colours[node].hash(&mut hasher);
+      neighbourhood.hash(&mut hasher);
+      next.insert(node, hasher.finish());
+    }
+    let count = next.values().collect::<HashSet<_>>().len();
+    colours = next;
+    if count == classes {
+      break
+    }
+    classes = count;
+  }
+  colours
+}
+struct Search<'a> {
+  /* a's triples by the blank nodes in them */
+  around: HashMap<&'a String, Vec<&'a Triple>>,
+  b_blank: HashSet<&'a Triple>,
+  a_colours: &'a HashMap<&'a String, u64>,
+  b_colours: &'a HashMap<&'a String, u64>,
+  order: Vec<&'a String>,
+}
+impl<'a> Search<'a> {
+  fn extend(&self, i: usize, map: &mut HashMap<&'a String, &'a String>, used: &mut HashSet<&'a String>) -> bool {
+    if i == self.order.len() {
+      return true
+    }
+    let node = self.order[i];
+    for (candidate, c) in self.b_colours.iter() {
+      if *c != self.a_colours[node] || used.contains(candidate) {
+        continue
+      }
+      map.insert(node, candidate);
+      used.insert(candidate);
+      if self.consistent(node, map) && self.extend(i + 1, map, used) {
+        return true
+      }
+      map.remove(node);
+      used.remove(candidate);
+    }
+    false
+  }
+  /* Every triple around node whose blank nodes are all mapped is in b */
+  fn consistent(&self, node: &String, map: &HashMap<&'a String, &'a String>) -> bool {
+    let is_blank = |t: &String| Term::kind(t) == TermKind::BlankNode;
+    let mapped = |t: &'a String| -> Option<&'a String> {
+      match is_blank(t) {
+        true => map.get(t).copied(),
+        false => Some(t),
+      }
+    };
+    self.around[node].iter().all(|(s, p, o)| match (mapped(s), mapped(o)) {
+      (Some(s), Some(o)) => self.b_blank.contains(&(s.clone(), p.clone(), o.clone())),
+      _ => true,
+    })
+  }
+}
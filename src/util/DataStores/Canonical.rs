@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashMap};
+use sha2::{Digest, Sha256};
+use super::super::{
+  Error::Error,
+  Term::{self, TermKind},
+};
+
+type Quad = (String, String, String, Option<String>);
+
+/*
+RDF Dataset Canonicalization (RDFC-1.0, https://www.w3.org/TR/rdf-canon/)
+with SHA-256. Blank nodes are relabelled _:c14n0, _:c14n1, ... from
+hashes of their surroundings, so two datasets that differ only in blank
+node labels come out as the same canonical N-Quads, and so the same hash.
+Labels that can't be told apart by their own quads are settled by the
+N-Degree step, which tries orderings of the nodes around them; that is
+exponential for very symmetric data, so it gives up after MAX_WORK steps.
+*/
+const MAX_WORK: usize = 1 << 20;
+
+pub fn canonicalize(quads: &[Quad]) -> Result<String, Error> {
+  let mut state = State {
+    quads_of: HashMap::new(),
+    canonical: Issuer::new("c14n"),
+    work: 0,
+  };
+  for quad in quads.iter() {
+    for term in [Some(&quad.0), Some(&quad.2), quad.3.as_ref()].iter().flatten() {
+      if Term::kind(term) == TermKind::BlankNode {
+        let around = state.quads_of.entry(term.as_str()).or_default();
+        if around.last().is_none_or(|last| !std::ptr::eq(*last, quad)) {
+          around.push(quad);
+        }
+      }
+    }
+  }
+  let mut nodes: Vec<&str> = state.quads_of.keys().copied().collect();
+  nodes.sort_unstable();
+  let mut by_hash: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+  for node in nodes {
+    by_hash.entry(state.first_degree(node)).or_default().push(node);
+  }
+  /* Nodes with a hash of their own are labelled straight away */
+  for nodes in by_hash.values().filter(|nodes| nodes.len() == 1) {
+    state.canonical.issue(nodes[0]);
+  }
+  for nodes in by_hash.values().filter(|nodes| nodes.len() > 1) {
+    let mut paths = Vec::new();
+    for node in nodes.iter() {
+      if state.canonical.get(node).is_some() {
+        continue
+      }
+      let mut issuer = Issuer::new("b");
+      issuer.issue(node);
+      paths.push(state.n_degree(node, issuer)?);
+    }
+    paths.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, issuer) in paths {
+      for node in issuer.issued {
+        state.canonical.issue(&node);
+      }
+    }
+  }
+  let label = |node: &str| format!("_:{}", state.canonical.get(node).unwrap());
+  let mut lines: Vec<String> = quads.iter().map(|quad| nquad(quad, &label)).collect();
+  lines.sort_unstable();
+  Ok(lines.concat())
+}
+/* Lowercase hex SHA-256 of a canonical document, as RDFC-1.0 suggests */
+pub fn hash(doc: &str) -> String {
+  hex(&sha256(doc.as_bytes()))
+}
+
+/* Hands out prefix0, prefix1, ... remembering the order they were issued in */
+#[derive(Clone)]
+struct Issuer {
+  prefix: &'static str,
+  issued: Vec<String>,
+  ids: HashMap<String, String>,
+}
+impl Issuer {
+  fn new(prefix: &'static str) -> Self {
+    Issuer {
+      prefix,
+      issued: Vec::new(),
+      ids: HashMap::new(),
+    }
+  }
+  fn get(&self, node: &str) -> Option<&String> {
+    self.ids.get(node)
+  }
+  fn issue(&mut self, node: &str) -> String {
+    if let Some(id) = self.ids.get(node) {
+      return id.clone()
+    }
+    let id = format!("{}{}", self.prefix, self.issued.len());
+    self.issued.push(node.to_string());
+    self.ids.insert(node.to_string(), id.clone());
+    id
+  }
+}
+
+struct State<'a> {
+  quads_of: HashMap<&'a str, Vec<&'a Quad>>,
+  canonical: Issuer,
+  work: usize,
+}
+impl<'a> State<'a> {
+  /* The node's own quads, with it written as _:a and any other blank node as _:z */
+  fn first_degree(&self, node: &str) -> String {
+    let label = |other: &str| if other == node { "_:a".to_string() } else { "_:z".to_string() };
+    let mut lines: Vec<String> = self.quads_of[node].iter().map(|quad| nquad(quad, &label)).collect();
+    lines.sort_unstable();
+    hash(&lines.concat())
+  }
+  fn related(&self, related: &str, quad: &Quad, issuer: &Issuer, position: char) -> String {
+    let mut input = position.to_string();
+    if position != 'g' {
+      input.push_str(&format!("<{}>", quad.1));
+    }
+    match self.canonical.get(related).or_else(|| issuer.get(related)) {
+      Some(id) => input.push_str(&format!("_:{}", id)),
+      None => input.push_str(&self.first_degree(related)),
+    }
+    hash(&input)
+  }
+  /*
+  Hashes the paths out from node to the blank nodes around it, labelling
+  each group of related nodes in whichever order gives the smallest path
+  */
+  fn n_degree(&mut self, node: &'a str, mut issuer: Issuer) -> Result<(String, Issuer), Error> {
+    let mut groups: BTreeMap<String, Vec<&'a str>> = BTreeMap::new();
+    for quad in self.quads_of[node].iter() {
+      let positions = [('s', Some(&quad.0)), ('o', Some(&quad.2)), ('g', quad.3.as_ref())];
+      for (position, term) in positions.iter() {
+        if let Some(term) = term {
+          if Term::kind(term) == TermKind::BlankNode && term.as_str() != node {
+            let hash = self.related(term, quad, &issuer, *position);
+            groups.entry(hash).or_default().push(term.as_str());
+          }
+        }
+      }
+    }
+    let mut data = String::new();
+    for (hash, related) in groups {
+      data.push_str(&hash);
+      let mut chosen: Option<(String, Issuer)> = None;
+      let mut order: Vec<usize> = (0..related.len()).collect();
+      loop {
+        self.work += 1;
+        if self.work > MAX_WORK {
+          return Err(Error::Constraint("Canonicalization gave up, the blank nodes are too symmetric to label in time".into()))
+        }
+        if let Some(path) = self.path(&order, &related, &issuer, chosen.as_ref().map(|c| &c.0))? {
+          if chosen.as_ref().is_none_or(|c| path.0 < c.0) {
+            chosen = Some(path);
+          }
+        }
+        if !next_permutation(&mut order) {
+          break
+        }
+      }
+      let (path, chosen_issuer) = chosen.unwrap();
+      data.push_str(&path);
+      issuer = chosen_issuer;
+    }
+    Ok((hash(&data), issuer))
+  }
+  /* One ordering of related, or None once it can't beat the chosen path */
+  fn path(&mut self, order: &[usize], related: &[&'a str], issuer: &Issuer, chosen: Option<&String>)
+    -> Result<Option<(String, Issuer)>, Error> {
+    let worse = |path: &String| chosen.is_some_and(|c| path.len() >= c.len() && path > c);
+    let mut issuer = issuer.clone();
+    let mut path = String::new();
+    let mut recurse = Vec::new();
+    for &i in order.iter() {
+      match self.canonical.get(related[i]) {
+        Some(id) => path.push_str(&format!("_:{}", id)),
+        None => {
+          if issuer.get(related[i]).is_none() {
+            recurse.push(related[i]);
+          }
+          path.push_str(&format!("_:{}", issuer.issue(related[i])));
+        },
+      }
+      if worse(&path) {
+        return Ok(None)
+      }
+    }
+    for node in recurse {
+      let id = issuer.issue(node);
+      let (hash, result) = self.n_degree(node, issuer)?;
+      path.push_str(&format!("_:{}<{}>", id, hash));
+      issuer = result;
+      if worse(&path) {
+        return Ok(None)
+      }
+    }
+    Ok(Some((path, issuer)))
+  }
+}
+/* Steps order to the next lexicographic permutation, false after the last */
+fn next_permutation(order: &mut [usize]) -> bool {
+  let i = match (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) {
+    Some(i) => i,
+    None => return false,
+  };
+  let j = (i..order.len()).rev().find(|&j| order[j] > order[i - 1]).unwrap();
+  order.swap(i - 1, j);
+  order[i..].reverse();
+  true
+}
+
+/* Canonical N-Quads: one space between terms, and the canonical literal escapes */
+fn nquad<F: Fn(&str) -> String>(quad: &Quad, blank: &F) -> String {
+  let term = |t: &str| match Term::kind(t) {
+    TermKind::Iri       => format!("<{}>", t),
+    TermKind::BlankNode => blank(t),
+    TermKind::Literal   => {
+      let (lexical, lang, datatype) = Term::literal(t);
+      match (lang, datatype) {
+        (Some(lang), _) => format!("\"{}\"@{}", escape(&lexical), lang),
+        (None, Some(dt)) if dt != "http://www.w3.org/2001/XMLSchema#string" => {
+          format!("\"{}\"^^<{}>", escape(&lexical), dt)
+        },
+        _ => format!("\"{}\"", escape(&lexical)),
+      }
+    },
+  };
+  match &quad.3 {
+    Some(g) => format!("{} {} {} {} .\n", term(&quad.0), term(&quad.1), term(&quad.2), term(g)),
+    None => format!("{} {} {} .\n", term(&quad.0), term(&quad.1), term(&quad.2)),
+  }
+}
+fn escape(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '\u{8}' => escaped.push_str("\\b"),
+      '\t'    => escaped.push_str("\\t"),
+      '\n'    => escaped.push_str("\\n"),
+      '\u{c}' => escaped.push_str("\\f"),
+      '\r'    => escaped.push_str("\\r"),
+      '"'     => escaped.push_str("\\\""),
+      '\\'    => escaped.push_str("\\\\"),
+      '\u{0}'..='\u{1f}' | '\u{7f}' => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+      _       => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+  Sha256::digest(bytes).into()
+}
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,133 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  io::Read,
+};
+use super::{
+  Canonical,
+  Graph::Graph,
+  super::{Error::Error, Formats::TriG},
+};
+
+type Quad = (String, String, String, Option<String>);
+
+/*
+An RDF dataset: one default Graph and any number of named Graphs.
+A quad's fourth term is the name of its Graph, None for the default.
+Graph names are IRIs or blank nodes. A dataset has no empty named
+graphs: remove() drops a named Graph it empties, and one graph_mut()
+created but left empty is skipped by graph(), names(), is_empty(),
+trig() and ==, as if it weren't there.
+*/
+#[derive(Clone, Debug)]
+pub struct Dataset {
+  pub default: Graph,
+  pub named: HashMap<String, Graph>,
+}
+impl Dataset {
+  pub fn new() -> Self {
+    Dataset {
+      default: Graph::new(),
+      named: HashMap::new(),
+    }
+  }
+  pub fn graph(&self, name: Option<&str>) -> Option<&Graph> {
+    match name {
+      Some(name) => self.named.get(name).filter(|g| !g.spo.0.is_empty()),
+      None => Some(&self.default),
+    }
+  }
+  /* Creates the named Graph if it isn't there yet */
+  pub fn graph_mut(&mut self, name: Option<&str>) -> &mut Graph {
+    match name {
+      Some(name) => self.named.entry(name.to_string()).or_insert_with(Graph::new),
+      None => &mut self.default,
+    }
+  }
+  pub fn names(&self) -> Vec<&String> {
+    let mut names: Vec<&String> = self.nonempty().map(|(name, _)| name).collect();
+    names.sort();
+    names
+  }
+  pub fn insert(&mut self, (s, p, o, g): Quad) {
+    self.graph_mut(g.as_deref()).insert((s, p, o));
+  }
+  pub fn remove(&mut self, (s, p, o, g): &Quad) {
+    let t = (s.clone(), p.clone(), o.clone());
+    match g {
+      Some(name) => {
+        if let Some(graph) = self.named.get_mut(name) {
+          graph.remove(&t);
+          if graph.spo.0.is_empty() {
+            self.named.remove(name);
+          }
+        }
+      },
+      None => self.default.remove(&t),
+    }
+  }
+  pub fn len(&self) -> usize {
+    self.default.iter().count() + self.named.values().map(|g| g.iter().count()).sum::<usize>()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.default.spo.0.is_empty() && self.nonempty().next().is_none()
+  }
+  fn nonempty(&self) -> impl Iterator<Item=(&String, &Graph)> {
+    self.named.iter().filter(|(_, g)| !g.spo.0.is_empty())
+  }
+  pub fn quads(&self) -> Vec<Quad> {
+    let mut quads: Vec<Quad> = self.default.iter().map(|(s, p, o)| (s, p, o, None)).collect();
+    for (name, graph) in self.named.iter() {
+      quads.extend(graph.iter().map(|(s, p, o)| (s, p, o, Some(name.clone()))));
+    }
+    quads
+  }
+}
+impl PartialEq for Dataset {
+  fn eq(&self, other: &Self) -> bool {
+    self.default == other.default
+      && self.nonempty().count() == other.nonempty().count()
+      && self.nonempty().all(|(name, g)| other.named.get(name) == Some(g))
+  }
+}
+impl Default for Dataset {
+  fn default() -> Self {
+    Dataset::new()
+  }
+}
+impl From<Graph> for Dataset {
+  fn from(default: Graph) -> Self {
+    Dataset {
+      default,
+      named: HashMap::new(),
+    }
+  }
+}
+/* TriG Interface */
+impl Dataset {
+  pub fn trig(&self) -> String {
+    let named: BTreeMap<&String, _> = self.nonempty().map(|(name, g)| (name, &g.spo)).collect();
+    TriG::write(&self.default.spo, &named)
+  }
+  /* Relative IRIs resolve against base, or any @base, and are kept as-is without one */
+  pub fn from_trig(data: &str, base: Option<&str>) -> Result<Self, Error> {
+    let mut dataset = Dataset::new();
+    for quad in TriG::parse(data, base)? {
+      dataset.insert(quad);
+    }
+    Ok(dataset)
+  }
+  pub fn read_trig<R: Read>(mut reader: R, base: Option<&str>) -> Result<Self, Error> {
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
+    Dataset::from_trig(&data, base)
+  }
+}
+/* Canonicalization Interface */
+impl Dataset {
+  pub fn canonical_nquads(&self) -> Result<String, Error> {
+    Canonical::canonicalize(&self.quads())
+  }
+  pub fn canonical_hash(&self) -> Result<String, Error> {
+    Ok(Canonical::hash(&self.canonical_nquads()?))
+  }
+}
@@ -0,0 +1,44 @@
+use super::super::{Error::Error, Formats::RdfPatch};
+
+type Triple = (String, String, String);
+
+/*
+The triples to add to and remove from one Graph to get another,
+each list sorted. Blank nodes are compared by label, so two Graphs
+that only differ in blank node labels still have a diff.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GraphDiff {
+  pub added: Vec<Triple>,
+  pub removed: Vec<Triple>,
+}
+impl GraphDiff {
+  pub fn new() -> Self {
+    GraphDiff::default()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty()
+  }
+  pub fn len(&self) -> usize {
+    self.added.len() + self.removed.len()
+  }
+  /* Undoes this diff */
+  pub fn inverse(&self) -> Self {
+    GraphDiff {
+      added: self.removed.clone(),
+      removed: self.added.clone(),
+    }
+  }
+}
+/* RDF Patch Interface */
+impl GraphDiff {
+  /* One transaction, deletions first */
+  pub fn patch(&self) -> String {
+    RdfPatch::write(&self.added, &self.removed)
+  }
+  /* The net change of every committed transaction in the patch */
+  pub fn from_patch(data: &str) -> Result<Self, Error> {
+    let (added, removed) = RdfPatch::parse(data)?;
+    Ok(GraphDiff { added, removed })
+  }
+}
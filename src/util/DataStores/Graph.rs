@@ -1,8 +1,24 @@
 
+use std::{
+  collections::{hash_map::RandomState, HashSet},
+  hash::BuildHasher,
+  io::{BufRead, Read, Write},
+  thread,
+};
 use super::{
+  BlankNodes,
+  Canonical,
+  Diff::GraphDiff,
+  Maintenance::CompactionStats,
+  MappedGraph,
+  Observers::GraphChange,
   TripleStore::{TripleStore, TripleStoreIterator, TripleStoreRefIterator},
   super::{
-    Ordering
+    Ordering,
+    Error::Error,
+    Formats::{Ion::{self, IonFormat}, JsonLd, JsonStream, NTriples, RdfXml, Snapshot, Turtle},
+    SPARQL::Update::Update,
+    Term::{self, TermKind},
   }
 };
 
@@ -47,6 +63,10 @@ impl Graph {
     self.pos.remove(&(p.to_string(), o.to_string(), s.to_string()));
     self.osp.remove(&(o.to_string(), s.to_string(), p.to_string()));
   }
+  /* contains_pattern for a whole triple, without building a pattern */
+  pub(super) fn has(&self, s: &str, p: &str, o: &str) -> bool {
+    self.spo.0.get(s).and_then(|mids| mids.get(p)).is_some_and(|tails| tails.contains(o))
+  }
   pub fn replace(&mut self, old_t: &Triple, new_t: Triple) {
     self.remove(&old_t);
     self.insert(new_t);
@@ -54,6 +74,23 @@ impl Graph {
   pub fn iter(&self) -> TripleStoreRefIterator {
     self.spo.iter()
   }
+  pub fn extend<T>(&mut self, triples: T)
+    where T: IntoIterator<Item=Triple> {
+    for triple in triples {
+      self.insert(triple);
+    }
+  }
+  /*
+    Concise Bounded Description of a resource:
+      every triple it is the subject of (via spo)
+      and every triple it is the object of (via osp)
+  */
+  pub fn describe(&self, resource: &str) -> Graph {
+    let mut g = Graph::new();
+    g.extend(self.get_triple(&(Some(resource.to_string()), None, None)));
+    g.extend(self.get_triple(&(None, None, Some(resource.to_string()))));
+    g
+  }
 }
 impl Graph {
   pub fn get_triple(&self, qt: &QueryTriple) -> Vec<Triple> {
@@ -132,6 +169,16 @@ impl Graph {
       },
     }
   }
+  pub fn contains_pattern(&self, qt: &QueryTriple) -> bool {
+    let (s, p, o) = qt.clone();
+    match (&s, &p, &o) {
+      (Some(_), Some(_), _)
+      | (Some(_), None, None)
+      | (None, None, None) => self.spo.contains(&(s, p, o)),
+      (None, Some(_), _)   => self.pos.contains(&(p, o, s)),
+      (_, None, Some(_))   => self.osp.contains(&(o, s, p)),
+    }
+  }
   // fn get_double(&self, qd: &QueryDouble, ord: [Ordering; 2]) -> Vec<Double> {
   //   use Ordering::{S, P, O};
   //   let store = match &ord {
@@ -328,40 +375,438 @@ impl Graph {
   //   rc
   // }
 }
+/* SPARQL Update Interface */
+impl Graph {
+  pub fn update(&mut self, request: &str) -> Result<(), Error> {
+    Update::parse(request)?.execute(self)
+  }
+}
+/* Set Algebra */
+impl Graph {
+  /*
+    Each ordering is combined with its counterpart in other, see
+    TripleStore. Blank nodes match by label; merge() keeps them apart
+  */
+  pub fn union(&self, other: &Graph) -> Graph {
+    Graph {
+      spo: self.spo.union(&other.spo),
+      pos: self.pos.union(&other.pos),
+      osp: self.osp.union(&other.osp),
+    }
+  }
+  pub fn intersection(&self, other: &Graph) -> Graph {
+    Graph {
+      spo: self.spo.intersection(&other.spo),
+      pos: self.pos.intersection(&other.pos),
+      osp: self.osp.intersection(&other.osp),
+    }
+  }
+  pub fn difference(&self, other: &Graph) -> Graph {
+    Graph {
+      spo: self.spo.difference(&other.spo),
+      pos: self.pos.difference(&other.pos),
+      osp: self.osp.difference(&other.osp),
+    }
+  }
+  pub fn union_with(&mut self, other: &Graph) {
+    self.spo.union_with(&other.spo);
+    self.pos.union_with(&other.pos);
+    self.osp.union_with(&other.osp);
+  }
+  pub fn intersect_with(&mut self, other: &Graph) {
+    self.spo.intersect_with(&other.spo);
+    self.pos.intersect_with(&other.pos);
+    self.osp.intersect_with(&other.osp);
+  }
+  pub fn difference_with(&mut self, other: &Graph) {
+    self.spo.difference_with(&other.spo);
+    self.pos.difference_with(&other.pos);
+    self.osp.difference_with(&other.osp);
+  }
+}
+/* Diff Interface */
+impl Graph {
+  /* The changes that turn this Graph into other */
+  pub fn diff(&self, other: &Graph) -> GraphDiff {
+    let missing = |g: &Graph, (s, p, o): &Triple| !g.contains_pattern(&(Some(s.clone()), Some(p.clone()), Some(o.clone())));
+    let mut added: Vec<Triple> = other.iter().filter(|t| missing(self, t)).collect();
+    let mut removed: Vec<Triple> = self.iter().filter(|t| missing(other, t)).collect();
+    added.sort_unstable();
+    removed.sort_unstable();
+    GraphDiff { added, removed }
+  }
+  /*
+    All or nothing: if a removed triple isn't in the Graph, or an
+    added one already is, the Graph has moved on from the one the
+    diff was taken against and is left untouched
+  */
+  pub fn apply(&mut self, diff: &GraphDiff) -> Result<(), Error> {
+    let has = |(s, p, o): &Triple| self.contains_pattern(&(Some(s.clone()), Some(p.clone()), Some(o.clone())));
+    let conflict = |(s, p, o): &Triple, state: &str| {
+      Error::Constraint(format!("Diff doesn't apply, {} {} {} is {}",
+                                Term::to_ntriples(s), Term::to_ntriples(p), Term::to_ntriples(o), state))
+    };
+    if let Some(t) = diff.removed.iter().find(|t| !has(t)) {
+      return Err(conflict(t, "not in the Graph"))
+    }
+    if let Some(t) = diff.added.iter().find(|t| has(t)) {
+      return Err(conflict(t, "already in the Graph"))
+    }
+    for t in diff.removed.iter() {
+      self.remove(t);
+    }
+    self.extend(diff.added.iter().cloned());
+    Ok(())
+  }
+  pub fn apply_patch(&mut self, patch: &str) -> Result<(), Error> {
+    self.apply(&GraphDiff::from_patch(patch)?)
+  }
+}
+/* Change Tracking */
+impl Graph {
+  /*
+    Runs f and hands back its result along with the net change it made
+    to this Graph, each triple that really went out or came in once:
+    removals first, then insertions, each sorted. A SPARQL Update that
+    fails and is rolled back comes to nothing.
+    It's worked out by comparing against a copy taken before f runs,
+    so it costs a clone of the Graph; a Graph keeps no record of its
+    own changes. ObservedGraph and SharedGraph note what their own
+    methods change as they go, and only fall back on this for write()
+  */
+  pub fn track<F, R>(&mut self, f: F) -> (R, Vec<GraphChange>)
+    where F: FnOnce(&mut Graph) -> R {
+    let before = self.clone();
+    let ret = f(self);
+    let GraphDiff { added, removed } = before.diff(self);
+    let changes = removed.into_iter().map(GraphChange::Removed)
+                         .chain(added.into_iter().map(GraphChange::Inserted))
+                         .collect();
+    (ret, changes)
+  }
+}
+/* Compaction */
+impl Graph {
+  pub fn compact(&mut self) -> CompactionStats {
+    self.spo.compact() + self.pos.compact() + self.osp.compact()
+  }
+  pub fn reclaimable(&self) -> CompactionStats {
+    self.spo.reclaimable() + self.pos.reclaimable() + self.osp.reclaimable()
+  }
+}
+/* Bulk loading */
+impl Graph {
+  pub fn bulk_load(triples: &[Triple]) -> Self {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    Self::bulk_load_with_threads(triples, threads)
+  }
+  /*
+    Builds spo, pos and osp at the same time, one thread per
+    ordering; with 6 or more threads each ordering is split
+    again into shards by head, which can never overlap
+  */
+  pub fn bulk_load_with_threads(triples: &[Triple], threads: usize) -> Self {
+    bulk_load(triples, threads)
+  }
+}
+/* Generic over the term type, so triples borrowed from a snapshot's dictionary load without copying first */
+fn bulk_load<T: AsRef<str> + Sync>(triples: &[(T, T, T)], threads: usize) -> Graph {
+  if threads <= 1 {
+    /* Nothing to gain from threads, fill all three in one pass */
+    let mut g = Graph::new();
+    for (s, p, o) in triples {
+      let (s, p, o) = (s.as_ref(), p.as_ref(), o.as_ref());
+      g.spo.insert_ref((s, p, o));
+      g.pos.insert_ref((p, o, s));
+      g.osp.insert_ref((o, s, p));
+    }
+    return g
+  }
+  let shards = (threads / 3).max(1);
+  thread::scope(|scope| {
+    let spo = scope.spawn(|| build_store(triples, shards, |(s, p, o)| (s.as_ref(), p.as_ref(), o.as_ref())));
+    let pos = scope.spawn(|| build_store(triples, shards, |(s, p, o)| (p.as_ref(), o.as_ref(), s.as_ref())));
+    let osp = scope.spawn(|| build_store(triples, shards, |(s, p, o)| (o.as_ref(), s.as_ref(), p.as_ref())));
+    Graph {
+      spo: spo.join().expect("Bulk load of spo panicked"),
+      pos: pos.join().expect("Bulk load of pos panicked"),
+      osp: osp.join().expect("Bulk load of osp panicked"),
+    }
+  })
+}
+/* Puts a triple's terms in one ordering's order */
+type Reorder<T> = fn(&(T, T, T)) -> (&str, &str, &str);
+fn build_store<T: AsRef<str> + Sync>(triples: &[(T, T, T)], shards: usize, order: Reorder<T>) -> TripleStore {
+  if shards == 1 {
+    let mut store = TripleStore::new();
+    for triple in triples {
+      store.insert_ref(order(triple));
+    }
+    return store
+  }
+  let shard_of = RandomState::new();
+  thread::scope(|scope| {
+    let workers: Vec<_> = (0..shards).map(|shard| {
+      let shard_of = &shard_of;
+      scope.spawn(move || {
+        let mut store = TripleStore::new();
+        for triple in triples {
+          let (h, m, t) = order(triple);
+          if shard_of.hash_one(h) as usize % shards == shard {
+            store.insert_ref((h, m, t));
+          }
+        }
+        store
+      })
+    }).collect();
+    /* Shards have no heads in common, so merging is just moving
+         the smaller maps' branches into the first */
+    let mut stores = workers.into_iter()
+                            .map(|worker| worker.join().expect("Bulk load worker panicked"));
+    let mut store = stores.next().unwrap_or_else(TripleStore::new);
+    for shard in stores {
+      store.0.extend(shard.0);
+    }
+    store
+  })
+}
+/* N-Triples Interface */
+impl Graph {
+  pub fn ntriples(&self) -> String {
+    NTriples::write(&self.get_triple(&(None, None, None)))
+  }
+  pub fn from_ntriples(data: &str) -> Result<Self, Error> {
+    Ok(Graph::bulk_load(&NTriples::parse(data)?))
+  }
+  pub fn read_ntriples<R: BufRead>(reader: R) -> Result<Self, Error> {
+    Ok(Graph::bulk_load(&NTriples::read(reader)?))
+  }
+  pub fn insert_ntriples(&mut self, data: &str) -> Result<(), Error> {
+    let triples = NTriples::parse(data)?;
+    self.insert_document(triples);
+    Ok(())
+  }
+}
+/* Turtle Interface */
+impl Graph {
+  pub fn turtle(&self) -> String {
+    Turtle::write(&self.spo)
+  }
+  pub fn from_turtle(data: &str, base: Option<&str>) -> Result<Self, Error> {
+    Ok(Graph::bulk_load(&Turtle::parse(data, base)?))
+  }
+  pub fn read_turtle<R: Read>(mut reader: R, base: Option<&str>) -> Result<Self, Error> {
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
+    Graph::from_turtle(&data, base)
+  }
+  pub fn insert_turtle(&mut self, data: &str, base: Option<&str>) -> Result<(), Error> {
+    let triples = Turtle::parse(data, base)?;
+    self.insert_document(triples);
+    Ok(())
+  }
+}
+/* Blank Node Interface */
+impl Graph {
+  pub fn blank_nodes(&self) -> HashSet<String> {
+    self.spo.0.keys()
+              .chain(self.osp.0.keys())
+              .filter(|t| Term::kind(t) == TermKind::BlankNode)
+              .cloned()
+              .collect()
+  }
+  /* Adds other's triples, with its blank nodes kept apart as for any document */
+  pub fn merge(&mut self, other: Graph) {
+    self.insert_document(other.into_iter().collect());
+  }
+  /*
+    Every insert_* and import_* method and merge add a whole document
+    through here, and SPARQL LOAD the same way: its blank nodes whose labels
+    this Graph already uses are renamed first, so two documents' _:b0 stay apart
+  */
+  pub(crate) fn insert_document(&mut self, triples: Vec<Triple>) {
+    self.extend(BlankNodes::relabel_apart(&self.blank_nodes(), triples));
+  }
+  /* Blank nodes become IRIs under <authority>/.well-known/genid/, for exporting */
+  pub fn skolemize(&self, authority: &str) -> Graph {
+    BlankNodes::skolemize(self, authority)
+  }
+  /* The inverse of skolemize with the same authority */
+  pub fn deskolemize(&self, authority: &str) -> Graph {
+    BlankNodes::deskolemize(self, authority)
+  }
+  /* Equal up to renaming blank nodes */
+  pub fn isomorphic(&self, other: &Graph) -> bool {
+    BlankNodes::isomorphic(self, other)
+  }
+}
+/* Canonicalization Interface */
+impl Graph {
+  /* Sorted N-Quads with blank nodes relabelled _:c14n0.. as in RDFC-1.0 */
+  pub fn canonical_nquads(&self) -> Result<String, Error> {
+    let quads: Vec<_> = self.iter().map(|(s, p, o)| (s, p, o, None)).collect();
+    Canonical::canonicalize(&quads)
+  }
+  /* SHA-256 of canonical_nquads(), equal for Graphs equal up to blank node labels */
+  pub fn canonical_hash(&self) -> Result<String, Error> {
+    Ok(Canonical::hash(&self.canonical_nquads()?))
+  }
+}
+/* RDF/XML Interface */
+impl Graph {
+  pub fn rdfxml(&self) -> Result<String, Error> {
+    RdfXml::write(&self.spo)
+  }
+  /* Relative IRIs resolve against base, or any xml:base, and are kept as-is without one */
+  pub fn from_rdfxml(data: &str, base: Option<&str>) -> Result<Self, Error> {
+    Ok(Graph::bulk_load(&RdfXml::parse(data, base)?))
+  }
+  pub fn read_rdfxml<R: Read>(mut reader: R, base: Option<&str>) -> Result<Self, Error> {
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
+    Graph::from_rdfxml(&data, base)
+  }
+}
+/* Binary Snapshot Interface */
+impl Graph {
+  pub fn to_snapshot(&self) -> Vec<u8> {
+    Snapshot::write(&self.spo)
+  }
+  pub fn write_snapshot<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+    writer.write_all(&self.to_snapshot())?;
+    Ok(())
+  }
+  pub fn from_snapshot(data: &[u8]) -> Result<Self, Error> {
+    let (terms, ids) = Snapshot::read(data)?;
+    let triples: Vec<(&str, &str, &str)> = ids.into_iter()
+                                              .map(|(s, p, o)| (terms[s].as_str(), terms[p].as_str(), terms[o].as_str()))
+                                              .collect();
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    Ok(bulk_load(&triples, threads))
+  }
+  pub fn read_snapshot<R: Read>(mut reader: R) -> Result<Self, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Graph::from_snapshot(&data)
+  }
+  /* Rewrites a snapshot from any version this build can read in the current one */
+  pub fn upgrade_snapshot(data: &[u8]) -> Result<Vec<u8>, Error> {
+    match Snapshot::version(data) {
+      Some(Snapshot::VERSION) => {
+        Snapshot::read(data)?;
+        Ok(data.to_vec())
+      },
+      _ => Ok(Graph::from_snapshot(data)?.to_snapshot()),
+    }
+  }
+}
+/* Memory-mapped Interface */
+impl Graph {
+  /* Opened again with MappedGraph::open */
+  pub fn write_mapped<W: Write>(&self, writer: W) -> Result<(), Error> {
+    MappedGraph::write(self, writer)
+  }
+}
+/* Ion Interface */
+impl Graph {
+  pub fn ion(&self, format: IonFormat) -> Vec<u8> {
+    Ion::write(&self.spo, format)
+  }
+  pub fn write_ion<W: Write>(&self, mut writer: W, format: IonFormat) -> Result<(), Error> {
+    writer.write_all(&self.ion(format))?;
+    Ok(())
+  }
+  /* Binary or text, told apart by the binary version marker */
+  pub fn from_ion(data: &[u8]) -> Result<Self, Error> {
+    Ok(Graph::from_spo(Ion::parse(data)?))
+  }
+  pub fn read_ion<R: Read>(mut reader: R) -> Result<Self, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Graph::from_ion(&data)
+  }
+  /* Builds the other two orderings from spo */
+  fn from_spo(spo: TripleStore) -> Self {
+    Graph {
+      pos: spo.clone().h_shift(),
+      osp: spo.clone().t_shift(),
+      spo,
+    }
+  }
+}
+/* JSON-LD Interface */
+impl Graph {
+  /*
+    Compacted against context, one node object per subject.
+    The context is never fetched, so it must be given whole:
+    either the context itself or a document holding one under @context
+  */
+  pub fn jsonld(&self, context: &str) -> Result<String, Error> {
+    JsonLd::write(&self.spo, context)
+  }
+  /* The document's own @context applies on top of context, if one is given */
+  pub fn from_jsonld(data: &str, context: Option<&str>) -> Result<Self, Error> {
+    Ok(Graph::bulk_load(&JsonLd::parse(data, context)?))
+  }
+  pub fn insert_jsonld(&mut self, data: &str, context: Option<&str>) -> Result<(), Error> {
+    let triples = JsonLd::parse(data, context)?;
+    self.insert_document(triples);
+    Ok(())
+  }
+}
 /* Json Interface */
 impl Graph {
-  pub fn json(&self) -> String {
-    serde_json::to_string(&self.spo).unwrap()
+  pub fn json(&self) -> Result<String, Error> {
+    self.spo.json()
   }
-  pub fn into_json(self) -> String {
-    serde_json::to_string(&self.spo).unwrap()
+  pub fn into_json(self) -> Result<String, Error> {
+    self.spo.into_json()
   }
-  pub fn from_json(data: &str) -> Result<Self, serde_json::error::Error> {
-    let triple_store: TripleStore = serde_json::from_str(data)?;
-    Ok(Graph {
-      spo: triple_store.clone(),
-      pos: triple_store.clone().h_shift(),
-      osp: triple_store.t_shift(),
-    })
+  pub fn from_json(data: &str) -> Result<Self, Error> {
+    Ok(Graph::from_spo(serde_json::from_str(data)?))
   }
-  pub fn insert_json<'a, T>(&mut self, data: &'a str) -> Result<(), serde_json::error::Error>
+  pub fn insert_json<'a, T>(&mut self, data: &'a str) -> Result<(), Error>
     where T: serde::Deserialize<'a>
-           + Iterator<Item=Triple> {
+           + IntoIterator<Item=Triple> {
     let triples: T = serde_json::from_str(&data)?;
-    for triple in triples {
-      self.insert(triple);
-    };
+    self.insert_document(triples.into_iter().collect());
     Ok(())
   }
-  pub fn remove_json<'a, T>(&mut self, data: &'a str) -> Result<(), serde_json::error::Error>
+  pub fn remove_json<'a, T>(&mut self, data: &'a str) -> Result<(), Error>
     where T: serde::Deserialize<'a>
-           + Iterator<Item=Triple> {
+           + IntoIterator<Item=Triple> {
     let triples: T = serde_json::from_str(&data)?;
     for triple in triples {
       self.remove(&triple);
     }
     Ok(())
   }
+  /*
+    Works out which shape data is in: the one json() writes, or an
+    array of [s, p, o] arrays and/or {"s", "p", "o"} objects.
+    Returns how many triples were read, and inserts none on an error
+  */
+  pub fn import_json(&mut self, data: &str) -> Result<usize, Error> {
+    let mut triples = Vec::new();
+    JsonStream::read(data.as_bytes(), |t| triples.push(t))?;
+    let read = triples.len();
+    self.insert_document(triples);
+    Ok(read)
+  }
+  /* As import_json, streamed from reader one triple at a time */
+  pub fn read_json<R: Read>(reader: R) -> Result<Self, Error> {
+    let mut g = Graph::new();
+    g.insert_json_stream(reader)?;
+    Ok(g)
+  }
+  /*
+    Returns how many triples were read; those before any error stay inserted.
+    Blank nodes are kept apart as with insert_document, a triple at a time
+  */
+  pub fn insert_json_stream<R: Read>(&mut self, reader: R) -> Result<usize, Error> {
+    let mut apart = BlankNodes::Apart::new(self.blank_nodes());
+    JsonStream::read(reader, |t| self.insert(apart.triple(t)))
+  }
 }
 impl IntoIterator for Graph {
   type Item = (String, String, String);
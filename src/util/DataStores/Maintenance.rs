@@ -0,0 +1,176 @@
+use std::{
+  ops::{Add, AddAssign},
+  sync::{Arc, Mutex, PoisonError, mpsc},
+  thread,
+  time::Duration,
+};
+use super::{MappedGraph::MappedGraph, SharedGraph::SharedGraph};
+
+/* What a maintenance pass gave back and sorted */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+  /* Spare HashMap/HashSet slots released */
+  pub slots_reclaimed: usize,
+  /* Rough size of those slots, not counting the Strings they pointed to */
+  pub bytes_reclaimed: usize,
+  /* Triples copied into a sorted segment for snapshots to scan */
+  pub triples_sorted: usize,
+  /* Passes that were abandoned because a write landed first */
+  pub passes_skipped: usize,
+}
+impl Add for CompactionStats {
+  type Output = Self;
+  fn add(self, other: Self) -> Self {
+    CompactionStats {
+      slots_reclaimed: self.slots_reclaimed + other.slots_reclaimed,
+      bytes_reclaimed: self.bytes_reclaimed + other.bytes_reclaimed,
+      triples_sorted: self.triples_sorted + other.triples_sorted,
+      passes_skipped: self.passes_skipped + other.passes_skipped,
+    }
+  }
+}
+impl CompactionStats {
+  pub(super) fn reclaimed(&mut self, slots: usize, slot_size: usize) {
+    self.slots_reclaimed += slots;
+    self.bytes_reclaimed += slots * slot_size;
+  }
+}
+impl AddAssign for CompactionStats {
+  fn add_assign(&mut self, other: Self) {
+    *self = *self + other;
+  }
+}
+
+/*
+Maintenance for a SharedGraph that doesn't hold up readers.
+Compaction copies the current version and compacts it off to the
+side, then swaps it in only if no write has landed in the meantime;
+inserts alone never leave spare capacity behind, so the copy is only
+made once removals have left something to give back.
+Sorting builds an immutable MappedGraph of the current version in
+memory, every ordering sorted, and hands it to that version's later
+snapshots, whose pattern scans then read contiguous records instead
+of walking hash levels. The next write retires it, and the hash
+indexes stay as they are for writes and single lookups, so a sorted
+version costs about a MappedGraph file's worth of memory on top.
+Readers keep querying their snapshots throughout both.
+*/
+impl SharedGraph {
+  pub fn compact(&self) -> CompactionStats {
+    let mut stats = CompactionStats::default();
+    /* Give up on going round writers after a few tries and
+         compact in place, behind the write lock */
+    let mut compacted = None;
+    for _ in 0..3 {
+      match self.try_compact() {
+        Some(pass) => {
+          compacted = Some(pass);
+          break
+        },
+        None => stats.passes_skipped += 1,
+      }
+    }
+    stats += compacted.unwrap_or_else(|| self.compact_locked());
+    match self.try_sort() {
+      Some(pass) => stats + pass,
+      None => stats + CompactionStats { passes_skipped: 1, ..CompactionStats::default() },
+    }
+  }
+  /* None if a write landed while compacting */
+  pub fn try_compact(&self) -> Option<CompactionStats> {
+    let snapshot = self.snapshot();
+    if snapshot.reclaimable().slots_reclaimed == 0 {
+      return Some(CompactionStats::default())
+    }
+    let mut graph = (*snapshot).clone();
+    let stats = graph.compact();
+    if self.swap_if_current(snapshot.version(), graph) {
+      Some(stats)
+    }
+    else {
+      None
+    }
+  }
+  /* None if a write landed while sorting; sorting a sorted version does nothing */
+  pub fn try_sort(&self) -> Option<CompactionStats> {
+    let snapshot = self.snapshot();
+    if snapshot.is_sorted() || snapshot.spo.0.is_empty() {
+      return Some(CompactionStats::default())
+    }
+    /* Only fails past u32::MAX distinct terms, which stay hash-only */
+    let sorted = match MappedGraph::from_graph(&snapshot) {
+      Ok(sorted) => sorted,
+      Err(_) => return Some(CompactionStats::default()),
+    };
+    let triples_sorted = sorted.len();
+    match self.sort_if_current(snapshot.version(), sorted) {
+      true => Some(CompactionStats { triples_sorted, ..CompactionStats::default() }),
+      false => None,
+    }
+  }
+  /*
+    Compacts and sorts every interval on a background thread until the
+    returned handle is stopped or dropped.
+    Versions that haven't changed since the last pass are left alone.
+  */
+  pub fn start_maintenance(&self, interval: Duration) -> MaintenanceHandle {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let totals = Arc::new(Mutex::new(CompactionStats::default()));
+    let graph = self.clone();
+    let thread_totals = Arc::clone(&totals);
+    let thread = thread::spawn(move || {
+      let mut compacted = None;
+      while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+        let version = graph.version();
+        if compacted == Some(version) {
+          continue
+        }
+        let skipped = CompactionStats { passes_skipped: 1, ..CompactionStats::default() };
+        let pass = match graph.try_compact() {
+          Some(compaction) => match graph.try_sort() {
+            Some(sorting) => {
+              compacted = Some(version);
+              compaction + sorting
+            },
+            None => compaction + skipped,
+          },
+          None => skipped,
+        };
+        *thread_totals.lock().unwrap_or_else(PoisonError::into_inner) += pass;
+      }
+    });
+    MaintenanceHandle { stop: Some(stop), thread: Some(thread), totals }
+  }
+}
+
+/* Controls a background maintenance thread */
+#[derive(Debug)]
+pub struct MaintenanceHandle {
+  stop: Option<mpsc::Sender<()>>,
+  thread: Option<thread::JoinHandle<()>>,
+  totals: Arc<Mutex<CompactionStats>>,
+}
+impl MaintenanceHandle {
+  /* Everything reclaimed so far */
+  pub fn stats(&self) -> CompactionStats {
+    *self.totals.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+  /* Waits for any pass in progress to finish */
+  pub fn stop(mut self) -> CompactionStats {
+    self.shutdown();
+    self.stats()
+  }
+  fn shutdown(&mut self) {
+    if let Some(stop) = self.stop.take() {
+      let _ = stop.send(());
+    }
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
+impl Drop for MaintenanceHandle {
+  fn drop(&mut self) {
+    self.shutdown();
+  }
+}
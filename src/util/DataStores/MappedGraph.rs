@@ -0,0 +1,329 @@
+use std::{
+  cmp::Ordering,
+  collections::{BTreeSet, HashMap},
+  fs::File,
+  io::{BufWriter, Write},
+  ops::Deref,
+  path::Path,
+};
+use memmap2::Mmap;
+use super::{
+  Graph::Graph,
+  PatternMatch::PatternMatch,
+  super::Error::Error,
+};
+
+type Triple = (String, String, String);
+type QueryTriple = (Option<String>, Option<String>, Option<String>);
+
+/*
+A read-only Graph queried straight out of a memory-mapped file,
+without building any HashMaps. Written by Graph::write_mapped, or
+built in memory by from_graph as the sorted, immutable segment a
+SharedGraph's maintenance answers snapshot queries from:
+
+  "OJIM" | version: u16 | reserved: u16 | terms: u64 | triples: u64
+  offsets: (terms + 1) x u64, where each term starts in the blob
+  blob: every term's UTF-8, sorted, padded to a multiple of 4
+  spo, pos, osp: triples x [u32; 3] term ids, each sorted
+
+Everything is little-endian. Since ids follow the sorted terms,
+each ordering is sorted by term too, and a pattern is answered by
+binary searching whichever ordering has its bound terms up front,
+as Graph picks between its three TripleStores.
+*/
+#[derive(Debug)]
+pub struct MappedGraph {
+  map: Bytes,
+  terms: usize,
+  triples: usize,
+  blob: usize,
+  orderings: [usize; 3],
+}
+
+/* The file's bytes, mapped or held in memory */
+#[derive(Debug)]
+enum Bytes {
+  Mapped(Mmap),
+  Owned(Vec<u8>),
+}
+impl Deref for Bytes {
+  type Target = [u8];
+  fn deref(&self) -> &[u8] {
+    match self {
+      Bytes::Mapped(map) => map,
+      Bytes::Owned(bytes) => bytes,
+    }
+  }
+}
+
+const MAGIC: &[u8; 4] = b"OJIM";
+const VERSION: u16 = 1;
+const HEADER: usize = 24;
+const RECORD: usize = 12;
+const SPO: usize = 0;
+const POS: usize = 1;
+const OSP: usize = 2;
+
+impl MappedGraph {
+  /*
+    The file mustn't be modified while it's open;
+    the map would change underneath any queries.
+  */
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    let file = File::open(path)?;
+    let map = unsafe { Mmap::map(&file)? };
+    MappedGraph::check(Bytes::Mapped(map))
+  }
+  /* The same layout, sorted into memory rather than a file */
+  pub fn from_graph(graph: &Graph) -> Result<Self, Error> {
+    let mut bytes = Vec::new();
+    write(graph, &mut bytes)?;
+    MappedGraph::check(Bytes::Owned(bytes))
+  }
+  fn check(map: Bytes) -> Result<Self, Error> {
+    let corrupt = |what: &str| Error::Parse(format!("Not a mapped Oji graph: {}", what));
+    if map.len() < HEADER || &map[..4] != MAGIC {
+      return Err(corrupt("bad header"))
+    }
+    let version = u16::from_le_bytes([map[4], map[5]]);
+    if version != VERSION {
+      return Err(Error::Parse(format!("Mapped graph version {} isn't supported (expected {})", version, VERSION)))
+    }
+    let terms = u64_at(&map, 8) as usize;
+    let triples = u64_at(&map, 16) as usize;
+    let blob = terms.checked_add(1)
+                    .and_then(|n| n.checked_mul(8))
+                    .and_then(|n| n.checked_add(HEADER))
+                    .filter(|&blob| blob <= map.len())
+                    .ok_or_else(|| corrupt("offsets run past the end"))?;
+    let blob_len = u64_at(&map, blob - 8) as usize;
+    let spo = blob.checked_add(blob_len)
+                  .map(|end| end.div_ceil(4) * 4)
+                  .ok_or_else(|| corrupt("terms run past the end"))?;
+    let ordering_len = triples.checked_mul(RECORD).ok_or_else(|| corrupt("too many triples"))?;
+    let expected = ordering_len.checked_mul(3).and_then(|n| n.checked_add(spo));
+    if expected != Some(map.len()) {
+      return Err(corrupt("length doesn't match its header"))
+    }
+    if terms > u32::MAX as usize {
+      return Err(corrupt("too many terms"))
+    }
+    let graph = MappedGraph {
+      terms,
+      triples,
+      blob,
+      orderings: [spo, spo + ordering_len, spo + 2 * ordering_len],
+      map,
+    };
+    /* Checked once here, so lookups can trust the file afterwards */
+    let offsets = blob - 8 * (terms + 1);
+    let mut last: Option<&[u8]> = None;
+    let mut start = 0;
+    for id in 0..=terms {
+      let end = u64_at(&graph.map, offsets + 8 * id) as usize;
+      if (id == 0 && end != 0) || end < start || end > blob_len {
+        return Err(corrupt("term offsets are out of order"))
+      }
+      if id > 0 {
+        let term = &graph.map[blob + start..blob + end];
+        std::str::from_utf8(term).map_err(|_| corrupt("term isn't valid UTF-8"))?;
+        if last.is_some_and(|last| last >= term) {
+          return Err(corrupt("terms aren't sorted"))
+        }
+        last = Some(term);
+      }
+      start = end;
+    }
+    for ordering in [SPO, POS, OSP].iter() {
+      let mut last = None;
+      for i in 0..triples {
+        let record = graph.record(*ordering, i);
+        if record.iter().any(|&id| id as usize >= terms) {
+          return Err(corrupt("term id out of range"))
+        }
+        if last.is_some_and(|last| last >= record) {
+          return Err(corrupt("triples aren't sorted"))
+        }
+        last = Some(record);
+      }
+    }
+    Ok(graph)
+  }
+  pub fn len(&self) -> usize {
+    self.triples
+  }
+  pub fn is_empty(&self) -> bool {
+    self.triples == 0
+  }
+  pub fn iter(&self) -> impl Iterator<Item=Triple> + '_ {
+    (0..self.triples).map(move |i| self.triple(SPO, i))
+  }
+  pub fn to_graph(&self) -> Graph {
+    let mut g = Graph::new();
+    g.extend(self.iter());
+    g
+  }
+  pub fn get_triple(&self, qt: &QueryTriple) -> Vec<Triple> {
+    match self.range(qt) {
+      Some((ordering, start, end)) => (start..end).map(|i| self.triple(ordering, i)).collect(),
+      None => Vec::new(),
+    }
+  }
+  pub fn contains_pattern(&self, qt: &QueryTriple) -> bool {
+    self.range(qt).is_some_and(|(_, start, end)| start < end)
+  }
+  /* The ordering to answer qt from, and the records in it that match */
+  fn range(&self, qt: &QueryTriple) -> Option<(usize, usize, usize)> {
+    let id = |term: &Option<String>| match term {
+      Some(t) => self.id(t).map(Some),
+      None => Some(None),
+    };
+    let (s, p, o) = match (id(&qt.0), id(&qt.1), id(&qt.2)) {
+      (Some(s), Some(p), Some(o)) => (s, p, o),
+      /* A term that isn't in the file can't match anything */
+      _ => return None,
+    };
+    let (ordering, prefix) = match (s, p, o) {
+      (Some(s), Some(p), Some(o)) => (SPO, vec![s, p, o]),
+      (Some(s), Some(p), None)    => (SPO, vec![s, p]),
+      (Some(s), None, Some(o))    => (OSP, vec![o, s]),
+      (None, Some(p), Some(o))    => (POS, vec![p, o]),
+      (Some(s), None, None)       => (SPO, vec![s]),
+      (None, Some(p), None)       => (POS, vec![p]),
+      (None, None, Some(o))       => (OSP, vec![o]),
+      (None, None, None)          => (SPO, vec![]),
+    };
+    let start = self.bound(ordering, &prefix, Ordering::Less);
+    let end = self.bound(ordering, &prefix, Ordering::Equal);
+    Some((ordering, start, end))
+  }
+
+  fn term(&self, id: u32) -> String {
+    String::from_utf8_lossy(self.term_bytes(id as usize)).into_owned()
+  }
+  /* check() has made sure every offset is in the blob */
+  fn term_bytes(&self, id: usize) -> &[u8] {
+    let at = self.blob - 8 * (self.terms + 1) + 8 * id;
+    let (start, end) = (u64_at(&self.map, at) as usize, u64_at(&self.map, at + 8) as usize);
+    &self.map[self.blob + start..self.blob + end]
+  }
+  fn id(&self, term: &str) -> Option<u32> {
+    let (mut lo, mut hi) = (0, self.terms);
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      match self.term_bytes(mid).cmp(term.as_bytes()) {
+        Ordering::Less => lo = mid + 1,
+        Ordering::Greater => hi = mid,
+        Ordering::Equal => return Some(mid as u32),
+      }
+    }
+    None
+  }
+  fn record(&self, ordering: usize, i: usize) -> [u32; 3] {
+    let at = self.orderings[ordering] + i * RECORD;
+    [u32_at(&self.map, at), u32_at(&self.map, at + 4), u32_at(&self.map, at + 8)]
+  }
+  fn triple(&self, ordering: usize, i: usize) -> Triple {
+    let [a, b, c] = self.record(ordering, i);
+    let (s, p, o) = match ordering {
+      POS => (c, a, b),
+      OSP => (b, c, a),
+      _   => (a, b, c),
+    };
+    (self.term(s), self.term(p), self.term(o))
+  }
+  /*
+    Index of the first record whose leading ids compare to prefix
+    as more than past: Less finds the start of the range, Equal its end
+  */
+  fn bound(&self, ordering: usize, prefix: &[u32], past: Ordering) -> usize {
+    let (mut lo, mut hi) = (0, self.triples);
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      if self.record(ordering, mid)[..prefix.len()].cmp(prefix) <= past {
+        lo = mid + 1;
+      }
+      else {
+        hi = mid;
+      }
+    }
+    lo
+  }
+}
+impl PatternMatch for MappedGraph {
+  fn get_triple(&self, qt: &QueryTriple) -> Vec<Triple> {
+    MappedGraph::get_triple(self, qt)
+  }
+  fn contains_pattern(&self, qt: &QueryTriple) -> bool {
+    MappedGraph::contains_pattern(self, qt)
+  }
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+  let mut buf = [0; 8];
+  if let Some(b) = bytes.get(at..at + 8) {
+    buf.copy_from_slice(b);
+  }
+  u64::from_le_bytes(buf)
+}
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+  let mut buf = [0; 4];
+  if let Some(b) = bytes.get(at..at + 4) {
+    buf.copy_from_slice(b);
+  }
+  u32::from_le_bytes(buf)
+}
+
+pub fn write<W: Write>(graph: &Graph, writer: W) -> Result<(), Error> {
+  let mut terms: BTreeSet<&str> = BTreeSet::new();
+  let mut triples: Vec<(&str, &str, &str)> = Vec::new();
+  for (s, predicates) in graph.spo.0.iter() {
+    for (p, objects) in predicates.iter() {
+      for o in objects.iter() {
+        terms.insert(s);
+        terms.insert(p);
+        terms.insert(o);
+        triples.push((s, p, o));
+      }
+    }
+  }
+  if terms.len() > u32::MAX as usize {
+    return Err(Error::Constraint("Too many distinct terms for a mapped graph".into()))
+  }
+  let ids: HashMap<&str, u32> = terms.iter().enumerate().map(|(i, t)| (*t, i as u32)).collect();
+  let mut spo: Vec<[u32; 3]> = triples.into_iter()
+                                      .map(|(s, p, o)| [ids[s], ids[p], ids[o]])
+                                      .collect();
+  let mut pos: Vec<[u32; 3]> = spo.iter().map(|&[s, p, o]| [p, o, s]).collect();
+  let mut osp: Vec<[u32; 3]> = spo.iter().map(|&[s, p, o]| [o, s, p]).collect();
+  spo.sort_unstable();
+  pos.sort_unstable();
+  osp.sort_unstable();
+
+  let mut out = BufWriter::new(writer);
+  out.write_all(MAGIC)?;
+  out.write_all(&VERSION.to_le_bytes())?;
+  out.write_all(&[0, 0])?;
+  out.write_all(&(terms.len() as u64).to_le_bytes())?;
+  out.write_all(&(spo.len() as u64).to_le_bytes())?;
+  let mut offset: u64 = 0;
+  out.write_all(&offset.to_le_bytes())?;
+  for term in terms.iter() {
+    offset += term.len() as u64;
+    out.write_all(&offset.to_le_bytes())?;
+  }
+  for term in terms.iter() {
+    out.write_all(term.as_bytes())?;
+  }
+  out.write_all(&[0; 3][..(4 - offset as usize % 4) % 4])?;
+  for ordering in [spo, pos, osp].iter() {
+    for record in ordering.iter() {
+      for id in record.iter() {
+        out.write_all(&id.to_le_bytes())?;
+      }
+    }
+  }
+  out.flush()?;
+  Ok(())
+}
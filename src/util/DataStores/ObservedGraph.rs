@@ -0,0 +1,107 @@
+use std::{
+  ops::Deref,
+  sync::mpsc::Receiver,
+};
+use super::{
+  Diff::GraphDiff,
+  Graph::Graph,
+  Observers::{Changes, GraphChange, Subscribers, SubscriptionId},
+  super::Error::Error,
+};
+
+type Triple = (String, String, String);
+type QueryTriple = (Option<String>, Option<String>, Option<String>);
+
+/*
+A Graph that tells its subscriptions about every triple inserted or
+removed that matches their pattern (None matches anything), right
+after each mutation is done. A Graph on its own can't be subscribed
+to; this one only derefs to &Graph, so every change goes through the
+methods below. Those note what they change as they make it, except
+write(), which compares against a copy of the Graph (Graph::track)
+while anyone is subscribed. A write that fails and is undone, like a
+SPARQL Update, tells nobody. For a Graph shared between threads,
+SharedGraph has the same subscriptions.
+*/
+#[derive(Debug)]
+pub struct ObservedGraph {
+  graph: Graph,
+  subscribers: Subscribers,
+}
+impl ObservedGraph {
+  pub fn new() -> Self {
+    ObservedGraph::from(Graph::new())
+  }
+  pub fn into_inner(self) -> Graph {
+    self.graph
+  }
+  pub fn subscribe<F>(&mut self, pattern: QueryTriple, callback: F) -> SubscriptionId
+    where F: FnMut(&GraphChange) + Send + 'static {
+    self.subscribers.subscribe(pattern, callback)
+  }
+  /* Dropping the Receiver unsubscribes, as of the next change it would get */
+  pub fn subscribe_channel(&mut self, pattern: QueryTriple) -> (SubscriptionId, Receiver<GraphChange>) {
+    self.subscribers.subscribe_channel(pattern)
+  }
+  pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+    self.subscribers.unsubscribe(id)
+  }
+  /* Runs f, then tells subscribers the net change it made */
+  pub fn write<F, R>(&mut self, f: F) -> R
+    where F: FnOnce(&mut Graph) -> R {
+    self.write_if(|g, changes| changes.write(g, f), |_| true)
+  }
+  /* Like write, but a closure that returns Err must have left the Graph as it found it */
+  pub fn try_write<F, R, E>(&mut self, f: F) -> Result<R, E>
+    where F: FnOnce(&mut Graph) -> Result<R, E> {
+    self.write_if(|g, changes| changes.write(g, f), Result::is_ok)
+  }
+  fn write_if<F, R, C>(&mut self, f: F, changed: C) -> R
+    where F: FnOnce(&mut Graph, &mut Changes) -> R,
+          C: Fn(&R) -> bool {
+    let mut changes = Changes::new(!self.subscribers.is_empty());
+    let ret = f(&mut self.graph, &mut changes);
+    if changed(&ret) {
+      self.subscribers.notify(&changes.finish());
+    }
+    ret
+  }
+  pub fn insert(&mut self, triple: Triple) {
+    self.write_if(|g, changes| changes.insert(g, triple), |_| true);
+  }
+  pub fn remove(&mut self, triple: &Triple) {
+    self.write_if(|g, changes| changes.remove(g, triple), |_| true);
+  }
+  pub fn replace(&mut self, old_t: &Triple, new_t: Triple) {
+    self.write_if(|g, changes| changes.replace(g, old_t, new_t), |_| true);
+  }
+  pub fn extend<T>(&mut self, triples: T)
+    where T: IntoIterator<Item=Triple> {
+    self.write_if(|g, changes| changes.extend(g, triples), |_| true);
+  }
+  pub fn update(&mut self, request: &str) -> Result<(), Error> {
+    self.write_if(|g, changes| changes.update(g, request), Result::is_ok)
+  }
+  pub fn apply(&mut self, diff: &GraphDiff) -> Result<(), Error> {
+    self.write_if(|g, changes| changes.apply(g, diff), Result::is_ok)
+  }
+}
+impl Default for ObservedGraph {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl From<Graph> for ObservedGraph {
+  fn from(graph: Graph) -> Self {
+    ObservedGraph {
+      graph,
+      subscribers: Subscribers::default(),
+    }
+  }
+}
+impl Deref for ObservedGraph {
+  type Target = Graph;
+  fn deref(&self) -> &Graph {
+    &self.graph
+  }
+}
@@ -0,0 +1,217 @@
+use std::{
+  collections::{HashMap, HashSet},
+  fmt,
+  sync::mpsc,
+};
+use super::{
+  Diff::GraphDiff,
+  Graph::Graph,
+  super::{Error::Error, SPARQL::Update::Update},
+};
+
+type Triple = (String, String, String);
+type QueryTriple = (Option<String>, Option<String>, Option<String>);
+
+/* One triple that was really added to or taken out of a Graph */
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphChange {
+  Inserted(Triple),
+  Removed(Triple),
+}
+impl GraphChange {
+  pub fn triple(&self) -> &Triple {
+    match self {
+      GraphChange::Inserted(t) | GraphChange::Removed(t) => t,
+    }
+  }
+}
+
+/*
+What one write through an ObservedGraph or SharedGraph changed.
+A Graph keeps no record of its own changes, so the wrappers' methods
+make theirs through here, which notes each triple that really goes
+in or comes out. While nobody's subscribed it notes nothing, and the
+methods cost what Graph's do. Anything else a write() closure does
+is worked out afterwards with Graph::track.
+*/
+#[derive(Debug)]
+pub struct Changes(Option<Vec<GraphChange>>);
+impl Changes {
+  pub fn new(listening: bool) -> Self {
+    Changes(if listening { Some(Vec::new()) } else { None })
+  }
+  pub fn insert(&mut self, graph: &mut Graph, triple: Triple) {
+    match &mut self.0 {
+      Some(changes) if !graph.has(&triple.0, &triple.1, &triple.2) => {
+        changes.push(GraphChange::Inserted(triple.clone()));
+        graph.insert(triple);
+      },
+      _ => graph.insert(triple),
+    }
+  }
+  pub fn remove(&mut self, graph: &mut Graph, triple: &Triple) {
+    if let Some(changes) = &mut self.0 {
+      if graph.has(&triple.0, &triple.1, &triple.2) {
+        changes.push(GraphChange::Removed(triple.clone()));
+      }
+    }
+    graph.remove(triple);
+  }
+  pub fn replace(&mut self, graph: &mut Graph, old_t: &Triple, new_t: Triple) {
+    self.remove(graph, old_t);
+    self.insert(graph, new_t);
+  }
+  pub fn extend<T>(&mut self, graph: &mut Graph, triples: T)
+    where T: IntoIterator<Item=Triple> {
+    for triple in triples {
+      self.insert(graph, triple);
+    }
+  }
+  /* Update already journals what it changes, to roll back with */
+  pub fn update(&mut self, graph: &mut Graph, request: &str) -> Result<(), Error> {
+    let journal = Update::parse(request)?.execute_journaled(graph)?;
+    if let Some(changes) = &mut self.0 {
+      changes.extend(journal);
+    }
+    Ok(())
+  }
+  /* An applied diff changes exactly what it says */
+  pub fn apply(&mut self, graph: &mut Graph, diff: &GraphDiff) -> Result<(), Error> {
+    graph.apply(diff)?;
+    if let Some(changes) = &mut self.0 {
+      changes.extend(diff.removed.iter().cloned().map(GraphChange::Removed));
+      changes.extend(diff.added.iter().cloned().map(GraphChange::Inserted));
+    }
+    Ok(())
+  }
+  pub fn write<F, R>(&mut self, graph: &mut Graph, f: F) -> R
+    where F: FnOnce(&mut Graph) -> R {
+    match &mut self.0 {
+      Some(changes) => {
+        let (ret, tracked) = graph.track(f);
+        changes.extend(tracked);
+        ret
+      },
+      None => f(graph),
+    }
+  }
+  /* The net change */
+  pub fn finish(self) -> Vec<GraphChange> {
+    self.0.map_or_else(Vec::new, net)
+  }
+}
+/*
+Only effective changes are recorded, so a triple's changes alternate
+between Inserted and Removed; an even number of them cancel out
+*/
+fn net(changes: Vec<GraphChange>) -> Vec<GraphChange> {
+  if changes.len() < 2 {
+    return changes
+  }
+  let mut counts: HashMap<&Triple, usize> = HashMap::new();
+  for change in changes.iter() {
+    *counts.entry(change.triple()).or_default() += 1;
+  }
+  let mut seen: HashMap<&Triple, usize> = HashMap::new();
+  let mut kept = Vec::with_capacity(changes.len());
+  for change in changes.iter() {
+    let n = seen.entry(change.triple()).or_default();
+    *n += 1;
+    /* An odd count keeps the last change, which says where the triple ended up */
+    let count = counts[change.triple()];
+    kept.push(count % 2 == 1 && *n == count);
+  }
+  changes.into_iter().zip(kept).filter(|(_, keep)| *keep).map(|(change, _)| change).collect()
+}
+
+/* Handed back by subscribe, to unsubscribe with */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+/* The subscriptions of a SharedGraph or an ObservedGraph */
+#[derive(Default)]
+pub struct Subscribers {
+  next: u64,
+  subscribers: Vec<Subscriber>,
+  /* Lent out to be told about changes, and not unsubscribed since */
+  lent: HashSet<SubscriptionId>,
+}
+pub struct Subscriber {
+  id: SubscriptionId,
+  pattern: QueryTriple,
+  /* Returns false once it wants no more, e.g. its channel has closed */
+  notify: Box<dyn FnMut(&GraphChange) -> bool + Send>,
+}
+impl Subscribers {
+  pub fn is_empty(&self) -> bool {
+    self.subscribers.is_empty() && self.lent.is_empty()
+  }
+  pub fn subscribe<F>(&mut self, pattern: QueryTriple, mut callback: F) -> SubscriptionId
+    where F: FnMut(&GraphChange) + Send + 'static {
+    self.add(pattern, Box::new(move |change| {
+      callback(change);
+      true
+    }))
+  }
+  pub fn subscribe_channel(&mut self, pattern: QueryTriple) -> (SubscriptionId, mpsc::Receiver<GraphChange>) {
+    let (sender, receiver) = mpsc::channel();
+    let id = self.add(pattern, Box::new(move |change| sender.send(change.clone()).is_ok()));
+    (id, receiver)
+  }
+  fn add(&mut self, pattern: QueryTriple, notify: Box<dyn FnMut(&GraphChange) -> bool + Send>) -> SubscriptionId {
+    let id = SubscriptionId(self.next);
+    self.next += 1;
+    self.subscribers.push(Subscriber { id, pattern, notify });
+    id
+  }
+  pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+    let before = self.subscribers.len();
+    self.subscribers.retain(|s| s.id != id);
+    self.lent.remove(&id) || self.subscribers.len() != before
+  }
+  pub fn notify(&mut self, changes: &[GraphChange]) {
+    if changes.is_empty() {
+      return
+    }
+    let mut lent = self.lend(changes);
+    Subscribers::tell(&mut lent, changes);
+    self.give_back(lent);
+  }
+  /*
+    Takes out the subscribers with a pattern some of changes match,
+    so they can be told without holding whatever guards these. They
+    still count as subscribed, and can be unsubscribed meanwhile
+  */
+  pub fn lend(&mut self, changes: &[GraphChange]) -> Vec<Subscriber> {
+    let (lent, kept) = self.subscribers.drain(..)
+                                       .partition(|s| changes.iter().any(|c| matches(&s.pattern, c.triple())));
+    self.subscribers = kept;
+    self.lent.extend(lent.iter().map(|s: &Subscriber| s.id));
+    lent
+  }
+  /* Drops any that want no more, e.g. their channel has closed */
+  pub fn tell(lent: &mut Vec<Subscriber>, changes: &[GraphChange]) {
+    lent.retain_mut(|Subscriber { pattern, notify, .. }| {
+      changes.iter()
+             .filter(|change| matches(pattern, change.triple()))
+             .all(notify)
+    });
+  }
+  /* Puts back what lend took out, bar any unsubscribed in the meantime */
+  pub fn give_back(&mut self, lent: Vec<Subscriber>) {
+    let lent: Vec<Subscriber> = lent.into_iter().filter(|s| self.lent.contains(&s.id)).collect();
+    self.lent.clear();
+    self.subscribers.extend(lent);
+    self.subscribers.sort_by_key(|s| s.id.0);
+  }
+}
+impl fmt::Debug for Subscribers {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Subscribers({})", self.subscribers.len())
+  }
+}
+fn matches((s, p, o): &QueryTriple, (ts, tp, to): &Triple) -> bool {
+  s.as_ref().is_none_or(|s| s == ts)
+    && p.as_ref().is_none_or(|p| p == tp)
+    && o.as_ref().is_none_or(|o| o == to)
+}
@@ -0,0 +1,35 @@
+use std::fmt::Debug;
+use super::Graph::Graph;
+
+type Triple = (String, String, String);
+type QueryTriple = (Option<String>, Option<String>, Option<String>);
+
+/*
+Anything triple patterns can be matched against, so queries
+can run over a Graph, a GraphSnapshot or a MappedGraph alike.
+None in a QueryTriple matches any term.
+*/
+pub trait PatternMatch: Debug {
+  fn get_triple(&self, qt: &QueryTriple) -> Vec<Triple>;
+  fn contains_pattern(&self, qt: &QueryTriple) -> bool {
+    !self.get_triple(qt).is_empty()
+  }
+  /* Every triple with resource as its subject or object */
+  fn describe(&self, resource: &str) -> Graph {
+    let mut g = Graph::new();
+    g.extend(self.get_triple(&(Some(resource.to_string()), None, None)));
+    g.extend(self.get_triple(&(None, None, Some(resource.to_string()))));
+    g
+  }
+}
+impl PatternMatch for Graph {
+  fn get_triple(&self, qt: &QueryTriple) -> Vec<Triple> {
+    Graph::get_triple(self, qt)
+  }
+  fn contains_pattern(&self, qt: &QueryTriple) -> bool {
+    Graph::contains_pattern(self, qt)
+  }
+  fn describe(&self, resource: &str) -> Graph {
+    Graph::describe(self, resource)
+  }
+}
@@ -0,0 +1,339 @@
+use std::{
+  collections::VecDeque,
+  mem,
+  ops::{Deref, DerefMut},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Receiver,
+    Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockWriteGuard,
+  },
+  thread,
+};
+use super::{
+  Diff::GraphDiff,
+  Graph::Graph,
+  MappedGraph::MappedGraph,
+  Observers::{Changes, GraphChange, Subscriber, Subscribers, SubscriptionId},
+  PatternMatch::PatternMatch,
+  super::Error::Error,
+};
+
+type Triple = (String, String, String);
+type QueryTriple = (Option<String>, Option<String>, Option<String>);
+
+/*
+A cloneable handle to one Graph that can be handed to
+as many threads as needed.
+Readers take a snapshot(), an immutable view of the Graph as
+it was at that moment, and can keep it for as long as they like.
+Writers queue up and apply their changes one at a time, copying
+the Graph first only if a snapshot of the current version is
+still alive; so a query never sees a mutation half-way through
+being applied to spo, pos and osp, nor any that came after it.
+A write that panics part way may leave the Graph half changed,
+so every later use of the SharedGraph panics too, as a poisoned
+Mutex would; snapshots taken before it are unaffected.
+*/
+#[derive(Clone, Debug)]
+pub struct SharedGraph {
+  head: Arc<RwLock<Version>>,
+  subscribers: Arc<Mutex<Subscribers>>,
+  /* Whether anyone is subscribed, read under the write lock in place of subscribers */
+  listening: Arc<AtomicBool>,
+  deliveries: Arc<Mutex<Deliveries>>,
+}
+/* Changes from each write in the order they landed, waiting to be told */
+#[derive(Debug, Default)]
+struct Deliveries {
+  queue: VecDeque<Vec<GraphChange>>,
+  /* A thread is already working through the queue */
+  busy: bool,
+}
+#[derive(Debug)]
+struct Version {
+  graph: Arc<Graph>,
+  number: u64,
+  /* graph sorted by maintenance, until the next write */
+  sorted: Option<Arc<MappedGraph>>,
+}
+impl Version {
+  /* A new version, which no sorted copy describes yet */
+  fn bump(&mut self) {
+    self.number += 1;
+    self.sorted = None;
+  }
+}
+impl SharedGraph {
+  pub fn new() -> Self {
+    SharedGraph::from(Graph::new())
+  }
+  pub fn snapshot(&self) -> GraphSnapshot {
+    let head = self.head.read().unwrap_or_else(poisoned);
+    GraphSnapshot {
+      graph: Arc::clone(&head.graph),
+      version: head.number,
+      sorted: head.sorted.clone(),
+    }
+  }
+  pub fn version(&self) -> u64 {
+    self.head.read().unwrap_or_else(poisoned).number
+  }
+  fn lock(&self) -> RwLockWriteGuard<'_, Version> {
+    self.head.write().unwrap_or_else(poisoned)
+  }
+  /* Maintenance only: replaces the Graph without bumping the version */
+  pub(super) fn swap_if_current(&self, version: u64, graph: Graph) -> bool {
+    let mut head = self.lock();
+    if head.number != version {
+      return false
+    }
+    head.graph = Arc::new(graph);
+    true
+  }
+  /* Maintenance only: hands out sorted for snapshots of version, if it's still current */
+  pub(super) fn sort_if_current(&self, version: u64, sorted: MappedGraph) -> bool {
+    let mut head = self.lock();
+    if head.number != version {
+      return false
+    }
+    head.sorted = Some(Arc::new(sorted));
+    true
+  }
+  pub(super) fn compact_locked(&self) -> super::Maintenance::CompactionStats {
+    let mut head = self.lock();
+    Arc::make_mut(&mut head.graph).compact()
+  }
+  pub fn write<F, R>(&self, f: F) -> R
+    where F: FnOnce(&mut Graph) -> R {
+    self.write_if(|g, changes| changes.write(g, f), |_| true)
+  }
+  /* Runs f under the write lock, making a new version if changed(&ret) */
+  fn write_if<F, R, C>(&self, f: F, changed: C) -> R
+    where F: FnOnce(&mut Graph, &mut Changes) -> R,
+          C: Fn(&R) -> bool {
+    let mut head = self.lock();
+    /* Copy-on-write: only clones if a snapshot still points here */
+    let graph = Arc::make_mut(&mut head.graph);
+    let listening = self.listening.load(Ordering::SeqCst);
+    let mut changes = Changes::new(listening);
+    let ret = f(graph, &mut changes);
+    let changed = changed(&ret);
+    if changed {
+      head.bump();
+    }
+    if !listening {
+      return ret
+    }
+    /* Queued before the write lock is let go, so subscribers hear
+         about writes in the order they landed; told after, so they
+         can read the new version without waiting on this write */
+    let changes = changes.finish();
+    if changed && !changes.is_empty() {
+      self.deliveries().queue.push_back(changes);
+    }
+    drop(head);
+    self.deliver();
+    ret
+  }
+  /*
+    Tells subscribers about queued changes, unless another thread
+    already is, in which case that one gets to these too. The
+    subscribers being told are lent out of their lock, so no lock
+    is held while callbacks run
+  */
+  fn deliver(&self) {
+    {
+      let mut deliveries = self.deliveries();
+      if deliveries.busy {
+        return
+      }
+      deliveries.busy = true;
+    }
+    let _busy = Busy(&self.deliveries);
+    loop {
+      let changes = {
+        let mut deliveries = self.deliveries();
+        match deliveries.queue.pop_front() {
+          Some(changes) => changes,
+          None => {
+            deliveries.busy = false;
+            return
+          },
+        }
+      };
+      let mut lent = Lent {
+        shared: self,
+        subscribers: self.subscribers().lend(&changes),
+      };
+      Subscribers::tell(&mut lent.subscribers, &changes);
+    }
+  }
+  fn deliveries(&self) -> MutexGuard<'_, Deliveries> {
+    self.deliveries.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+  pub fn insert(&self, triple: Triple) {
+    self.write_if(|g, changes| changes.insert(g, triple), |_| true);
+  }
+  pub fn remove(&self, triple: &Triple) {
+    self.write_if(|g, changes| changes.remove(g, triple), |_| true);
+  }
+  pub fn replace(&self, old_t: &Triple, new_t: Triple) {
+    self.write_if(|g, changes| changes.replace(g, old_t, new_t), |_| true);
+  }
+  /*
+    Like write, but a closure that returns Err must have left the
+    Graph as it found it, and the version isn't bumped
+  */
+  pub fn try_write<F, R, E>(&self, f: F) -> Result<R, E>
+    where F: FnOnce(&mut Graph) -> Result<R, E> {
+    self.write_if(|g, changes| changes.write(g, f), Result::is_ok)
+  }
+  pub fn update(&self, request: &str) -> Result<(), Error> {
+    self.write_if(|g, changes| changes.update(g, request), Result::is_ok)
+  }
+}
+/*
+Subscriptions, told about each triple inserted or removed that matches
+their pattern (None matches anything) once a write is done. A write
+that fails part way and is undone, like a SPARQL Update, tells them
+nothing. Callbacks run one at a time, in the order the writes landed,
+on whichever writing thread is delivering at the time, so a write may
+return before its own changes have been told. They may take snapshots,
+write, subscribe and unsubscribe; a subscription made in a callback
+hears about writes from the next batch on. Only changes made through
+the SharedGraph are told: a Graph can't be subscribed to by itself.
+*/
+impl SharedGraph {
+  fn subscribers(&self) -> SubscribersGuard<'_> {
+    SubscribersGuard {
+      subscribers: self.subscribers.lock().unwrap_or_else(PoisonError::into_inner),
+      listening: &self.listening,
+    }
+  }
+  pub fn subscribe<F>(&self, pattern: QueryTriple, callback: F) -> SubscriptionId
+    where F: FnMut(&GraphChange) + Send + 'static {
+    self.subscribers().subscribe(pattern, callback)
+  }
+  /* Dropping the Receiver unsubscribes, as of the next change it would get */
+  pub fn subscribe_channel(&self, pattern: QueryTriple) -> (SubscriptionId, Receiver<GraphChange>) {
+    self.subscribers().subscribe_channel(pattern)
+  }
+  pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+    self.subscribers().unsubscribe(id)
+  }
+  /* Applied under one write, so no snapshot sees half a diff */
+  pub fn apply(&self, diff: &GraphDiff) -> Result<(), Error> {
+    self.write_if(|g, changes| changes.apply(g, diff), Result::is_ok)
+  }
+}
+/* Keeps listening in step with subscribers whenever they're let go */
+struct SubscribersGuard<'a> {
+  subscribers: MutexGuard<'a, Subscribers>,
+  listening: &'a AtomicBool,
+}
+impl Deref for SubscribersGuard<'_> {
+  type Target = Subscribers;
+  fn deref(&self) -> &Subscribers {
+    &self.subscribers
+  }
+}
+impl DerefMut for SubscribersGuard<'_> {
+  fn deref_mut(&mut self) -> &mut Subscribers {
+    &mut self.subscribers
+  }
+}
+impl Drop for SubscribersGuard<'_> {
+  fn drop(&mut self) {
+    self.listening.store(!self.subscribers.is_empty(), Ordering::SeqCst);
+  }
+}
+/* Subscribers out being told, put back when done or if a callback panics */
+struct Lent<'a> {
+  shared: &'a SharedGraph,
+  subscribers: Vec<Subscriber>,
+}
+impl Drop for Lent<'_> {
+  fn drop(&mut self) {
+    let lent = mem::take(&mut self.subscribers);
+    self.shared.subscribers().give_back(lent);
+  }
+}
+/* Lets another thread deliver if a callback panics part way */
+struct Busy<'a>(&'a Mutex<Deliveries>);
+impl Drop for Busy<'_> {
+  fn drop(&mut self) {
+    if thread::panicking() {
+      self.0.lock().unwrap_or_else(PoisonError::into_inner).busy = false;
+    }
+  }
+}
+fn poisoned<T>(_: PoisonError<T>) -> T {
+  panic!("A write to this SharedGraph panicked, so its Graph may be half changed")
+}
+impl Default for SharedGraph {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl From<Graph> for SharedGraph {
+  fn from(graph: Graph) -> Self {
+    SharedGraph {
+      head: Arc::new(RwLock::new(Version {
+        graph: Arc::new(graph),
+        number: 0,
+        sorted: None,
+      })),
+      subscribers: Arc::new(Mutex::new(Subscribers::default())),
+      listening: Arc::new(AtomicBool::new(false)),
+      deliveries: Arc::new(Mutex::new(Deliveries::default())),
+    }
+  }
+}
+
+/*
+A point-in-time, read-only view of a SharedGraph.
+Derefs to Graph, so it can be queried, iterated or
+exported just like one.
+*/
+#[derive(Clone, Debug)]
+pub struct GraphSnapshot {
+  graph: Arc<Graph>,
+  version: u64,
+  sorted: Option<Arc<MappedGraph>>,
+}
+impl GraphSnapshot {
+  pub fn version(&self) -> u64 {
+    self.version
+  }
+  /* Whether maintenance had sorted this version when it was taken */
+  pub fn is_sorted(&self) -> bool {
+    self.sorted.is_some()
+  }
+  /*
+    Scans read contiguous sorted records when maintenance has sorted
+    this version; single lookups stay on the hash indexes either way
+  */
+  pub fn get_triple(&self, qt: &QueryTriple) -> Vec<Triple> {
+    match &self.sorted {
+      Some(sorted) => sorted.get_triple(qt),
+      None => self.graph.get_triple(qt),
+    }
+  }
+}
+impl Deref for GraphSnapshot {
+  type Target = Graph;
+  fn deref(&self) -> &Graph {
+    &self.graph
+  }
+}
+impl PatternMatch for GraphSnapshot {
+  fn get_triple(&self, qt: &(Option<String>, Option<String>, Option<String>)) -> Vec<Triple> {
+    GraphSnapshot::get_triple(self, qt)
+  }
+  fn contains_pattern(&self, qt: &(Option<String>, Option<String>, Option<String>)) -> bool {
+    self.graph.contains_pattern(qt)
+  }
+  fn describe(&self, resource: &str) -> Graph {
+    self.graph.describe(resource)
+  }
+}
@@ -1,6 +1,13 @@
 
-use std::collections::{HashMap, HashSet};
-use serde::{Serialize, Deserialize};
+use std::{
+  collections::{HashMap, HashSet},
+  io::Read,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use super::{
+  Maintenance::CompactionStats,
+  super::{Error::Error, Formats::JsonStream},
+};
 
 type Triple = (String, String, String);
 type QueryTriple = (Option<String>, Option<String>, Option<String>);
@@ -9,8 +16,22 @@ type Double = (String, String);
 type QueryDouble = (Option<String>, Option<String>);
 
 /* TripleStore */
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct TripleStore(pub HashMap<String, Box<HashMap<String, Box<HashSet<String>>>>>);
+/*
+The iterators expect every branch to lead to a tail, so empty
+ones in a document, like {"s": {"p": []}}, are dropped on the way in
+*/
+impl<'de> Deserialize<'de> for TripleStore {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let mut heads: HashMap<String, Box<HashMap<String, Box<HashSet<String>>>>> = HashMap::deserialize(deserializer)?;
+    heads.retain(|_, mids| {
+      mids.retain(|_, tails| !tails.is_empty());
+      !mids.is_empty()
+    });
+    Ok(TripleStore(heads))
+  }
+}
 impl TripleStore {
   pub fn new() -> Self {
     TripleStore(HashMap::new())
@@ -143,6 +164,30 @@ impl TripleStore {
     };
     ret_v
   }
+  pub fn contains(&self, qt: &QueryTriple) -> bool {
+    /* Same shapes as get_triple, but stops at the first match */
+    let heads = &self.0;
+    match qt {
+      (Some(h), Some(m), Some(t)) => {
+        match heads.get(h) {
+          Some(mids) => match mids.get(m) {
+            Some(tails) => tails.contains(t),
+            None => false,
+          },
+          None => false,
+        }
+      },
+      (Some(h), Some(m), None) => {
+        match heads.get(h) {
+          Some(mids) => mids.contains_key(m),
+          None => false,
+        }
+      },
+      (Some(h), None, None) => heads.contains_key(h),
+      (None, None, None) => !heads.is_empty(),
+      _ => false,
+    }
+  }
   pub fn get_double(&self, qd: &QueryDouble) -> Vec<Double> {
     let mut ret_v: Vec<Double> = Vec::new();
     let heads = &self.0;
@@ -211,18 +256,216 @@ impl TripleStore {
     }
   }
 }
+/* Bulk loading */
+impl TripleStore {
+  /* Like insert, but only allocates Strings for new branches */
+  pub fn insert_ref(&mut self, (h, m, t): (&str, &str, &str)) {
+    let heads = &mut self.0;
+    if let Some(mids) = heads.get_mut(h) {
+      if let Some(tails) = mids.get_mut(m) {
+        if !tails.contains(t) {
+          tails.insert(t.to_string());
+        }
+        return
+      }
+      let mut tails = HashSet::with_capacity(1);
+      tails.insert(t.to_string());
+      mids.insert(m.to_string(), Box::new(tails));
+      return
+    }
+    let mut tails = HashSet::with_capacity(1);
+    tails.insert(t.to_string());
+    let mut mids = HashMap::with_capacity(1);
+    mids.insert(m.to_string(), Box::new(tails));
+    heads.insert(h.to_string(), Box::new(mids));
+  }
+}
+/* Set Algebra */
+impl TripleStore {
+  /*
+    These work a level at a time, so a head or mid only one side has
+    is cloned or dropped as a whole branch, and only tails both sides
+    share are compared one by one. No branch is left empty.
+  */
+  pub fn union(&self, other: &TripleStore) -> TripleStore {
+    let (larger, smaller) = if self.0.len() >= other.0.len() { (self, other) } else { (other, self) };
+    let mut union = larger.clone();
+    union.union_with(smaller);
+    union
+  }
+  pub fn intersection(&self, other: &TripleStore) -> TripleStore {
+    let (smaller, larger) = if self.0.len() <= other.0.len() { (self, other) } else { (other, self) };
+    let mut heads = HashMap::new();
+    for (h, mids) in smaller.0.iter() {
+      let other_mids = match larger.0.get(h) {
+        Some(other_mids) => other_mids,
+        None => continue,
+      };
+      let mut shared = HashMap::new();
+      for (m, tails) in mids.iter() {
+        if let Some(other_tails) = other_mids.get(m) {
+          let both: HashSet<String> = tails.intersection(other_tails).cloned().collect();
+          if !both.is_empty() {
+            shared.insert(m.clone(), Box::new(both));
+          }
+        }
+      }
+      if !shared.is_empty() {
+        heads.insert(h.clone(), Box::new(shared));
+      }
+    }
+    TripleStore(heads)
+  }
+  pub fn difference(&self, other: &TripleStore) -> TripleStore {
+    let mut heads = HashMap::new();
+    for (h, mids) in self.0.iter() {
+      let other_mids = match other.0.get(h) {
+        Some(other_mids) => other_mids,
+        None => {
+          heads.insert(h.clone(), mids.clone());
+          continue
+        },
+      };
+      let mut left = HashMap::new();
+      for (m, tails) in mids.iter() {
+        let tails = match other_mids.get(m) {
+          Some(other_tails) => Box::new(tails.difference(other_tails).cloned().collect()),
+          None => tails.clone(),
+        };
+        if !tails.is_empty() {
+          left.insert(m.clone(), tails);
+        }
+      }
+      if !left.is_empty() {
+        heads.insert(h.clone(), Box::new(left));
+      }
+    }
+    TripleStore(heads)
+  }
+  pub fn union_with(&mut self, other: &TripleStore) {
+    for (h, other_mids) in other.0.iter().filter(|(_, mids)| !mids.is_empty()) {
+      let mids = match self.0.get_mut(h) {
+        Some(mids) => mids,
+        None => {
+          self.0.insert(h.clone(), other_mids.clone());
+          continue
+        },
+      };
+      for (m, other_tails) in other_mids.iter().filter(|(_, tails)| !tails.is_empty()) {
+        match mids.get_mut(m) {
+          Some(tails) => tails.extend(other_tails.iter().cloned()),
+          None => { mids.insert(m.clone(), other_tails.clone()); },
+        }
+      }
+    }
+  }
+  pub fn intersect_with(&mut self, other: &TripleStore) {
+    self.0.retain(|h, mids| {
+      let other_mids = match other.0.get(h) {
+        Some(other_mids) => other_mids,
+        None => return false,
+      };
+      mids.retain(|m, tails| {
+        let other_tails = match other_mids.get(m) {
+          Some(other_tails) => other_tails,
+          None => return false,
+        };
+        tails.retain(|t| other_tails.contains(t));
+        !tails.is_empty()
+      });
+      !mids.is_empty()
+    });
+  }
+  pub fn difference_with(&mut self, other: &TripleStore) {
+    self.0.retain(|h, mids| {
+      let other_mids = match other.0.get(h) {
+        Some(other_mids) => other_mids,
+        None => return true,
+      };
+      mids.retain(|m, tails| {
+        let other_tails = match other_mids.get(m) {
+          Some(other_tails) => other_tails,
+          None => return true,
+        };
+        /* Walk whichever side is shorter */
+        if other_tails.len() < tails.len() {
+          for t in other_tails.iter() {
+            tails.remove(t);
+          }
+        }
+        else {
+          tails.retain(|t| !other_tails.contains(t));
+        }
+        !tails.is_empty()
+      });
+      !mids.is_empty()
+    });
+  }
+}
+/* Compaction */
+impl TripleStore {
+  /*
+    Gives back the spare capacity HashMaps/HashSets hold on to
+    after shrinking. Every level stays a hash level; the sorted
+    form is a MappedGraph, which SharedGraph maintenance builds.
+  */
+  pub fn compact(&mut self) -> CompactionStats {
+    let mut stats = CompactionStats::default();
+    let heads = &mut self.0;
+    for mids in heads.values_mut() {
+      for tails in mids.values_mut() {
+        let before = tails.capacity();
+        tails.shrink_to_fit();
+        stats.reclaimed(before - tails.capacity(), LEAF);
+      }
+      let before = mids.capacity();
+      mids.shrink_to_fit();
+      stats.reclaimed(before - mids.capacity(), BRANCH);
+    }
+    let before = heads.capacity();
+    heads.shrink_to_fit();
+    stats.reclaimed(before - heads.capacity(), BRANCH);
+    stats
+  }
+  /*
+    What compact() would give back, worked out without changing
+    or copying anything, so callers can skip a pass with nothing to do
+  */
+  pub fn reclaimable(&self) -> CompactionStats {
+    /* The capacity shrink_to_fit settles on depends only on the length and slot type */
+    let mut fitted_leaves: HashMap<usize, usize> = HashMap::new();
+    let mut fitted_branches: HashMap<usize, usize> = HashMap::new();
+    let mut leaf = |len: usize| *fitted_leaves.entry(len)
+                                              .or_insert_with(|| HashSet::<String>::with_capacity(len).capacity());
+    let mut branch = |len: usize| *fitted_branches.entry(len)
+                                                  .or_insert_with(|| HashMap::<String, Box<()>>::with_capacity(len).capacity());
+    let mut stats = CompactionStats::default();
+    let heads = &self.0;
+    for mids in heads.values() {
+      for tails in mids.values() {
+        stats.reclaimed(tails.capacity().saturating_sub(leaf(tails.len())), LEAF);
+      }
+      stats.reclaimed(mids.capacity().saturating_sub(branch(mids.len())), BRANCH);
+    }
+    stats.reclaimed(heads.capacity().saturating_sub(branch(heads.len())), BRANCH);
+    stats
+  }
+}
+/* Rough size of a slot, not counting the Strings it points to */
+const BRANCH: usize = std::mem::size_of::<String>() + std::mem::size_of::<Box<()>>();
+const LEAF: usize = std::mem::size_of::<String>();
 /* Json Interface */
 impl TripleStore {
-  pub fn json(&self) -> String {
-    serde_json::to_string(self).unwrap()
+  pub fn json(&self) -> Result<String, Error> {
+    Ok(serde_json::to_string(self)?)
   }
-  pub fn into_json(self) -> String {
-    serde_json::to_string(&self).unwrap()
+  pub fn into_json(self) -> Result<String, Error> {
+    Ok(serde_json::to_string(&self)?)
   }
-  pub fn from_json(data: &str) -> Result<Self, serde_json::error::Error> {
-    serde_json::from_str(data)
+  pub fn from_json(data: &str) -> Result<Self, Error> {
+    Ok(serde_json::from_str(data)?)
   }
-  pub fn insert_json<'a, T>(&mut self, data: &'a str) -> Result<(), serde_json::error::Error>
+  pub fn insert_json<'a, T>(&mut self, data: &'a str) -> Result<(), Error>
     where T: serde::Deserialize<'a>
            + IntoIterator<Item=Triple> {
     let triples: T = serde_json::from_str(&data)?;
@@ -231,7 +474,7 @@ impl TripleStore {
     }
     Ok(())
   }
-  pub fn remove_json<'a, T>(&mut self, data: &'a str) -> Result<(), serde_json::error::Error>
+  pub fn remove_json<'a, T>(&mut self, data: &'a str) -> Result<(), Error>
     where T: serde::Deserialize<'a>
            + IntoIterator<Item=Triple> {
     let triples: T = serde_json::from_str(&data)?;
@@ -240,6 +483,19 @@ impl TripleStore {
     }
     Ok(())
   }
+  /*
+    Streamed from reader one triple at a time, from the shape json()
+    writes or an array of [s, p, o] arrays and/or {"s", "p", "o"} objects
+  */
+  pub fn read_json<R: Read>(reader: R) -> Result<Self, Error> {
+    let mut store = TripleStore::new();
+    store.insert_json_stream(reader)?;
+    Ok(store)
+  }
+  /* Returns how many triples were read; those before any error stay inserted */
+  pub fn insert_json_stream<R: Read>(&mut self, reader: R) -> Result<usize, Error> {
+    JsonStream::read(reader, |t| self.insert(t))
+  }
 }
 /* Shift implementation */
 impl TripleStore {
@@ -318,6 +574,7 @@ pub struct TripleStoreIterator {
 }
 impl<'a> Iterator for TripleStoreIterator {
   type Item = (String, String, String);
+  #[allow(semicolon_in_expressions_from_macros)]
   fn next(&mut self) -> Option<Self::Item> {
 
     /* Remove redundant code */
@@ -408,6 +665,7 @@ pub struct TripleStoreRefIterator<'a> {
 }
 impl<'a> Iterator for TripleStoreRefIterator<'a> {
   type Item = (String, String, String);
+  #[allow(semicolon_in_expressions_from_macros)]
   fn next(&mut self) -> Option<Self::Item> {
 
     /* Remove redundant code */
@@ -1,4 +1,14 @@
 #![allow(non_snake_case)]
 
+pub mod BlankNodes;
+pub mod Canonical;
+pub mod Dataset;
+pub mod Diff;
 pub mod Graph;
+pub mod Maintenance;
+pub mod MappedGraph;
+pub mod ObservedGraph;
+pub mod Observers;
+pub mod PatternMatch;
+pub mod SharedGraph;
 pub mod TripleStore;
\ No newline at end of file
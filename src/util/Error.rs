@@ -0,0 +1,58 @@
+use std::fmt;
+
+/*
+Every fallible operation in Oji reports one of these
+instead of panicking.
+*/
+#[derive(Debug)]
+pub enum Error {
+  /* Input text or documents that couldn't be read */
+  Parse(String),
+  /* Queries that don't make sense, e.g. undeclared variables */
+  Query(String),
+  Io(std::io::Error),
+  /* Data that couldn't be converted to or from another representation */
+  Serialization(String),
+  /* Requests the Graph's current state can't satisfy */
+  Constraint(String),
+}
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Parse(msg)         => write!(f, "Parse error: {}", msg),
+      Error::Query(msg)         => write!(f, "Invalid query: {}", msg),
+      Error::Io(e)              => write!(f, "IO error: {}", e),
+      Error::Serialization(msg) => write!(f, "Serialization error: {}", msg),
+      Error::Constraint(msg)    => write!(f, "Constraint violated: {}", msg),
+    }
+  }
+}
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+impl From<std::io::Error> for Error {
+  fn from(e: std::io::Error) -> Self {
+    Error::Io(e)
+  }
+}
+impl From<serde_json::error::Error> for Error {
+  fn from(e: serde_json::error::Error) -> Self {
+    use serde_json::error::Category;
+    match e.classify() {
+      Category::Io => Error::Io(e.into()),
+      Category::Syntax
+      | Category::Eof => Error::Parse(e.to_string()),
+      Category::Data => Error::Serialization(e.to_string()),
+    }
+  }
+}
+impl serde::de::Error for Error {
+  fn custom<T: fmt::Display>(msg: T) -> Self {
+    Error::Serialization(msg.to_string())
+  }
+}
@@ -0,0 +1,360 @@
+#![allow(clippy::missing_safety_doc)]
+use std::{
+  cell::RefCell,
+  ffi::{CStr, CString},
+  os::raw::{c_char, c_int, c_void},
+  panic::{self, AssertUnwindSafe},
+  ptr,
+};
+use super::{
+  DataStores::Graph::Graph,
+  Error::Error,
+  SPARQL::Query::Query,
+};
+
+type Triple = (String, String, String);
+
+/*
+The C API. include/oji.h is generated from this file by cbindgen
+(see cbindgen.toml), so the /// docs on each export are the pointer
+and lifetime contracts C callers read; keep them there.
+Every function that can fail returns an OjiStatus and leaves a
+message for oji_last_error() on the calling thread. No export lets
+a panic unwind into C: it's caught and reported as OJI_STATUS_PANIC,
+or as the NULL/0/false a function returns on failure.
+*/
+pub struct OjiGraph {
+  graph: Graph,
+}
+/* A query's results, copied out so they outlive the Graph borrow */
+pub struct OjiCursor {
+  rows: Vec<Vec<(CString, CString)>>,
+  next: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OjiStatus {
+  Ok = 0,
+  NullArgument = 1,
+  /// Not UTF-8, or a term containing a NUL byte
+  InvalidString = 2,
+  ParseError = 3,
+  QueryError = 4,
+  IoError = 5,
+  SerializationError = 6,
+  ConstraintError = 7,
+  /// A bug in Oji; a graph it was changing may be left half-changed
+  Panic = 8,
+}
+impl From<&Error> for OjiStatus {
+  fn from(e: &Error) -> Self {
+    match e {
+      Error::Parse(_)         => OjiStatus::ParseError,
+      Error::Query(_)         => OjiStatus::QueryError,
+      Error::Io(_)            => OjiStatus::IoError,
+      Error::Serialization(_) => OjiStatus::SerializationError,
+      Error::Constraint(_)    => OjiStatus::ConstraintError,
+    }
+  }
+}
+/// Called once per matching triple; the strings are only valid for the
+/// duration of the call. Returning non-zero stops the iteration.
+pub type OjiTripleCallback = Option<unsafe extern "C" fn(s: *const c_char,
+                                                         p: *const c_char,
+                                                         o: *const c_char,
+                                                         user_data: *mut c_void) -> c_int>;
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+fn fail(status: OjiStatus, msg: String) -> OjiStatus {
+  let msg = CString::new(msg.replace('\0', "\\0")).unwrap_or_default();
+  LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+  status
+}
+fn failed(e: Error) -> OjiStatus {
+  fail(OjiStatus::from(&e), e.to_string())
+}
+/* Runs an export's body, handing back on_panic rather than unwinding into C */
+pub(crate) fn guarded<R, F>(on_panic: R, body: F) -> R
+  where F: FnOnce() -> R {
+  match panic::catch_unwind(AssertUnwindSafe(body)) {
+    Ok(ret) => ret,
+    Err(payload) => {
+      let msg = payload.downcast_ref::<&str>().map(|m| m.to_string())
+                       .or_else(|| payload.downcast_ref::<String>().cloned())
+                       .unwrap_or_else(|| "unknown cause".to_string());
+      fail(OjiStatus::Panic, format!("Oji panicked: {}", msg));
+      on_panic
+    }
+  }
+}
+macro_rules! try_ffi {
+  ($e:expr) => {
+    match $e {
+      Ok(v) => v,
+      Err(status) => return status,
+    }
+  }
+}
+unsafe fn string_arg(s: *const c_char, name: &str) -> Result<String, OjiStatus> {
+  if s.is_null() {
+    return Err(fail(OjiStatus::NullArgument, format!("{} is NULL", name)))
+  }
+  match CStr::from_ptr(s).to_str() {
+    Ok(s) => Ok(s.to_string()),
+    Err(_) => Err(fail(OjiStatus::InvalidString, format!("{} is not valid UTF-8", name))),
+  }
+}
+/* NULL means "anything" in a pattern */
+unsafe fn pattern_arg(s: *const c_char, name: &str) -> Result<Option<String>, OjiStatus> {
+  if s.is_null() {
+    return Ok(None)
+  }
+  string_arg(s, name).map(Some)
+}
+unsafe fn triple_args(s: *const c_char, p: *const c_char, o: *const c_char) -> Result<Triple, OjiStatus> {
+  Ok((string_arg(s, "subject")?, string_arg(p, "predicate")?, string_arg(o, "object")?))
+}
+fn c_string(s: String) -> Result<CString, OjiStatus> {
+  CString::new(s).map_err(|_| fail(OjiStatus::InvalidString, "Term contains a NUL byte".into()))
+}
+unsafe fn graph_ref<'a>(graph: *const OjiGraph) -> Result<&'a Graph, OjiStatus> {
+  match graph.as_ref() {
+    Some(g) => Ok(&g.graph),
+    None => Err(fail(OjiStatus::NullArgument, "graph is NULL".into())),
+  }
+}
+unsafe fn graph_mut<'a>(graph: *mut OjiGraph) -> Result<&'a mut Graph, OjiStatus> {
+  match graph.as_mut() {
+    Some(g) => Ok(&mut g.graph),
+    None => Err(fail(OjiStatus::NullArgument, "graph is NULL".into())),
+  }
+}
+
+/* Errors and strings */
+/// Message describing the last failure on this thread, or NULL.
+/// Borrowed: valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn oji_last_error() -> *const c_char {
+  guarded(ptr::null(), || LAST_ERROR.with(|e| match &*e.borrow() {
+    Some(msg) => msg.as_ptr(),
+    None => ptr::null(),
+  }))
+}
+/// Frees a string returned by oji_graph_json or oji_graph_ntriples,
+/// which must not be used afterwards. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn oji_string_free(s: *mut c_char) {
+  guarded((), || if !s.is_null() {
+    drop(CString::from_raw(s));
+  })
+}
+
+/* Graph lifecycle */
+/// A new, empty graph, owned by the caller until it's passed to
+/// oji_graph_free. NULL only if creating it panicked.
+#[no_mangle]
+pub extern "C" fn oji_graph_new() -> *mut OjiGraph {
+  guarded(ptr::null_mut(), || Box::into_raw(Box::new(OjiGraph { graph: Graph::new() })))
+}
+/// Frees a graph from oji_graph_new; neither it nor any string it
+/// lent out may be used afterwards. Cursors stay valid. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_free(graph: *mut OjiGraph) {
+  guarded((), || if !graph.is_null() {
+    drop(Box::from_raw(graph));
+  })
+}
+/// Number of triples, 0 for NULL.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_len(graph: *const OjiGraph) -> usize {
+  guarded(0, || match graph.as_ref() {
+    Some(g) => g.graph.iter().count(),
+    None => 0,
+  })
+}
+
+/* Mutation */
+/// Inserts the triple s p o. The terms are copied, so the caller
+/// keeps ownership of them.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_insert(graph: *mut OjiGraph,
+                                          s: *const c_char,
+                                          p: *const c_char,
+                                          o: *const c_char) -> OjiStatus {
+  guarded(OjiStatus::Panic, || {
+    let graph = try_ffi!(graph_mut(graph));
+    graph.insert(try_ffi!(triple_args(s, p, o)));
+    OjiStatus::Ok
+  })
+}
+/// Removes the triple s p o, if it's there. The caller keeps
+/// ownership of the terms.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_remove(graph: *mut OjiGraph,
+                                          s: *const c_char,
+                                          p: *const c_char,
+                                          o: *const c_char) -> OjiStatus {
+  guarded(OjiStatus::Panic, || {
+    let graph = try_ffi!(graph_mut(graph));
+    graph.remove(&try_ffi!(triple_args(s, p, o)));
+    OjiStatus::Ok
+  })
+}
+/// Applies a SPARQL Update request; on failure the graph is left unchanged.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_update(graph: *mut OjiGraph, request: *const c_char) -> OjiStatus {
+  guarded(OjiStatus::Panic, || {
+    let graph = try_ffi!(graph_mut(graph));
+    let request = try_ffi!(string_arg(request, "request"));
+    match graph.update(&request) {
+      Ok(()) => OjiStatus::Ok,
+      Err(e) => failed(e),
+    }
+  })
+}
+
+/* Pattern iteration */
+/// Calls callback for every triple matching the pattern, where a
+/// NULL s, p or o matches anything. user_data is passed through
+/// untouched. The graph must not be changed from inside callback.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_match(graph: *const OjiGraph,
+                                         s: *const c_char,
+                                         p: *const c_char,
+                                         o: *const c_char,
+                                         callback: OjiTripleCallback,
+                                         user_data: *mut c_void) -> OjiStatus {
+  guarded(OjiStatus::Panic, || {
+    let graph = try_ffi!(graph_ref(graph));
+    let callback = match callback {
+      Some(cb) => cb,
+      None => return fail(OjiStatus::NullArgument, "callback is NULL".into()),
+    };
+    let pattern = (try_ffi!(pattern_arg(s, "subject")),
+                   try_ffi!(pattern_arg(p, "predicate")),
+                   try_ffi!(pattern_arg(o, "object")));
+    for (s, p, o) in graph.get_triple(&pattern) {
+      let (s, p, o) = (try_ffi!(c_string(s)), try_ffi!(c_string(p)), try_ffi!(c_string(o)));
+      if callback(s.as_ptr(), p.as_ptr(), o.as_ptr(), user_data) != 0 {
+        break
+      }
+    }
+    OjiStatus::Ok
+  })
+}
+
+/* Queries */
+/// Runs a query and stores a cursor over its results in *cursor.
+/// vars holds n_vars variable names ("$name"), conds holds n_conds
+/// patterns as 3 * n_conds consecutive terms; variables in the
+/// patterns must be among vars. The cursor copies the results, so
+/// the graph may change or be freed while it is in use.
+/// The caller owns the cursor and releases it with oji_cursor_free;
+/// *cursor is left alone on failure.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_query(graph: *const OjiGraph,
+                                         vars: *const *const c_char,
+                                         n_vars: usize,
+                                         conds: *const *const c_char,
+                                         n_conds: usize,
+                                         cursor: *mut *mut OjiCursor) -> OjiStatus {
+  guarded(OjiStatus::Panic, || {
+    let graph = try_ffi!(graph_ref(graph));
+    if cursor.is_null() || (vars.is_null() && n_vars > 0) || (conds.is_null() && n_conds > 0) {
+      return fail(OjiStatus::NullArgument, "vars, conds and cursor must not be NULL".into())
+    }
+    let mut var_names = Vec::with_capacity(n_vars);
+    for i in 0..n_vars {
+      var_names.push(try_ffi!(string_arg(*vars.add(i), "var")));
+    }
+    let mut cond_terms = Vec::with_capacity(n_conds);
+    for i in 0..n_conds {
+      let at = |j| *conds.add(i * 3 + j);
+      cond_terms.push(try_ffi!(triple_args(at(0), at(1), at(2))));
+    }
+    let var_refs: Vec<&str> = var_names.iter().map(String::as_str).collect();
+    let cond_refs: Vec<(&str, &str, &str)> = cond_terms.iter()
+                                                       .map(|(s, p, o)| (s.as_str(), p.as_str(), o.as_str()))
+                                                       .collect();
+    let results = match Query::new().from(graph).select(&var_refs).filter(&cond_refs) {
+      Ok(query) => query.fetch(),
+      Err(e) => return failed(e),
+    };
+    let mut rows = Vec::with_capacity(results.results.len());
+    for r in results.results.iter() {
+      let mut row = Vec::new();
+      for var in r.var_map.keys() {
+        if let Some(val) = r.get_var(var) {
+          row.push((try_ffi!(c_string(var.clone())), try_ffi!(c_string(val))));
+        }
+      }
+      rows.push(row);
+    }
+    *cursor = Box::into_raw(Box::new(OjiCursor { rows, next: 0 }));
+    OjiStatus::Ok
+  })
+}
+/// Moves to the next result; false once they're exhausted, or for
+/// NULL. Call before the first oji_cursor_get.
+#[no_mangle]
+pub unsafe extern "C" fn oji_cursor_next(cursor: *mut OjiCursor) -> bool {
+  guarded(false, || match cursor.as_mut() {
+    Some(c) if c.next < c.rows.len() => {
+      c.next += 1;
+      true
+    },
+    _ => false,
+  })
+}
+/// Value of var ("name" or "$name") in the current result, or NULL if
+/// it's unbound. Borrowed: valid until the cursor is freed.
+#[no_mangle]
+pub unsafe extern "C" fn oji_cursor_get(cursor: *const OjiCursor, var: *const c_char) -> *const c_char {
+  guarded(ptr::null(), || {
+    let (cursor, var) = match (cursor.as_ref(), var.is_null()) {
+      (Some(c), false) if c.next > 0 => (c, CStr::from_ptr(var)),
+      _ => return ptr::null(),
+    };
+    let var = var.to_bytes().strip_prefix(b"$").unwrap_or(var.to_bytes());
+    cursor.rows[cursor.next - 1].iter()
+                                .find(|(name, _)| name.as_bytes() == var)
+                                .map_or(ptr::null(), |(_, val)| val.as_ptr())
+  })
+}
+/// Frees a cursor from oji_graph_query, along with every value
+/// oji_cursor_get lent out. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn oji_cursor_free(cursor: *mut OjiCursor) {
+  guarded((), || if !cursor.is_null() {
+    drop(Box::from_raw(cursor));
+  })
+}
+
+/* Documents */
+/// The graph as an N-Triples document, or NULL on failure.
+/// The caller owns it and frees it with oji_string_free.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_ntriples(graph: *const OjiGraph) -> *mut c_char {
+  guarded(ptr::null_mut(), || match graph_ref(graph).and_then(|g| c_string(g.ntriples())) {
+    Ok(doc) => doc.into_raw(),
+    Err(_) => ptr::null_mut(),
+  })
+}
+/// The graph as a TripleStore JSON document, or NULL on failure.
+/// The caller owns it and frees it with oji_string_free.
+#[no_mangle]
+pub unsafe extern "C" fn oji_graph_json(graph: *const OjiGraph) -> *mut c_char {
+  guarded(ptr::null_mut(), || {
+    let graph = match graph_ref(graph) {
+      Ok(g) => g,
+      Err(_) => return ptr::null_mut(),
+    };
+    match graph.json().map_err(failed).and_then(c_string) {
+      Ok(doc) => doc.into_raw(),
+      Err(_) => ptr::null_mut(),
+    }
+  })
+}
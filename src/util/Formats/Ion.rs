@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use super::super::{DataStores::TripleStore::TripleStore, Error::Error};
+
+/*
+Amazon Ion, text or binary, in the same shape as a TripleStore's
+JSON document: a struct of subjects, each a struct of predicates,
+each a list of objects.
+  { 'Gabe': { 'likes': ["Rust", "C++"] } }
+In binary, subjects and predicates are written once to a local
+symbol table and referred to by id after that.
+Only the parts of Ion this layout needs are read: structs, lists,
+strings and symbols, plus symbol tables and version markers.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IonFormat {
+  Binary,
+  Text,
+}
+
+const BVM: [u8; 4] = [0xE0, 0x01, 0x00, 0xEA];
+const SYSTEM_SYMBOLS: [&str; 10] = [
+  "$0", "$ion", "$ion_1_0", "$ion_symbol_table", "name",
+  "version", "imports", "symbols", "max_id", "$ion_shared_symbol_table",
+];
+const ION_SYMBOL_TABLE: usize = 3;
+const IMPORTS: usize = 6;
+const SYMBOLS: usize = 7;
+
+const NULL: u8 = 0x0;
+const SYMBOL: u8 = 0x7;
+const STRING: u8 = 0x8;
+const LIST: u8 = 0xB;
+const STRUCT: u8 = 0xD;
+const ANNOTATION: u8 = 0xE;
+
+pub fn write(store: &TripleStore, format: IonFormat) -> Vec<u8> {
+  match format {
+    IonFormat::Binary => write_binary(store),
+    IonFormat::Text => write_text(store).into_bytes(),
+  }
+}
+pub fn parse(doc: &[u8]) -> Result<TripleStore, Error> {
+  let values = if doc.starts_with(&BVM) {
+    binary_values(doc)?
+  }
+  else {
+    let doc = std::str::from_utf8(doc).map_err(|_| Error::Parse("Ion text isn't valid UTF-8".into()))?;
+    Text { doc, pos: 0 }.values()?
+  };
+  let mut store = TripleStore::new();
+  for value in values {
+    add_subjects(&mut store, value)?;
+  }
+  Ok(store)
+}
+
+/* Both readers produce these before they're turned into triples */
+enum Value {
+  Str(String),
+  List(Vec<Value>),
+  Struct(Vec<(String, Value)>),
+}
+fn add_subjects(store: &mut TripleStore, value: Value) -> Result<(), Error> {
+  let shape = || Error::Parse("Expected { subject: { predicate: [objects] } }".into());
+  let subjects = match value {
+    Value::Struct(fields) => fields,
+    _ => return Err(shape()),
+  };
+  for (s, predicates) in subjects {
+    let predicates = match predicates {
+      Value::Struct(fields) => fields,
+      _ => return Err(shape()),
+    };
+    for (p, objects) in predicates {
+      let objects = match objects {
+        Value::List(values) => values,
+        _ => return Err(shape()),
+      };
+      for o in objects {
+        match o {
+          Value::Str(o) => store.insert_ref((&s, &p, &o)),
+          _ => return Err(shape()),
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+/* Binary */
+fn write_binary(store: &TripleStore) -> Vec<u8> {
+  let mut symbols: Vec<&str> = Vec::new();
+  let mut sids: HashMap<&str, usize> = HashMap::new();
+  let mut subjects = Vec::new();
+  for (s, predicates) in store.0.iter() {
+    let subject = intern(&mut symbols, &mut sids, s);
+    let mut fields = Vec::new();
+    for (p, objects) in predicates.iter() {
+      let mut list = Vec::new();
+      for o in objects.iter() {
+        value(&mut list, STRING, o.as_bytes());
+      }
+      var_uint(&mut fields, intern(&mut symbols, &mut sids, p));
+      value(&mut fields, LIST, &list);
+    }
+    var_uint(&mut subjects, subject);
+    value(&mut subjects, STRUCT, &fields);
+  }
+  let mut doc = BVM.to_vec();
+  if !symbols.is_empty() {
+    let mut list = Vec::new();
+    for symbol in symbols.iter() {
+      value(&mut list, STRING, symbol.as_bytes());
+    }
+    let mut table = Vec::new();
+    var_uint(&mut table, SYMBOLS);
+    value(&mut table, LIST, &list);
+    let mut wrapped = vec![0x81, 0x80 | ION_SYMBOL_TABLE as u8];
+    value(&mut wrapped, STRUCT, &table);
+    value(&mut doc, ANNOTATION, &wrapped);
+  }
+  value(&mut doc, STRUCT, &subjects);
+  doc
+}
+fn intern<'s>(symbols: &mut Vec<&'s str>, sids: &mut HashMap<&'s str, usize>, name: &'s str) -> usize {
+  let next = SYSTEM_SYMBOLS.len() + symbols.len();
+  *sids.entry(name).or_insert_with(|| {
+    symbols.push(name);
+    next
+  })
+}
+fn value(out: &mut Vec<u8>, kind: u8, body: &[u8]) {
+  if body.len() < 14 {
+    out.push(kind << 4 | body.len() as u8);
+  }
+  else {
+    out.push(kind << 4 | 14);
+    var_uint(out, body.len());
+  }
+  out.extend_from_slice(body);
+}
+fn var_uint(out: &mut Vec<u8>, mut n: usize) {
+  let mut groups = vec![0x80 | (n & 0x7F) as u8];
+  n >>= 7;
+  while n > 0 {
+    groups.push((n & 0x7F) as u8);
+    n >>= 7;
+  }
+  out.extend(groups.iter().rev());
+}
+fn system_symbols() -> Vec<String> {
+  SYSTEM_SYMBOLS.iter().map(|s| s.to_string()).collect()
+}
+
+fn binary_values(doc: &[u8]) -> Result<Vec<Value>, Error> {
+  let mut reader = Binary { doc, pos: 0 };
+  let mut symbols = system_symbols();
+  let mut values = Vec::new();
+  while !reader.at_end() {
+    if reader.doc[reader.pos..].starts_with(&BVM) {
+      reader.pos += BVM.len();
+      symbols = system_symbols();
+      continue
+    }
+    let (kind, body) = reader.next()?;
+    if kind == ANNOTATION {
+      let mut inner = Binary { doc: body, pos: 0 };
+      let annotations = inner.annotations()?;
+      let (kind, body) = inner.next()?;
+      if annotations.first() == Some(&ION_SYMBOL_TABLE) && kind == STRUCT {
+        symbol_table(body, &mut symbols)?;
+        continue
+      }
+    }
+    values.extend(binary_value(kind, body, &symbols)?);
+  }
+  Ok(values)
+}
+fn symbol_table(body: &[u8], symbols: &mut Vec<String>) -> Result<(), Error> {
+  let mut table = Binary { doc: body, pos: 0 };
+  let mut append = false;
+  let mut added = Vec::new();
+  while !table.at_end() {
+    let field = table.var_uint()?;
+    let (kind, body) = table.next()?;
+    match (field, kind) {
+      (IMPORTS, SYMBOL) => append = uint(body) == ION_SYMBOL_TABLE,
+      (IMPORTS, LIST) => return Err(Error::Parse("Shared Ion symbol tables aren't supported".into())),
+      (SYMBOLS, LIST) => {
+        let mut list = Binary { doc: body, pos: 0 };
+        while !list.at_end() {
+          let (kind, body) = list.next()?;
+          added.push(match kind {
+            STRING => utf8(body)?,
+            /* Gaps in the table still take up an id */
+            _ => String::new(),
+          });
+        }
+      },
+      _ => {},
+    }
+  }
+  if !append {
+    *symbols = system_symbols();
+  }
+  symbols.extend(added);
+  Ok(())
+}
+fn binary_value(kind: u8, body: &[u8], symbols: &[String]) -> Result<Option<Value>, Error> {
+  let symbol = |sid: usize| symbols.get(sid)
+                                   .cloned()
+                                   .ok_or_else(|| Error::Parse(format!("Unknown Ion symbol ${}", sid)));
+  let mut reader = Binary { doc: body, pos: 0 };
+  Ok(Some(match kind {
+    NULL => return Ok(None),
+    STRING => Value::Str(utf8(body)?),
+    SYMBOL => Value::Str(symbol(uint(body))?),
+    LIST => {
+      let mut values = Vec::new();
+      while !reader.at_end() {
+        let (kind, body) = reader.next()?;
+        values.extend(binary_value(kind, body, symbols)?);
+      }
+      Value::List(values)
+    },
+    STRUCT => {
+      let mut fields = Vec::new();
+      while !reader.at_end() {
+        let name = reader.var_uint()?;
+        let (kind, body) = reader.next()?;
+        if let Some(v) = binary_value(kind, body, symbols)? {
+          fields.push((symbol(name)?, v));
+        }
+      }
+      Value::Struct(fields)
+    },
+    /* Annotations other than symbol tables carry no meaning here */
+    ANNOTATION => {
+      reader.annotations()?;
+      let (kind, body) = reader.next()?;
+      return binary_value(kind, body, symbols)
+    },
+    _ => return Err(Error::Parse(format!("Unsupported Ion type code {:X}", kind))),
+  }))
+}
+
+struct Binary<'a> {
+  doc: &'a [u8],
+  pos: usize,
+}
+impl<'a> Binary<'a> {
+  fn at_end(&self) -> bool {
+    self.pos >= self.doc.len()
+  }
+  fn byte(&mut self) -> Result<u8, Error> {
+    let b = *self.doc.get(self.pos).ok_or_else(truncated)?;
+    self.pos += 1;
+    Ok(b)
+  }
+  fn var_uint(&mut self) -> Result<usize, Error> {
+    let mut n: usize = 0;
+    loop {
+      let b = self.byte()?;
+      n = n.checked_mul(128).ok_or_else(|| Error::Parse("Ion VarUInt overflows".into()))? | (b & 0x7F) as usize;
+      if b & 0x80 != 0 {
+        return Ok(n)
+      }
+    }
+  }
+  /* A type descriptor and the bytes of the value it describes */
+  fn next(&mut self) -> Result<(u8, &'a [u8]), Error> {
+    let descriptor = self.byte()?;
+    let (kind, len) = (descriptor >> 4, descriptor & 0x0F);
+    let null = len == 15;
+    let len = match (kind, len) {
+      (_, 15) | (0x1, _) => 0,
+      (STRUCT, 1) | (_, 14) => self.var_uint()?,
+      (_, len) => len as usize,
+    };
+    let end = self.pos.checked_add(len).filter(|&end| end <= self.doc.len()).ok_or_else(truncated)?;
+    let body = &self.doc[self.pos..end];
+    self.pos = end;
+    /* Typed nulls read as plain nulls, NOP padding as nothing at all */
+    Ok((if null { NULL } else { kind }, body))
+  }
+  fn annotations(&mut self) -> Result<Vec<usize>, Error> {
+    let len = self.var_uint()?;
+    let end = self.pos.checked_add(len).filter(|&end| end <= self.doc.len()).ok_or_else(truncated)?;
+    let mut sids = Vec::new();
+    while self.pos < end {
+      sids.push(self.var_uint()?);
+    }
+    if self.pos != end {
+      return Err(Error::Parse("Ion annotation runs past its wrapper".into()))
+    }
+    Ok(sids)
+  }
+}
+fn uint(body: &[u8]) -> usize {
+  body.iter().fold(0, |n, &b| n << 8 | b as usize)
+}
+fn utf8(body: &[u8]) -> Result<String, Error> {
+  String::from_utf8(body.to_vec()).map_err(|_| Error::Parse("Ion string isn't valid UTF-8".into()))
+}
+fn truncated() -> Error {
+  Error::Parse("Ion value runs past the end of the document".into())
+}
+
+/* Text */
+fn write_text(store: &TripleStore) -> String {
+  let mut doc = String::from("$ion_1_0\n{\n");
+  for (s, predicates) in store.0.iter() {
+    doc.push_str(&format!("  {}: {{\n", quote(s, '\'')));
+    for (p, objects) in predicates.iter() {
+      let objects: Vec<String> = objects.iter().map(|o| quote(o, '"')).collect();
+      doc.push_str(&format!("    {}: [{}],\n", quote(p, '\''), objects.join(", ")));
+    }
+    doc.push_str("  },\n");
+  }
+  doc.push_str("}\n");
+  doc
+}
+fn quote(s: &str, q: char) -> String {
+  let mut quoted = String::with_capacity(s.len() + 2);
+  quoted.push(q);
+  for c in s.chars() {
+    match c {
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\t' => quoted.push_str("\\t"),
+      c if c == q => {
+        quoted.push('\\');
+        quoted.push(c);
+      },
+      c if c.is_control() && (c as u32) < 0x100 => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push(q);
+  quoted
+}
+
+struct Text<'a> {
+  doc: &'a str,
+  pos: usize,
+}
+impl<'a> Text<'a> {
+  fn values(&mut self) -> Result<Vec<Value>, Error> {
+    let mut values = Vec::new();
+    loop {
+      self.skip()?;
+      if self.rest().is_empty() {
+        return Ok(values)
+      }
+      if let Some(after) = self.rest().strip_prefix("$ion_1_0") {
+        if !after.starts_with(is_symbol_char) {
+          self.pos += "$ion_1_0".len();
+          continue
+        }
+      }
+      values.extend(self.value()?);
+    }
+  }
+  fn rest(&self) -> &'a str {
+    &self.doc[self.pos..]
+  }
+  fn error(&self, msg: &str) -> Error {
+    Error::Parse(format!("{} at byte {}", msg, self.pos))
+  }
+  /* Whitespace and comments */
+  fn skip(&mut self) -> Result<(), Error> {
+    loop {
+      let rest = self.rest();
+      let trimmed = rest.trim_start();
+      self.pos += rest.len() - trimmed.len();
+      if trimmed.starts_with("//") {
+        self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+      }
+      else if trimmed.starts_with("/*") {
+        let end = trimmed.find("*/").ok_or_else(|| self.error("Unterminated comment"))?;
+        self.pos += end + 2;
+      }
+      else {
+        return Ok(())
+      }
+    }
+  }
+  fn eat(&mut self, c: char) -> Result<bool, Error> {
+    self.skip()?;
+    if self.rest().starts_with(c) {
+      self.pos += c.len_utf8();
+      return Ok(true)
+    }
+    Ok(false)
+  }
+  /* None for nulls, which are skipped as they are in binary */
+  fn value(&mut self) -> Result<Option<Value>, Error> {
+    self.skip()?;
+    Ok(Some(match self.rest().chars().next() {
+      Some('{') => {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        while !self.eat('}')? {
+          let name = self.symbol()?;
+          if !self.eat(':')? {
+            return Err(self.error("Expected ':'"))
+          }
+          if let Some(value) = self.value()? {
+            fields.push((name, value));
+          }
+          if !self.eat(',')? && !self.rest().starts_with('}') {
+            return Err(self.error("Expected ',' or '}'"))
+          }
+        }
+        Value::Struct(fields)
+      },
+      Some('[') => {
+        self.pos += 1;
+        let mut values = Vec::new();
+        while !self.eat(']')? {
+          values.extend(self.value()?);
+          if !self.eat(',')? && !self.rest().starts_with(']') {
+            return Err(self.error("Expected ',' or ']'"))
+          }
+        }
+        Value::List(values)
+      },
+      Some('"') => Value::Str(self.quoted('"')?),
+      Some('\'') => Value::Str(self.symbol()?),
+      Some(_) => {
+        let start = self.pos;
+        let symbol = self.symbol()?;
+        /* Unquoted keywords aren't symbols: null and typed nulls, or values of other types */
+        match symbol.as_str() {
+          "null" => {
+            if self.rest().starts_with('.') {
+              self.pos += 1;
+              self.symbol()?;
+            }
+            return Ok(None)
+          },
+          "true" | "false" | "nan" => {
+            self.pos = start;
+            return Err(self.error(&format!("Unsupported Ion value '{}'", symbol)))
+          },
+          _ => Value::Str(symbol),
+        }
+      },
+      None => return Err(self.error("Expected a value")),
+    }))
+  }
+  /* Field names and symbol values, quoted or not */
+  fn symbol(&mut self) -> Result<String, Error> {
+    self.skip()?;
+    let rest = self.rest();
+    match rest.chars().next() {
+      Some('\'') => self.quoted('\''),
+      Some('"') => self.quoted('"'),
+      Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {
+        let len = rest.find(|c: char| !is_symbol_char(c)).unwrap_or(rest.len());
+        self.pos += len;
+        Ok(rest[..len].to_string())
+      },
+      _ => Err(self.error("Expected a string or symbol")),
+    }
+  }
+  fn quoted(&mut self, q: char) -> Result<String, Error> {
+    self.pos += 1;
+    let mut s = String::new();
+    let mut chars = self.rest().char_indices();
+    while let Some((i, c)) = chars.next() {
+      match c {
+        c if c == q => {
+          self.pos += i + 1;
+          return Ok(s)
+        },
+        '\\' => {
+          let escaped = match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 'r')) => '\r',
+            Some((_, 't')) => '\t',
+            Some((_, '0')) => '\0',
+            Some((_, 'x')) => hex(&mut chars, 2),
+            Some((_, 'u')) => hex(&mut chars, 4),
+            Some((_, 'U')) => hex(&mut chars, 8),
+            Some((_, c)) if "\\'\"/?".contains(c) => c,
+            _ => return Err(self.error("Invalid escape")),
+          };
+          s.push(escaped);
+        },
+        c => s.push(c),
+      }
+    }
+    Err(self.error("Unterminated string"))
+  }
+}
+fn is_symbol_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+fn hex(chars: &mut std::str::CharIndices, digits: usize) -> char {
+  let code: String = chars.take(digits).map(|(_, c)| c).collect();
+  u32::from_str_radix(&code, 16).ok()
+                                .and_then(std::char::from_u32)
+                                .unwrap_or(std::char::REPLACEMENT_CHARACTER)
+}
@@ -0,0 +1,525 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde_json::{Map, Value};
+use super::super::{
+  DataStores::{BlankNodes, TripleStore::TripleStore},
+  Error::Error,
+  Term::{self, rdf, xsd, TermKind},
+};
+
+type Triple = (String, String, String);
+
+/*
+JSON-LD 1.1, reading and writing node objects through a context
+that's given up front rather than fetched.
+Reading expands every node object (terms, compact IRIs, @vocab,
+@base, @language, type coercion, @reverse, @list, @graph) straight
+into triples. Writing compacts the spo index back into one node
+object per subject, choosing terms the way compaction would.
+Remote contexts, @import, scoped contexts and @json aren't supported.
+*/
+
+pub fn parse(doc: &str, context: Option<&str>) -> Result<Vec<Triple>, Error> {
+  let doc: Value = serde_json::from_str(doc)?;
+  let mut ctx = Context::default();
+  if let Some(context) = context {
+    ctx = ctx.update(local_context(&serde_json::from_str(context)?))?;
+  }
+  let mut reader = Reader { triples: Vec::new(), used: HashSet::new(), next_blank: 0 };
+  blank_labels(&doc, &mut reader.used);
+  for node in flatten(&doc) {
+    match node {
+      Value::Object(obj) => { reader.node(obj, &ctx)?; },
+      _ => return Err(Error::Parse("Expected a JSON-LD node object".into())),
+    }
+  }
+  Ok(reader.triples)
+}
+/* context is a context, or a document with one under @context */
+pub fn write(spo: &TripleStore, context: &str) -> Result<String, Error> {
+  let context: Value = serde_json::from_str(context)?;
+  let context = local_context(&context).clone();
+  let ctx = Context::default().update(&context)?;
+  let subjects: BTreeMap<&String, _> = spo.0.iter().collect();
+  let mut nodes = Vec::new();
+  for (s, predicates) in subjects {
+    let mut node = Map::new();
+    node.insert("@id".into(), Value::String(ctx.compact_iri(s, false)));
+    let predicates: BTreeMap<&String, _> = predicates.iter().collect();
+    for (p, objects) in predicates {
+      let mut objects: Vec<&String> = objects.iter().collect();
+      objects.sort();
+      if p.as_str() == rdf("type") && objects.iter().all(|o| Term::kind(o) == TermKind::Iri) {
+        let types: Vec<Value> = objects.iter().map(|o| Value::String(ctx.compact_iri(o, true))).collect();
+        node.insert("@type".into(), one_or_many(types));
+        continue
+      }
+      let (key, def) = ctx.choose_term(p, &objects);
+      let values: Vec<Value> = objects.iter().map(|o| ctx.compact_value(o, def)).collect();
+      node.insert(key, one_or_many(values));
+    }
+    nodes.push(Value::Object(node));
+  }
+  let mut doc = Map::new();
+  doc.insert("@context".into(), context);
+  if nodes.len() == 1 {
+    if let Some(Value::Object(node)) = nodes.pop() {
+      doc.extend(node);
+    }
+  }
+  else {
+    doc.insert("@graph".into(), Value::Array(nodes));
+  }
+  Ok(serde_json::to_string_pretty(&Value::Object(doc))?)
+}
+
+fn local_context(value: &Value) -> &Value {
+  match value {
+    Value::Object(obj) if obj.contains_key("@context") => &obj["@context"],
+    _ => value,
+  }
+}
+/* Arrays and @set objects both just hold several values */
+fn flatten(value: &Value) -> Vec<&Value> {
+  match value {
+    Value::Array(values) => values.iter().flat_map(flatten).collect(),
+    Value::Object(obj) if obj.contains_key("@set") => obj.get("@set").map(flatten).unwrap_or_default(),
+    Value::Null => Vec::new(),
+    _ => vec![value],
+  }
+}
+fn one_or_many(mut values: Vec<Value>) -> Value {
+  if values.len() == 1 {
+    return values.pop().unwrap_or(Value::Null)
+  }
+  Value::Array(values)
+}
+/* Labels the document already uses, so fresh ones can't clash */
+fn blank_labels(value: &Value, used: &mut HashSet<String>) {
+  match value {
+    Value::String(s) if s.starts_with("_:") => { used.insert(s.clone()); },
+    Value::Array(values) => values.iter().for_each(|v| blank_labels(v, used)),
+    Value::Object(obj) => obj.values().for_each(|v| blank_labels(v, used)),
+    _ => {},
+  }
+}
+fn is_gen_delim(c: char) -> bool {
+  ":/?#[]@".contains(c)
+}
+
+/* Contexts */
+#[derive(Clone, Debug, Default)]
+struct Context {
+  terms: HashMap<String, TermDef>,
+  vocab: Option<String>,
+  base: Option<String>,
+  language: Option<String>,
+}
+#[derive(Clone, Debug, Default, PartialEq)]
+struct TermDef {
+  iri: String,
+  /* "@id", "@vocab" or a datatype IRI */
+  coerce: Option<String>,
+  /* Some(None) when the term is explicitly language-less */
+  language: Option<Option<String>>,
+  list: bool,
+  reverse: bool,
+}
+impl Context {
+  fn update(&self, local: &Value) -> Result<Context, Error> {
+    match local {
+      Value::Null => Ok(Context::default()),
+      Value::Array(contexts) => contexts.iter().try_fold(self.clone(), |ctx, local| ctx.update(local)),
+      Value::String(url) => Err(Error::Parse(format!("Remote context '{}' can't be loaded; pass it in instead", url))),
+      Value::Object(local) => {
+        let mut ctx = self.clone();
+        if local.contains_key("@import") {
+          return Err(Error::Parse("@import isn't supported".into()))
+        }
+        if let Some(base) = local.get("@base") {
+          ctx.base = base.as_str().map(|b| ctx.resolve(b));
+        }
+        if let Some(vocab) = local.get("@vocab") {
+          ctx.vocab = vocab.as_str().map(|v| ctx.expand_iri(v, true, true));
+        }
+        if let Some(lang) = local.get("@language") {
+          ctx.language = lang.as_str().map(str::to_string);
+        }
+        let mut defined = HashMap::new();
+        for term in local.keys().filter(|k| !k.starts_with('@')) {
+          ctx.define(local, term, &mut defined)?;
+        }
+        Ok(ctx)
+      },
+      _ => Err(Error::Parse("A context must be an object, an array or null".into())),
+    }
+  }
+  fn define(&mut self, local: &Map<String, Value>, term: &str, defined: &mut HashMap<String, bool>) -> Result<(), Error> {
+    match defined.get(term) {
+      Some(true) => return Ok(()),
+      Some(false) => return Err(Error::Parse(format!("Cyclic definition of term '{}'", term))),
+      None => { defined.insert(term.to_string(), false); },
+    }
+    /* Terms this one is written in terms of come first */
+    let mut depends = |ctx: &mut Context, iri: &str| -> Result<(), Error> {
+      if let Some((prefix, _)) = iri.split_once(':') {
+        if prefix != term && local.contains_key(prefix) {
+          ctx.define(local, prefix, defined)?;
+        }
+      }
+      else if local.contains_key(iri) && iri != term {
+        ctx.define(local, iri, defined)?;
+      }
+      Ok(())
+    };
+    let def = match &local[term] {
+      Value::Null => None,
+      Value::String(iri) => {
+        depends(self, iri)?;
+        Some(TermDef { iri: self.expand_iri(iri, true, false), ..TermDef::default() })
+      },
+      Value::Object(def) => {
+        let mut d = TermDef::default();
+        let id = match (def.get("@reverse"), def.get("@id")) {
+          (Some(Value::String(iri)), _) => {
+            d.reverse = true;
+            Some(iri)
+          },
+          (_, Some(Value::String(iri))) => Some(iri),
+          _ => None,
+        };
+        match id {
+          Some(iri) => {
+            depends(self, iri)?;
+            d.iri = self.expand_iri(iri, true, false);
+          },
+          None => {
+            depends(self, term)?;
+            d.iri = self.expand_iri(term, true, false);
+          },
+        }
+        if let Some(Value::String(coerce)) = def.get("@type") {
+          depends(self, coerce)?;
+          d.coerce = Some(match coerce.as_str() {
+            "@id" | "@vocab" => coerce.clone(),
+            "@json" | "@none" => return Err(Error::Parse(format!("@type {} isn't supported", coerce))),
+            _ => self.expand_iri(coerce, true, false),
+          });
+        }
+        match def.get("@language") {
+          Some(Value::String(lang)) => d.language = Some(Some(lang.clone())),
+          Some(Value::Null) => d.language = Some(None),
+          _ => {},
+        }
+        d.list = container_has(def.get("@container"), "@list");
+        Some(d)
+      },
+      _ => return Err(Error::Parse(format!("Invalid definition of term '{}'", term))),
+    };
+    match def {
+      Some(def) => { self.terms.insert(term.to_string(), def); },
+      None => { self.terms.remove(term); },
+    }
+    defined.insert(term.to_string(), true);
+    Ok(())
+  }
+  fn expand_iri(&self, value: &str, vocab: bool, document_relative: bool) -> String {
+    if value.starts_with('@') {
+      return value.to_string()
+    }
+    if vocab {
+      if let Some(def) = self.terms.get(value) {
+        return def.iri.clone()
+      }
+    }
+    if let Some((prefix, suffix)) = value.split_once(':') {
+      if prefix == "_" || suffix.starts_with("//") {
+        return value.to_string()
+      }
+      if let Some(def) = self.terms.get(prefix) {
+        return format!("{}{}", def.iri, suffix)
+      }
+      return value.to_string()
+    }
+    if vocab {
+      if let Some(v) = &self.vocab {
+        return format!("{}{}", v, value)
+      }
+    }
+    if document_relative {
+      return self.resolve(value)
+    }
+    value.to_string()
+  }
+  fn resolve(&self, reference: &str) -> String {
+    match &self.base {
+      Some(base) => Term::resolve(base, reference),
+      None => reference.to_string(),
+    }
+  }
+
+  /* Compaction */
+  fn compact_iri(&self, iri: &str, vocab: bool) -> String {
+    if Term::kind(iri) != TermKind::Iri {
+      return iri.to_string()
+    }
+    if vocab {
+      let mut exact: Vec<&String> = self.terms.iter()
+                                               .filter(|(_, d)| d.iri == iri && *d == &TermDef { iri: d.iri.clone(), ..TermDef::default() })
+                                               .map(|(t, _)| t)
+                                               .collect();
+      exact.sort_by_key(|t| (t.len(), t.to_string()));
+      if let Some(term) = exact.first() {
+        return term.to_string()
+      }
+      if let Some(v) = &self.vocab {
+        if let Some(rest) = iri.strip_prefix(v.as_str()) {
+          if !rest.is_empty() && !self.terms.contains_key(rest) && !rest.contains(':') {
+            return rest.to_string()
+          }
+        }
+      }
+    }
+    let mut candidates: Vec<String> = self.terms.iter()
+      .filter(|(t, d)| !t.contains(':') && !d.reverse && d.iri.ends_with(is_gen_delim))
+      .filter_map(|(t, d)| iri.strip_prefix(d.iri.as_str())
+                              .filter(|rest| !rest.is_empty())
+                              .map(|rest| format!("{}:{}", t, rest)))
+      .collect();
+    candidates.sort_by_key(|c| (c.len(), c.clone()));
+    candidates.into_iter().next().unwrap_or_else(|| iri.to_string())
+  }
+  /* The term for p whose coercion suits every object, else p as a compact IRI */
+  fn choose_term(&self, p: &str, objects: &[&String]) -> (String, Option<&TermDef>) {
+    let mut terms: Vec<(&String, &TermDef)> = self.terms.iter()
+                                                        .filter(|(_, d)| d.iri == p && !d.reverse && !d.list)
+                                                        .collect();
+    terms.sort_by_key(|(t, d)| (d.coerce.is_none() && d.language.is_none(), t.len(), t.to_string()));
+    for (term, def) in terms {
+      if objects.iter().all(|o| self.suits(o, def)) {
+        return (term.clone(), Some(def))
+      }
+    }
+    (self.compact_iri(p, true), None)
+  }
+  fn suits(&self, o: &str, def: &TermDef) -> bool {
+    match (&def.coerce, Term::kind(o)) {
+      (Some(c), TermKind::Iri) | (Some(c), TermKind::BlankNode) => c == "@id" || c == "@vocab",
+      (Some(c), TermKind::Literal) => c != "@id" && c != "@vocab" && Term::literal(o).2.as_ref() == Some(c),
+      (None, TermKind::Literal) => match (&def.language, Term::literal(o)) {
+        (Some(lang), (_, o_lang, None)) => *lang == o_lang,
+        (Some(_), _) => false,
+        _ => true,
+      },
+      (None, _) => def.language.is_none(),
+    }
+  }
+  fn compact_value(&self, o: &str, def: Option<&TermDef>) -> Value {
+    let coerce = def.and_then(|d| d.coerce.as_deref());
+    let mut obj = Map::new();
+    match Term::kind(o) {
+      TermKind::Iri | TermKind::BlankNode => {
+        match coerce {
+          Some("@id") => return Value::String(self.compact_iri(o, false)),
+          Some("@vocab") => return Value::String(self.compact_iri(o, true)),
+          _ => { obj.insert("@id".into(), Value::String(self.compact_iri(o, false))); },
+        }
+      },
+      TermKind::Literal => {
+        let (lexical, lang, datatype) = Term::literal(o);
+        let default_lang = match def.and_then(|d| d.language.clone()) {
+          Some(lang) => lang,
+          None => self.language.clone(),
+        };
+        match (lang, datatype) {
+          (_, Some(dt)) if coerce == Some(dt.as_str()) => return Value::String(lexical),
+          (None, Some(dt)) if dt == xsd("string") && default_lang.is_none() => return Value::String(lexical),
+          (None, None) if default_lang.is_none() => return Value::String(lexical),
+          (Some(lang), None) if Some(&lang) == default_lang.as_ref() => return Value::String(lexical),
+          (lang, dt) => {
+            obj.insert("@value".into(), Value::String(lexical));
+            if let Some(lang) = lang {
+              obj.insert("@language".into(), Value::String(lang));
+            }
+            if let Some(dt) = dt {
+              obj.insert("@type".into(), Value::String(self.compact_iri(&dt, true)));
+            }
+          },
+        }
+      },
+    }
+    Value::Object(obj)
+  }
+}
+fn container_has(container: Option<&Value>, keyword: &str) -> bool {
+  match container {
+    Some(Value::String(c)) => c == keyword,
+    Some(Value::Array(cs)) => cs.iter().any(|c| c.as_str() == Some(keyword)),
+    _ => false,
+  }
+}
+
+/* Expansion straight to triples */
+struct Reader {
+  triples: Vec<Triple>,
+  used: HashSet<String>,
+  next_blank: usize,
+}
+impl Reader {
+  fn blank(&mut self) -> String {
+    let used = &self.used;
+    BlankNodes::fresh(&mut self.next_blank, |l| used.contains(l))
+  }
+  /* Returns the node's subject */
+  fn node(&mut self, obj: &Map<String, Value>, ctx: &Context) -> Result<String, Error> {
+    let local;
+    let ctx = match obj.get("@context") {
+      Some(c) => {
+        local = ctx.update(c)?;
+        &local
+      },
+      None => ctx,
+    };
+    let subject = match obj.get("@id") {
+      Some(Value::String(id)) => ctx.expand_iri(id, false, true),
+      Some(_) => return Err(Error::Parse("@id must be a string".into())),
+      None => self.blank(),
+    };
+    for (key, value) in obj.iter() {
+      let property = ctx.expand_iri(key, true, false);
+      match property.as_str() {
+        "@context" | "@id" | "@index" => {},
+        "@type" => {
+          for t in flatten(value) {
+            let t = t.as_str().ok_or_else(|| Error::Parse("@type must be a string".into()))?;
+            self.triples.push((subject.clone(), rdf("type"), ctx.expand_iri(t, true, true)));
+          }
+        },
+        "@graph" | "@included" => {
+          for inner in flatten(value) {
+            match inner {
+              Value::Object(inner) => { self.node(inner, ctx)?; },
+              _ => return Err(Error::Parse(format!("{} must hold node objects", property))),
+            }
+          }
+        },
+        "@reverse" => {
+          let reverse = value.as_object().ok_or_else(|| Error::Parse("@reverse must be an object".into()))?;
+          for (key, value) in reverse.iter() {
+            let property = ctx.expand_iri(key, true, false);
+            self.reverse(&subject, &property, value, ctx)?;
+          }
+        },
+        k if k.starts_with('@') => return Err(Error::Parse(format!("{} isn't supported in a node object", k))),
+        _ => {
+          /* Keys that don't expand to an IRI are dropped, as in expansion */
+          if Term::kind(&property) == TermKind::Literal {
+            continue
+          }
+          let def = ctx.terms.get(key.as_str());
+          if def.is_some_and(|d| d.reverse) {
+            self.reverse(&subject, &property, value, ctx)?;
+            continue
+          }
+          if def.is_some_and(|d| d.list) {
+            let items: Vec<&Value> = flatten(value);
+            let head = self.list(&items, def, ctx)?;
+            self.triples.push((subject.clone(), property, head));
+            continue
+          }
+          for v in flatten(value) {
+            if let Some(object) = self.value(v, def, ctx)? {
+              self.triples.push((subject.clone(), property.clone(), object));
+            }
+          }
+        },
+      }
+    }
+    Ok(subject)
+  }
+  fn reverse(&mut self, subject: &str, property: &str, value: &Value, ctx: &Context) -> Result<(), Error> {
+    for v in flatten(value) {
+      let node = match v {
+        Value::Object(obj) => self.node(obj, ctx)?,
+        Value::String(id) => ctx.expand_iri(id, false, true),
+        _ => return Err(Error::Parse("Reverse properties must point at nodes".into())),
+      };
+      self.triples.push((node, property.to_string(), subject.to_string()));
+    }
+    Ok(())
+  }
+  fn list(&mut self, items: &[&Value], def: Option<&TermDef>, ctx: &Context) -> Result<String, Error> {
+    let mut objects = Vec::new();
+    for item in items {
+      objects.extend(self.value(item, def, ctx)?);
+    }
+    let mut head = rdf("nil");
+    for object in objects.into_iter().rev() {
+      let node = self.blank();
+      self.triples.push((node.clone(), rdf("first"), object));
+      self.triples.push((node.clone(), rdf("rest"), head));
+      head = node;
+    }
+    Ok(head)
+  }
+  fn value(&mut self, v: &Value, def: Option<&TermDef>, ctx: &Context) -> Result<Option<String>, Error> {
+    let coerce = def.and_then(|d| d.coerce.as_deref());
+    Ok(Some(match v {
+      Value::Null => return Ok(None),
+      Value::String(s) => match coerce {
+        Some("@id") => ctx.expand_iri(s, false, true),
+        Some("@vocab") => ctx.expand_iri(s, true, true),
+        Some(dt) => Term::make_literal(s, None, Some(dt)),
+        None => {
+          let lang = match def.and_then(|d| d.language.clone()) {
+            Some(lang) => lang,
+            None => ctx.language.clone(),
+          };
+          Term::make_literal(s, lang.as_deref(), None)
+        },
+      },
+      Value::Bool(_) | Value::Number(_) => native(v, coerce),
+      Value::Array(_) => return Err(Error::Parse("Nested arrays aren't allowed outside @list".into())),
+      Value::Object(obj) => {
+        if let Some(value) = obj.get("@value") {
+          let datatype = match obj.get("@type") {
+            Some(Value::String(t)) if t == "@json" => return Err(Error::Parse("@json literals aren't supported".into())),
+            Some(Value::String(t)) => Some(ctx.expand_iri(t, true, true)),
+            _ => None,
+          };
+          let lang = obj.get("@language").and_then(Value::as_str);
+          match value {
+            Value::Null => return Ok(None),
+            Value::String(s) => Term::make_literal(s, lang, datatype.as_deref()),
+            Value::Bool(_) | Value::Number(_) => native(value, datatype.as_deref()),
+            _ => return Err(Error::Parse("@value must be a string, number or boolean".into())),
+          }
+        }
+        else if let Some(items) = obj.get("@list") {
+          let items = flatten(items);
+          return self.list(&items, def, ctx).map(Some)
+        }
+        else {
+          self.node(obj, ctx)?
+        }
+      },
+    }))
+  }
+}
+/* Booleans and numbers become xsd literals in their canonical form */
+fn native(v: &Value, datatype: Option<&str>) -> String {
+  let (lexical, default) = match v {
+    Value::Bool(b) => (b.to_string(), xsd("boolean")),
+    Value::Number(n) if n.is_i64() || n.is_u64() => (n.to_string(), xsd("integer")),
+    Value::Number(n) => {
+      let f = n.as_f64().unwrap_or(0.0);
+      let canonical = format!("{:E}", f);
+      let canonical = match canonical.split_once('E') {
+        Some((mantissa, exp)) if !mantissa.contains('.') => format!("{}.0E{}", mantissa, exp),
+        _ => canonical,
+      };
+      (canonical, xsd("double"))
+    },
+    _ => (v.to_string(), xsd("string")),
+  };
+  Term::make_literal(&lexical, None, Some(datatype.unwrap_or(&default)))
+}
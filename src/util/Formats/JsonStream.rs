@@ -0,0 +1,164 @@
+use std::{
+  fmt,
+  io::{BufReader, Read},
+};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use super::super::Error::Error;
+
+type Triple = (String, String, String);
+
+/*
+JSON read straight off an io::Read, in any of these shapes:
+  {"s": {"p": ["o", ...]}}          as TripleStore::json writes it
+  [["s", "p", "o"], ...]            an array of triples
+  [{"s": .., "p": .., "o": ..}, ...] an array of triple objects
+where the two kinds of array element can be mixed.
+Each triple goes to insert as soon as it's read, so only one is held
+at a time rather than the whole document. A document that turns out
+to be malformed part way through has still inserted what came before.
+*/
+pub fn read<R, F>(reader: R, insert: F) -> Result<usize, Error>
+  where R: Read,
+        F: FnMut(Triple) {
+  let mut de = serde_json::Deserializer::from_reader(BufReader::new(reader));
+  let mut sink = Sink { insert, count: 0 };
+  Document(&mut sink).deserialize(&mut de)?;
+  de.end()?;
+  Ok(sink.count)
+}
+
+struct Sink<F> {
+  insert: F,
+  count: usize,
+}
+impl<F: FnMut(Triple)> Sink<F> {
+  fn push(&mut self, triple: Triple) {
+    (self.insert)(triple);
+    self.count += 1;
+  }
+}
+
+struct Document<'a, F>(&'a mut Sink<F>);
+impl<'de, 'a, F: FnMut(Triple)> DeserializeSeed<'de> for Document<'a, F> {
+  type Value = ();
+  fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+    de.deserialize_any(self)
+  }
+}
+impl<'de, 'a, F: FnMut(Triple)> Visitor<'de> for Document<'a, F> {
+  type Value = ();
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("a map of subjects, or an array of [s, p, o] arrays or {\"s\", \"p\", \"o\"} objects")
+  }
+  fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+    while let Some(s) = map.next_key::<String>()? {
+      map.next_value_seed(Predicates { sink: &mut *self.0, s: &s })?;
+    }
+    Ok(())
+  }
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+    while let Some(triple) = seq.next_element_seed(Element)? {
+      self.0.push(triple);
+    }
+    Ok(())
+  }
+}
+
+struct Predicates<'a, 's, F> {
+  sink: &'a mut Sink<F>,
+  s: &'s str,
+}
+impl<'de, 'a, 's, F: FnMut(Triple)> DeserializeSeed<'de> for Predicates<'a, 's, F> {
+  type Value = ();
+  fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+    de.deserialize_map(self)
+  }
+}
+impl<'de, 'a, 's, F: FnMut(Triple)> Visitor<'de> for Predicates<'a, 's, F> {
+  type Value = ();
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("a map of predicates")
+  }
+  fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+    while let Some(p) = map.next_key::<String>()? {
+      map.next_value_seed(Objects { sink: &mut *self.sink, s: self.s, p: &p })?;
+    }
+    Ok(())
+  }
+}
+
+struct Objects<'a, 's, F> {
+  sink: &'a mut Sink<F>,
+  s: &'s str,
+  p: &'s str,
+}
+impl<'de, 'a, 's, F: FnMut(Triple)> DeserializeSeed<'de> for Objects<'a, 's, F> {
+  type Value = ();
+  fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+    de.deserialize_seq(self)
+  }
+}
+impl<'de, 'a, 's, F: FnMut(Triple)> Visitor<'de> for Objects<'a, 's, F> {
+  type Value = ();
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("an array of objects")
+  }
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+    while let Some(o) = seq.next_element::<String>()? {
+      self.sink.push((self.s.to_string(), self.p.to_string(), o));
+    }
+    Ok(())
+  }
+}
+
+/* One triple in an array, as [s, p, o] or {"s": .., "p": .., "o": ..} */
+struct Element;
+impl<'de> DeserializeSeed<'de> for Element {
+  type Value = Triple;
+  fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<Triple, D::Error> {
+    de.deserialize_any(self)
+  }
+}
+impl<'de> Visitor<'de> for Element {
+  type Value = Triple;
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("a triple, as [s, p, o] or {\"s\", \"p\", \"o\"}")
+  }
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Triple, A::Error> {
+    let mut terms = Vec::with_capacity(3);
+    while let Some(term) = seq.next_element::<String>()? {
+      if terms.len() == 3 {
+        return Err(de::Error::custom("a triple array has more than 3 terms"))
+      }
+      terms.push(term);
+    }
+    if terms.len() < 3 {
+      return Err(de::Error::custom(format!("a triple array has {} terms, expected [s, p, o]", terms.len())))
+    }
+    let o = terms.pop().unwrap_or_default();
+    let p = terms.pop().unwrap_or_default();
+    Ok((terms.pop().unwrap_or_default(), p, o))
+  }
+  fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Triple, A::Error> {
+    let (mut s, mut p, mut o) = (None, None, None);
+    while let Some(key) = map.next_key::<String>()? {
+      let slot = match key.as_str() {
+        "s" => &mut s,
+        "p" => &mut p,
+        "o" => &mut o,
+        _ => return Err(de::Error::custom(format!("unknown key '{}' in a triple object, expected s, p and o", key))),
+      };
+      if slot.is_some() {
+        return Err(de::Error::custom(format!("duplicate key '{}' in a triple object", key)))
+      }
+      *slot = Some(map.next_value::<String>()?);
+    }
+    match (s, p, o) {
+      (Some(s), Some(p), Some(o)) => Ok((s, p, o)),
+      (s, p, _) => {
+        let missing = if s.is_none() { "s" } else if p.is_none() { "p" } else { "o" };
+        Err(de::Error::custom(format!("a triple object is missing '{}'", missing)))
+      },
+    }
+  }
+}
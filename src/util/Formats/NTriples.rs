@@ -0,0 +1,105 @@
+use std::io::BufRead;
+use super::super::{Error::Error, Term};
+
+type Triple = (String, String, String);
+
+/*
+N-Triples, one "<s> <p> <o> ." per line.
+IRIs and blank nodes are stored as their text, literals
+through Term::make_literal so plain ones stay plain Strings.
+*/
+pub fn parse(doc: &str) -> Result<Vec<Triple>, Error> {
+  let mut triples = Vec::new();
+  for (n, line) in doc.lines().enumerate() {
+    if let Some(triple) = parse_line(line).map_err(|e| at_line(e, n))? {
+      triples.push(triple);
+    }
+  }
+  Ok(triples)
+}
+pub fn read<R: BufRead>(reader: R) -> Result<Vec<Triple>, Error> {
+  let mut triples = Vec::new();
+  for (n, line) in reader.lines().enumerate() {
+    if let Some(triple) = parse_line(&line?).map_err(|e| at_line(e, n))? {
+      triples.push(triple);
+    }
+  }
+  Ok(triples)
+}
+pub fn write<'a, I>(triples: I) -> String
+  where I: IntoIterator<Item=&'a Triple> {
+  let mut doc = String::new();
+  for (s, p, o) in triples {
+    doc.push_str(&Term::to_ntriples(s));
+    doc.push(' ');
+    doc.push_str(&Term::to_ntriples(p));
+    doc.push(' ');
+    doc.push_str(&Term::to_ntriples(o));
+    doc.push_str(" .\n");
+  }
+  doc
+}
+
+fn at_line(e: Error, n: usize) -> Error {
+  match e {
+    Error::Parse(msg) => Error::Parse(format!("line {}: {}", n + 1, msg)),
+    e => e,
+  }
+}
+pub fn parse_line(line: &str) -> Result<Option<Triple>, Error> {
+  let mut rest = line.trim();
+  if rest.is_empty() || rest.starts_with('#') {
+    return Ok(None)
+  }
+  let s = term(&mut rest)?;
+  let p = term(&mut rest)?;
+  let o = term(&mut rest)?;
+  match rest.trim_start().strip_prefix('.') {
+    Some(after) if after.trim().is_empty() || after.trim().starts_with('#') => Ok(Some((s, p, o))),
+    _ => Err(Error::Parse(format!("Expected '.' but found '{}'", rest.trim()))),
+  }
+}
+/* Reads one term off the front of rest */
+pub fn term(rest: &mut &str) -> Result<String, Error> {
+  let s = rest.trim_start();
+  if let Some(iri) = s.strip_prefix('<') {
+    let end = iri.find('>').ok_or_else(|| Error::Parse("Unterminated IRI".into()))?;
+    *rest = &iri[end+1..];
+    return Ok(Term::unescape(&iri[..end]))
+  }
+  if s.starts_with("_:") {
+    let end = s.find(|c: char| c.is_whitespace()).unwrap_or(s.len());
+    /* A label can't end in '.', that belongs to the statement */
+    let end = if s[..end].ends_with('.') { end - 1 } else { end };
+    *rest = &s[end..];
+    return Ok(s[..end].to_string())
+  }
+  if let Some(lit) = s.strip_prefix('"') {
+    let mut end = None;
+    let mut escaped = false;
+    for (i, c) in lit.char_indices() {
+      match c {
+        '\\' if !escaped => escaped = true,
+        '"' if !escaped => { end = Some(i); break },
+        _ => escaped = false,
+      }
+    }
+    let end = end.ok_or_else(|| Error::Parse("Unterminated literal".into()))?;
+    let lexical = Term::unescape(&lit[..end]);
+    let after = &lit[end+1..];
+    if let Some(lang) = after.strip_prefix('@') {
+      let len = lang.find(|c: char| !(c.is_alphanumeric() || c == '-')).unwrap_or(lang.len());
+      *rest = &lang[len..];
+      return Ok(Term::make_literal(&lexical, Some(&lang[..len]), None))
+    }
+    if let Some(dt) = after.strip_prefix("^^") {
+      let mut dt_rest = dt;
+      let datatype = term(&mut dt_rest)?;
+      *rest = dt_rest;
+      return Ok(Term::make_literal(&lexical, None, Some(&datatype)))
+    }
+    *rest = after;
+    return Ok(Term::make_literal(&lexical, None, None))
+  }
+  Err(Error::Parse(format!("Expected a term at '{}'", s)))
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+use super::{
+  NTriples,
+  super::{Error::Error, Term},
+};
+
+type Triple = (String, String, String);
+
+/*
+RDF Patch (https://afs.github.io/rdf-patch/), one row per line:
+  H id <urn:uuid:...> .     header, ignored
+  TX .                      begin a transaction
+  D <s> <p> <o> .           delete a triple
+  A <s> <p> <o> .           add a triple
+  TC .                      commit it
+  TA .                      abort it, dropping its rows
+Terms are written as in N-Triples. A patch can hold several
+transactions, and rows outside of one count as committed.
+parse() gives back the net (added, removed), so a triple added
+and then deleted again by a later row is in neither.
+*/
+pub fn write(added: &[Triple], removed: &[Triple]) -> String {
+  let mut doc = String::from("TX .\n");
+  for (code, triples) in [("D", removed), ("A", added)].iter() {
+    for (s, p, o) in triples.iter() {
+      doc.push_str(&format!("{} {} {} {} .\n", code, Term::to_ntriples(s), Term::to_ntriples(p), Term::to_ntriples(o)));
+    }
+  }
+  doc.push_str("TC .\n");
+  doc
+}
+pub fn parse(doc: &str) -> Result<(Vec<Triple>, Vec<Triple>), Error> {
+  let mut net = Net::default();
+  /* The open transaction's own net change, folded in by TC and dropped by TA */
+  let mut open: Option<Net> = None;
+  for (n, line) in doc.lines().enumerate() {
+    row(line, &mut net, &mut open).map_err(|e| match e {
+      Error::Parse(msg) => Error::Parse(format!("line {}: {}", n + 1, msg)),
+      e => e,
+    })?;
+  }
+  if open.is_some() {
+    return Err(Error::Parse("Transaction was never committed or aborted".into()))
+  }
+  let (mut added, mut removed): (Vec<Triple>, Vec<Triple>) = (net.added.into_iter().collect(), net.removed.into_iter().collect());
+  added.sort_unstable();
+  removed.sort_unstable();
+  Ok((added, removed))
+}
+fn row(line: &str, net: &mut Net, open: &mut Option<Net>) -> Result<(), Error> {
+  let mut rest = line.trim();
+  if rest.is_empty() || rest.starts_with('#') {
+    return Ok(())
+  }
+  let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+  let code = &rest[..end];
+  rest = &rest[end..];
+  match code {
+    "H" | "PA" | "PD" => return Ok(()),
+    "TX" if open.is_some() => return Err(Error::Parse("Transaction started inside another".into())),
+    "TX" => *open = Some(Net::default()),
+    "TC" => {
+      let tx = open.take().ok_or_else(|| Error::Parse("Commit without a transaction".into()))?;
+      tx.added.into_iter().for_each(|t| net.add(t));
+      tx.removed.into_iter().for_each(|t| net.delete(t));
+    },
+    "TA" => {
+      open.take().ok_or_else(|| Error::Parse("Abort without a transaction".into()))?;
+    },
+    "A" | "D" => {
+      let triple = (NTriples::term(&mut rest)?, NTriples::term(&mut rest)?, NTriples::term(&mut rest)?);
+      let mut graph = rest;
+      if NTriples::term(&mut graph).is_ok() {
+        return Err(Error::Parse("Rows for named graphs can't be applied to a Graph".into()))
+      }
+      let target = open.as_mut().unwrap_or(net);
+      match code {
+        "A" => target.add(triple),
+        _   => target.delete(triple),
+      }
+    },
+    _ => return Err(Error::Parse(format!("Unknown row '{}'", code))),
+  }
+  match rest.trim_start().strip_prefix('.') {
+    Some(after) if after.trim().is_empty() || after.trim().starts_with('#') => Ok(()),
+    _ => Err(Error::Parse(format!("Expected '.' but found '{}'", rest.trim()))),
+  }
+}
+
+#[derive(Default)]
+struct Net {
+  added: HashSet<Triple>,
+  removed: HashSet<Triple>,
+}
+impl Net {
+  fn add(&mut self, triple: Triple) {
+    if !self.removed.remove(&triple) {
+      self.added.insert(triple);
+    }
+  }
+  fn delete(&mut self, triple: Triple) {
+    if !self.added.remove(&triple) {
+      self.removed.insert(triple);
+    }
+  }
+}
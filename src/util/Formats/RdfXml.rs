@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use super::super::{
+  DataStores::{BlankNodes, TripleStore::TripleStore},
+  Error::Error,
+  Term::{self, rdf, TermKind, RDF},
+  Xml::{self, XmlElement, XmlNode},
+};
+
+type Triple = (String, String, String);
+
+/*
+RDF/XML, as in the 2014 RDF 1.1 XML Syntax recommendation.
+Reading walks the element tree with the namespaces, xml:base and
+xml:lang in scope, covering node elements (rdf:Description and
+typed nodes), rdf:about/rdf:ID/rdf:nodeID, property attributes,
+rdf:resource, rdf:datatype, rdf:li, reification through rdf:ID on
+property elements and every rdf:parseType.
+Writing gives one rdf:Description per subject from the spo index,
+with namespaces declared once on rdf:RDF.
+*/
+const XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+pub fn parse(doc: &str, base: Option<&str>) -> Result<Vec<Triple>, Error> {
+  let root = Xml::parse(doc).map_err(Error::Parse)?;
+  let mut used = HashSet::new();
+  node_ids(&root, &mut used);
+  let mut reader = Reader { triples: Vec::new(), used, next_blank: 0, ids: HashSet::new() };
+  let outer = Scope { base: base.map(str::to_string), ..Scope::default() };
+  let scope = outer.enter(&root)?;
+  /* rdf:RDF may be left out around a single node element */
+  if scope.expand(&root.name)? == rdf("RDF") {
+    no_text(&root)?;
+    for node in root.elements() {
+      reader.node_element(node, &scope)?;
+    }
+  }
+  else {
+    reader.node_element(&root, &outer)?;
+  }
+  Ok(reader.triples)
+}
+pub fn write(spo: &TripleStore) -> Result<String, Error> {
+  let subjects: BTreeMap<&String, _> = spo.0.iter().collect();
+  let mut namespaces: BTreeMap<String, String> = BTreeMap::new();
+  namespaces.insert(RDF.to_string(), "rdf".to_string());
+  let mut nodes = BlankLabels::default();
+  let mut body = String::new();
+  for (s, predicates) in subjects {
+    match Term::kind(s) {
+      TermKind::BlankNode => body.push_str(&format!("  <rdf:Description rdf:nodeID=\"{}\">\n", nodes.label(s))),
+      _ => body.push_str(&format!("  <rdf:Description rdf:about=\"{}\">\n", Xml::escape(s))),
+    }
+    let predicates: BTreeMap<&String, _> = predicates.iter().collect();
+    for (p, objects) in predicates {
+      let (ns, local) = split_iri(p).ok_or_else(|| {
+        Error::Serialization(format!("Predicate '{}' can't be written as an XML element name", p))
+      })?;
+      let next = namespaces.len() - 1;
+      let prefix = namespaces.entry(ns.to_string()).or_insert_with(|| format!("ns{}", next));
+      let name = format!("{}:{}", prefix, local);
+      let objects: BTreeSet<&String> = objects.iter().collect();
+      for o in objects {
+        body.push_str(&format!("    <{}", name));
+        match Term::kind(o) {
+          TermKind::Iri => body.push_str(&format!(" rdf:resource=\"{}\"/>\n", Xml::escape(o))),
+          TermKind::BlankNode => body.push_str(&format!(" rdf:nodeID=\"{}\"/>\n", nodes.label(o))),
+          TermKind::Literal => {
+            match Term::literal(o) {
+              (lexical, Some(lang), _) => body.push_str(&format!(" xml:lang=\"{}\">{}", Xml::escape(&lang), Xml::escape(&lexical))),
+              (lexical, None, Some(dt)) if dt == rdf("XMLLiteral") => body.push_str(&format!(" rdf:parseType=\"Literal\">{}", lexical)),
+              (lexical, None, Some(dt)) => body.push_str(&format!(" rdf:datatype=\"{}\">{}", Xml::escape(&dt), Xml::escape(&lexical))),
+              (lexical, None, None) => body.push_str(&format!(">{}", Xml::escape(&lexical))),
+            }
+            body.push_str(&format!("</{}>\n", name));
+          },
+        }
+      }
+    }
+    body.push_str("  </rdf:Description>\n");
+  }
+  let mut doc = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rdf:RDF");
+  let mut declarations: Vec<(&String, &String)> = namespaces.iter().map(|(ns, prefix)| (prefix, ns)).collect();
+  declarations.sort();
+  for (prefix, ns) in declarations {
+    doc.push_str(&format!("\n    xmlns:{}=\"{}\"", prefix, Xml::escape(ns)));
+  }
+  doc.push_str(">\n");
+  doc.push_str(&body);
+  doc.push_str("</rdf:RDF>\n");
+  Ok(doc)
+}
+
+/* rdf:nodeID values already in the document, so fresh ones can't clash */
+fn node_ids(e: &XmlElement, used: &mut HashSet<String>) {
+  for (name, value) in e.attrs.iter() {
+    if name.ends_with("nodeID") {
+      used.insert(format!("_:{}", value));
+    }
+  }
+  e.elements().for_each(|child| node_ids(child, used));
+}
+fn no_text(e: &XmlElement) -> Result<(), Error> {
+  let text = e.children.iter().any(|c| match c {
+    XmlNode::Text(t) => !t.trim().is_empty(),
+    XmlNode::Element(_) => false,
+  });
+  if text {
+    return Err(Error::Parse(format!("Unexpected text inside <{}>", e.name)))
+  }
+  Ok(())
+}
+fn is_name_start(c: char) -> bool {
+  c.is_alphabetic() || c == '_'
+}
+fn is_name_char(c: char) -> bool {
+  is_name_start(c) || c.is_numeric() || c == '-' || c == '.' || c == '\u{B7}'
+}
+fn is_ncname(s: &str) -> bool {
+  s.chars().next().is_some_and(is_name_start) && s.chars().all(is_name_char)
+}
+/* The longest NCName at the end of iri, as (namespace, local name) */
+fn split_iri(iri: &str) -> Option<(&str, &str)> {
+  let mut start = iri.len();
+  for (i, c) in iri.char_indices().rev() {
+    if !is_name_char(c) {
+      break
+    }
+    start = i;
+  }
+  let local = &iri[start..];
+  let skip = local.find(is_name_start)?;
+  let split = start + skip;
+  if split == 0 {
+    return None
+  }
+  Some((&iri[..split], &iri[split..]))
+}
+
+/* Blank node labels that are NCNames are kept, others get a fresh one */
+#[derive(Default)]
+struct BlankLabels {
+  labels: HashMap<String, String>,
+}
+impl BlankLabels {
+  fn label(&mut self, node: &str) -> String {
+    let next = self.labels.len();
+    self.labels.entry(node.to_string())
+               .or_insert_with(|| match &node[2..] {
+                 l if is_ncname(l) && !l.starts_with("genid") => l.to_string(),
+                 _ => format!("genid{}", next),
+               })
+               .clone()
+  }
+}
+
+/* What's in scope for an element */
+#[derive(Clone, Debug, Default)]
+struct Scope {
+  namespaces: HashMap<String, String>,
+  base: Option<String>,
+  lang: Option<String>,
+}
+impl Scope {
+  fn enter(&self, e: &XmlElement) -> Result<Scope, Error> {
+    let mut scope = self.clone();
+    for (name, value) in e.attrs.iter() {
+      if name == "xmlns" {
+        scope.namespaces.insert(String::new(), value.clone());
+      }
+      else if let Some(prefix) = name.strip_prefix("xmlns:") {
+        scope.namespaces.insert(prefix.to_string(), value.clone());
+      }
+    }
+    if let Some(base) = e.attr("xml:base") {
+      /* A base never keeps its fragment */
+      let base = scope.resolve(base);
+      scope.base = Some(base.split('#').next().unwrap_or(&base).to_string());
+    }
+    if let Some(lang) = e.attr("xml:lang") {
+      scope.lang = match lang {
+        "" => None,
+        lang => Some(lang.to_string()),
+      };
+    }
+    Ok(scope)
+  }
+  fn expand(&self, qname: &str) -> Result<String, Error> {
+    let (prefix, local) = qname.split_once(':').unwrap_or(("", qname));
+    if prefix == "xml" {
+      return Ok(format!("{}{}", XML, local))
+    }
+    match self.namespaces.get(prefix) {
+      Some(ns) => Ok(format!("{}{}", ns, local)),
+      None if prefix.is_empty() => Err(Error::Parse(format!("<{}> isn't in a namespace", qname))),
+      None => Err(Error::Parse(format!("Undeclared namespace prefix '{}'", prefix))),
+    }
+  }
+  fn resolve(&self, reference: &str) -> String {
+    match &self.base {
+      Some(base) => Term::resolve(base, reference),
+      None => reference.to_string(),
+    }
+  }
+  /* Attributes that are neither namespace declarations nor xml:*, by expanded name */
+  fn attributes<'a>(&self, e: &'a XmlElement) -> Result<Vec<(String, &'a str)>, Error> {
+    let mut attrs = Vec::new();
+    for (name, value) in e.attrs.iter() {
+      if name == "xmlns" || name.starts_with("xmlns:") || name.starts_with("xml") {
+        continue
+      }
+      let expanded = match name.split_once(':') {
+        Some(_) => self.expand(name)?,
+        /* Unqualified rdf attributes are still seen in older documents */
+        None if ["about", "ID", "resource", "parseType", "nodeID", "datatype", "type"].contains(&name.as_str()) => rdf(name),
+        None => return Err(Error::Parse(format!("Attribute '{}' isn't in a namespace", name))),
+      };
+      attrs.push((expanded, value.as_str()));
+    }
+    Ok(attrs)
+  }
+}
+
+const CORE_SYNTAX: [&str; 7] = ["RDF", "ID", "about", "parseType", "resource", "nodeID", "datatype"];
+const OLD_TERMS: [&str; 3] = ["aboutEach", "aboutEachPrefix", "bagID"];
+fn forbidden(iri: &str, also: &[&str]) -> bool {
+  match iri.strip_prefix(RDF) {
+    Some(name) => CORE_SYNTAX.contains(&name) || OLD_TERMS.contains(&name) || also.contains(&name),
+    None => false,
+  }
+}
+
+struct Reader {
+  triples: Vec<Triple>,
+  used: HashSet<String>,
+  next_blank: usize,
+  /* rdf:ID values seen, which must be unique per base */
+  ids: HashSet<String>,
+}
+impl Reader {
+  fn blank(&mut self) -> String {
+    let used = &self.used;
+    BlankNodes::fresh(&mut self.next_blank, |l| used.contains(l))
+  }
+  fn id(&mut self, value: &str, scope: &Scope) -> Result<String, Error> {
+    if !is_ncname(value) {
+      return Err(Error::Parse(format!("rdf:ID '{}' isn't an XML name", value)))
+    }
+    let iri = scope.resolve(&format!("#{}", value));
+    if !self.ids.insert(iri.clone()) {
+      return Err(Error::Parse(format!("rdf:ID '{}' is used more than once", value)))
+    }
+    Ok(iri)
+  }
+  fn node_id(value: &str) -> Result<String, Error> {
+    if !is_ncname(value) {
+      return Err(Error::Parse(format!("rdf:nodeID '{}' isn't an XML name", value)))
+    }
+    Ok(format!("_:{}", value))
+  }
+  /* Returns the node's subject */
+  fn node_element(&mut self, e: &XmlElement, parent: &Scope) -> Result<String, Error> {
+    let scope = parent.enter(e)?;
+    let name = scope.expand(&e.name)?;
+    if forbidden(&name, &["li"]) {
+      return Err(Error::Parse(format!("<{}> can't be a node element", e.name)))
+    }
+    let attrs = scope.attributes(e)?;
+    let mut subject = None;
+    let mut properties = Vec::new();
+    for (attr, value) in attrs {
+      let node = match attr.strip_prefix(RDF) {
+        Some("about") => Some(scope.resolve(value)),
+        Some("ID") => Some(self.id(value, &scope)?),
+        Some("nodeID") => Some(Reader::node_id(value)?),
+        _ => {
+          properties.push((attr, value));
+          None
+        },
+      };
+      if node.is_some() {
+        if subject.is_some() {
+          return Err(Error::Parse(format!("<{}> has more than one of rdf:about, rdf:ID and rdf:nodeID", e.name)))
+        }
+        subject = node;
+      }
+    }
+    let subject = match subject {
+      Some(s) => s,
+      None => self.blank(),
+    };
+    if name != rdf("Description") {
+      self.triples.push((subject.clone(), rdf("type"), name));
+    }
+    self.property_attributes(&subject, properties, &scope)?;
+    no_text(e)?;
+    let mut li = 1;
+    for property in e.elements() {
+      self.property_element(property, &subject, &scope, &mut li)?;
+    }
+    Ok(subject)
+  }
+  fn property_attributes(&mut self, subject: &str, properties: Vec<(String, &str)>, scope: &Scope) -> Result<(), Error> {
+    for (attr, value) in properties {
+      if forbidden(&attr, &["Description", "li"]) {
+        return Err(Error::Parse(format!("{} can't be a property attribute", attr)))
+      }
+      let object = match attr == rdf("type") {
+        true => scope.resolve(value),
+        false => Term::make_literal(value, scope.lang.as_deref(), None),
+      };
+      self.triples.push((subject.to_string(), attr, object));
+    }
+    Ok(())
+  }
+  fn property_element(&mut self, e: &XmlElement, subject: &str, parent: &Scope, li: &mut usize) -> Result<(), Error> {
+    let scope = parent.enter(e)?;
+    let mut predicate = scope.expand(&e.name)?;
+    if predicate == rdf("li") {
+      predicate = rdf(&format!("_{}", li));
+      *li += 1;
+    }
+    if forbidden(&predicate, &["Description"]) {
+      return Err(Error::Parse(format!("<{}> can't be a property element", e.name)))
+    }
+    let (mut id, mut parse_type, mut resource, mut node_id, mut datatype) = (None, None, None, None, None);
+    let mut properties = Vec::new();
+    for (attr, value) in scope.attributes(e)? {
+      match attr.strip_prefix(RDF) {
+        Some("ID") => id = Some(self.id(value, &scope)?),
+        Some("parseType") => parse_type = Some(value),
+        Some("resource") => resource = Some(scope.resolve(value)),
+        Some("nodeID") => node_id = Some(Reader::node_id(value)?),
+        Some("datatype") => datatype = Some(scope.resolve(value)),
+        _ => properties.push((attr, value)),
+      }
+    }
+    let children: Vec<&XmlElement> = e.elements().collect();
+    let object = match parse_type {
+      Some("Resource") => {
+        let object = self.blank();
+        no_text(e)?;
+        let mut li = 1;
+        for property in children {
+          self.property_element(property, &object, &scope, &mut li)?;
+        }
+        object
+      },
+      Some("Collection") => {
+        no_text(e)?;
+        let mut items = Vec::new();
+        for node in children {
+          items.push(self.node_element(node, &scope)?);
+        }
+        let mut head = rdf("nil");
+        for item in items.into_iter().rev() {
+          let node = self.blank();
+          self.triples.push((node.clone(), rdf("first"), item));
+          self.triples.push((node.clone(), rdf("rest"), head));
+          head = node;
+        }
+        head
+      },
+      /* "Literal", and any other parseType, keeps the content as XML */
+      Some(_) => Term::make_literal(&xml_literal(e, &scope), None, Some(&rdf("XMLLiteral"))),
+      None if !children.is_empty() => {
+        no_text(e)?;
+        if children.len() > 1 || resource.is_some() || node_id.is_some() || !properties.is_empty() {
+          return Err(Error::Parse(format!("<{}> must hold exactly one node element and nothing else", e.name)))
+        }
+        self.node_element(children[0], &scope)?
+      },
+      None if datatype.is_some() || !e.children.is_empty() => {
+        if resource.is_some() || node_id.is_some() || !properties.is_empty() {
+          return Err(Error::Parse(format!("<{}> can't have both text and a resource", e.name)))
+        }
+        match datatype {
+          Some(dt) => Term::make_literal(&e.text(), None, Some(&dt)),
+          None => Term::make_literal(&e.text(), scope.lang.as_deref(), None),
+        }
+      },
+      /* An empty property element */
+      None => {
+        if resource.is_none() && node_id.is_none() && properties.is_empty() {
+          Term::make_literal("", scope.lang.as_deref(), None)
+        }
+        else {
+          let object = match (resource, node_id) {
+            (Some(_), Some(_)) => return Err(Error::Parse(format!("<{}> has both rdf:resource and rdf:nodeID", e.name))),
+            (Some(r), None) => r,
+            (None, Some(n)) => n,
+            (None, None) => self.blank(),
+          };
+          self.property_attributes(&object, properties, &scope)?;
+          object
+        }
+      },
+    };
+    self.triples.push((subject.to_string(), predicate.clone(), object.clone()));
+    if let Some(statement) = id {
+      self.triples.push((statement.clone(), rdf("type"), rdf("Statement")));
+      self.triples.push((statement.clone(), rdf("subject"), subject.to_string()));
+      self.triples.push((statement.clone(), rdf("predicate"), predicate));
+      self.triples.push((statement, rdf("object"), object));
+    }
+    Ok(())
+  }
+}
+
+/*
+The content of a parseType="Literal" element, written back out
+with the namespaces each top level element uses declared on it,
+much as exclusive canonicalisation would
+*/
+fn xml_literal(e: &XmlElement, scope: &Scope) -> String {
+  let mut out = String::new();
+  for child in e.children.iter() {
+    match child {
+      XmlNode::Text(t) => out.push_str(&escape_text(t)),
+      XmlNode::Element(child) => write_element(child, scope, true, &mut out),
+    }
+  }
+  out
+}
+fn write_element(e: &XmlElement, scope: &Scope, top: bool, out: &mut String) {
+  out.push('<');
+  out.push_str(&e.name);
+  let mut declared: BTreeMap<&str, String> = BTreeMap::new();
+  if top {
+    let prefixes = std::iter::once(e.name.as_str()).chain(e.attrs.iter().map(|(n, _)| n.as_str()))
+                                                   .filter(|n| !n.starts_with("xmlns"))
+                                                   .map(|n| n.split_once(':').map_or("", |(p, _)| p))
+                                                   .filter(|p| *p != "xml");
+    for prefix in prefixes {
+      if let Some(ns) = scope.namespaces.get(prefix) {
+        declared.insert(prefix, ns.clone());
+      }
+    }
+  }
+  for (name, value) in e.attrs.iter() {
+    if name == "xmlns" {
+      declared.insert("", value.clone());
+    }
+    else if let Some(prefix) = name.strip_prefix("xmlns:") {
+      declared.insert(prefix, value.clone());
+    }
+  }
+  for (prefix, ns) in declared.iter() {
+    match *prefix {
+      "" => out.push_str(&format!(" xmlns=\"{}\"", Xml::escape(ns))),
+      p => out.push_str(&format!(" xmlns:{}=\"{}\"", p, Xml::escape(ns))),
+    }
+  }
+  for (name, value) in e.attrs.iter().filter(|(n, _)| !n.starts_with("xmlns")) {
+    out.push_str(&format!(" {}=\"{}\"", name, Xml::escape(value)));
+  }
+  out.push('>');
+  for child in e.children.iter() {
+    match child {
+      XmlNode::Text(t) => out.push_str(&escape_text(t)),
+      XmlNode::Element(child) => write_element(child, scope, false, out),
+    }
+  }
+  out.push_str(&format!("</{}>", e.name));
+}
+fn escape_text(s: &str) -> String {
+  s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
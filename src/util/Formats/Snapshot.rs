@@ -0,0 +1,280 @@
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  convert::TryFrom,
+};
+use super::super::{DataStores::TripleStore::TripleStore, Error::Error};
+
+/*
+Oji's own binary snapshot of a Graph.
+
+  "OJIS" | version: u16 LE | reserved: u16
+  section*: tag: u8 | length: u64 LE | payload | crc32(payload): u32 LE
+
+Sections, in order:
+  'D' dictionary: count, then each term as the number of bytes it
+      shares with the one before, then length + UTF-8 bytes of the
+      rest; sorted, so ids are stable for equal Graphs and IRIs
+      only spell out what differs from their neighbour
+  'T' triples: the spo tree as ids,
+        subject count, then per subject: id, predicate count,
+        per predicate: id, object count, object ids
+      with each run of ids delta-encoded against the previous one
+  'E' end of snapshot, empty
+All counts, lengths and ids inside sections are LEB128 varints.
+Sections a reader doesn't know are checked and skipped, so new
+optional ones don't need a version bump; anything else does, and
+gets a reader in read() so older snapshots can still be upgraded.
+  1: the dictionary spelled out every term in full
+  2: shared prefixes
+Loading decodes every term once and hands back ids, which the
+Graph builds its three orderings from in parallel; that's a pass
+of hash inserts per ordering, not a memcpy.
+*/
+pub const MAGIC: &[u8; 4] = b"OJIS";
+pub const VERSION: u16 = 2;
+
+const DICTIONARY: u8 = b'D';
+const TRIPLES: u8 = b'T';
+const END: u8 = b'E';
+
+pub fn write(store: &TripleStore) -> Vec<u8> {
+  /* Sorted throughout so equal Graphs give identical snapshots */
+  let mut terms: BTreeSet<&str> = BTreeSet::new();
+  let mut tree: BTreeMap<&str, BTreeMap<&str, Vec<&str>>> = BTreeMap::new();
+  for (s, predicates) in store.0.iter() {
+    terms.insert(s);
+    let branch = tree.entry(s).or_default();
+    for (p, objects) in predicates.iter() {
+      terms.insert(p);
+      let leaves = branch.entry(p).or_default();
+      for o in objects.iter() {
+        terms.insert(o);
+        leaves.push(o);
+      }
+    }
+  }
+  let ids: HashMap<&str, u64> = terms.iter().enumerate().map(|(i, t)| (*t, i as u64)).collect();
+
+  let mut dictionary = Vec::new();
+  varint(&mut dictionary, terms.len() as u64);
+  let mut last: &[u8] = &[];
+  for term in terms.iter() {
+    let term = term.as_bytes();
+    let shared = last.iter().zip(term.iter()).take_while(|(a, b)| a == b).count();
+    varint(&mut dictionary, shared as u64);
+    varint(&mut dictionary, (term.len() - shared) as u64);
+    dictionary.extend_from_slice(&term[shared..]);
+    last = term;
+  }
+
+  let mut triples = Vec::new();
+  varint(&mut triples, tree.len() as u64);
+  let mut last_s = 0;
+  for (s, predicates) in tree.iter() {
+    delta(&mut triples, &mut last_s, ids[s]);
+    varint(&mut triples, predicates.len() as u64);
+    let mut last_p = 0;
+    for (p, objects) in predicates.iter() {
+      delta(&mut triples, &mut last_p, ids[p]);
+      varint(&mut triples, objects.len() as u64);
+      let mut objects: Vec<u64> = objects.iter().map(|o| ids[o]).collect();
+      objects.sort_unstable();
+      let mut last_o = 0;
+      for o in objects {
+        delta(&mut triples, &mut last_o, o);
+      }
+    }
+  }
+
+  let mut doc = Vec::with_capacity(8 + dictionary.len() + triples.len() + 3 * 13);
+  doc.extend_from_slice(MAGIC);
+  doc.extend_from_slice(&VERSION.to_le_bytes());
+  doc.extend_from_slice(&[0, 0]);
+  section(&mut doc, DICTIONARY, &dictionary);
+  section(&mut doc, TRIPLES, &triples);
+  section(&mut doc, END, &[]);
+  doc
+}
+/* A snapshot's dictionary, and its triples as ids into it */
+pub type Contents = (Vec<String>, Vec<(usize, usize, usize)>);
+pub fn read(doc: &[u8]) -> Result<Contents, Error> {
+  if doc.len() < 8 || &doc[..4] != MAGIC {
+    return Err(Error::Parse("Not an Oji snapshot".into()))
+  }
+  let version = u16::from_le_bytes([doc[4], doc[5]]);
+  match version {
+    0 => Err(Error::Parse("Snapshot format version 0 is invalid".into())),
+    1 => read_sections(&doc[8..], dictionary_v1),
+    2 => read_sections(&doc[8..], dictionary),
+    _ => Err(Error::Parse(format!("Snapshot format version {} is newer than this build of Oji reads (up to {})",
+                                  version, VERSION))),
+  }
+}
+pub fn version(doc: &[u8]) -> Option<u16> {
+  match doc.get(..6) {
+    Some(header) if &header[..4] == MAGIC => Some(u16::from_le_bytes([header[4], header[5]])),
+    _ => None,
+  }
+}
+
+/* Every version so far shares its sections, only the dictionary has changed */
+fn read_sections(mut rest: &[u8], dictionary: fn(&[u8]) -> Result<Vec<String>, Error>) -> Result<Contents, Error> {
+  let mut terms: Vec<String> = Vec::new();
+  let mut triples = None;
+  loop {
+    let (tag, payload, after) = next_section(rest)?;
+    rest = after;
+    match tag {
+      DICTIONARY => terms = dictionary(payload)?,
+      TRIPLES => triples = Some(payload),
+      END => break,
+      _ => {},
+    }
+  }
+  let mut reader = Reader { bytes: triples.unwrap_or(&[]), pos: 0 };
+  let mut ids = Vec::new();
+  if reader.bytes.is_empty() {
+    return Ok((terms, ids))
+  }
+  let count = terms.len() as u64;
+  let id = |reader: &mut Reader, last: u64| reader.id(last)
+                                                  .and_then(|id| if id < count { Ok(id) } else { Err(corrupt("term id out of range")) });
+  let mut s = 0;
+  for _ in 0..reader.varint()? {
+    s = id(&mut reader, s)?;
+    let mut p = 0;
+    for _ in 0..reader.varint()? {
+      p = id(&mut reader, p)?;
+      let mut o = 0;
+      for _ in 0..reader.varint()? {
+        o = id(&mut reader, o)?;
+        ids.push((s as usize, p as usize, o as usize));
+      }
+    }
+  }
+  Ok((terms, ids))
+}
+fn next_section(doc: &[u8]) -> Result<(u8, &[u8], &[u8]), Error> {
+  if doc.len() < 9 {
+    return Err(corrupt("truncated section header"))
+  }
+  let tag = doc[0];
+  let mut len = [0; 8];
+  len.copy_from_slice(&doc[1..9]);
+  let len = u64::from_le_bytes(len);
+  let end = usize::try_from(len).ok()
+                                .and_then(|len| len.checked_add(9))
+                                .filter(|&end| end < doc.len() && doc.len() - end >= 4)
+                                .ok_or_else(|| corrupt("section runs past the end"))?;
+  let payload = &doc[9..end];
+  let mut checksum = [0; 4];
+  checksum.copy_from_slice(&doc[end..end + 4]);
+  if u32::from_le_bytes(checksum) != crc32(payload) {
+    return Err(Error::Parse(format!("Snapshot section '{}' failed its checksum", tag as char)))
+  }
+  Ok((tag, payload, &doc[end + 4..]))
+}
+fn dictionary(payload: &[u8]) -> Result<Vec<String>, Error> {
+  let mut reader = Reader { bytes: payload, pos: 0 };
+  let count = reader.varint()? as usize;
+  /* Every term takes at least its two length bytes */
+  let mut terms: Vec<String> = Vec::with_capacity(count.min(payload.len() / 2));
+  for _ in 0..count {
+    let shared = reader.varint()? as usize;
+    let len = reader.varint()? as usize;
+    let last = terms.last().map(|t| t.as_bytes()).unwrap_or(&[]);
+    let prefix = last.get(..shared).ok_or_else(|| corrupt("term shares more than the one before has"))?;
+    let mut term = Vec::with_capacity(shared.saturating_add(len).min(payload.len()));
+    term.extend_from_slice(prefix);
+    term.extend_from_slice(reader.take(len)?);
+    terms.push(String::from_utf8(term).map_err(|_| corrupt("term isn't valid UTF-8"))?);
+  }
+  Ok(terms)
+}
+/* Version 1 spelled out every term in full */
+fn dictionary_v1(payload: &[u8]) -> Result<Vec<String>, Error> {
+  let mut reader = Reader { bytes: payload, pos: 0 };
+  let count = reader.varint()? as usize;
+  /* Every term takes at least its length byte */
+  let mut terms = Vec::with_capacity(count.min(payload.len()));
+  for _ in 0..count {
+    let len = reader.varint()? as usize;
+    let bytes = reader.take(len)?;
+    terms.push(std::str::from_utf8(bytes).map_err(|_| corrupt("term isn't valid UTF-8"))?.to_string());
+  }
+  Ok(terms)
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+impl<'a> Reader<'a> {
+  fn varint(&mut self) -> Result<u64, Error> {
+    let mut n: u64 = 0;
+    for shift in (0..64).step_by(7) {
+      let b = *self.bytes.get(self.pos).ok_or_else(|| corrupt("truncated varint"))?;
+      self.pos += 1;
+      n |= ((b & 0x7F) as u64) << shift;
+      if b & 0x80 == 0 {
+        return Ok(n)
+      }
+    }
+    Err(corrupt("varint overflows"))
+  }
+  /* The next delta-encoded id */
+  fn id(&mut self, last: u64) -> Result<u64, Error> {
+    last.checked_add(self.varint()?).ok_or_else(|| corrupt("term id out of range"))
+  }
+  fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+    let end = self.pos.checked_add(len)
+                      .filter(|&end| end <= self.bytes.len())
+                      .ok_or_else(|| corrupt("term runs past the end"))?;
+    let bytes = &self.bytes[self.pos..end];
+    self.pos = end;
+    Ok(bytes)
+  }
+}
+fn corrupt(what: &str) -> Error {
+  Error::Parse(format!("Corrupt snapshot: {}", what))
+}
+
+fn section(doc: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+  doc.push(tag);
+  doc.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+  doc.extend_from_slice(payload);
+  doc.extend_from_slice(&crc32(payload).to_le_bytes());
+}
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+  while n >= 0x80 {
+    out.push(n as u8 | 0x80);
+    n >>= 7;
+  }
+  out.push(n as u8);
+}
+fn delta(out: &mut Vec<u8>, last: &mut u64, id: u64) {
+  varint(out, id - *last);
+  *last = id;
+}
+
+/* CRC-32 (IEEE), as used by zip and PNG */
+const CRC_TABLE: [u32; 256] = crc_table();
+const fn crc_table() -> [u32; 256] {
+  let mut table = [0; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut c = i as u32;
+    let mut k = 0;
+    while k < 8 {
+      c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+      k += 1;
+    }
+    table[i] = c;
+    i += 1;
+  }
+  table
+}
+pub fn crc32(bytes: &[u8]) -> u32 {
+  !bytes.iter().fold(!0, |c, &b| CRC_TABLE[((c ^ b as u32) & 0xFF) as usize] ^ (c >> 8))
+}
+
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use super::{
+  super::{DataStores::TripleStore::TripleStore, Error::Error, Term},
+  Turtle::{self, Parser},
+};
+
+type Quad = (String, String, String, Option<String>);
+
+/*
+TriG: Turtle statements, optionally wrapped in graph blocks.
+  <g> { ... }, GRAPH <g> { ... }, _:g { ... } name a graph,
+  { ... } and statements outside any block are the default graph.
+Prefixes, the base and blank node labels hold for the whole document,
+so the same _:b in two graphs is the same node.
+*/
+pub fn parse(doc: &str, base: Option<&str>) -> Result<Vec<Quad>, Error> {
+  let mut parser = Parser::new(doc, base);
+  let mut quads = Vec::new();
+  while !parser.at_end() {
+    if parser.directive()? {
+      continue
+    }
+    let graph = match parser.peek() {
+      Some('{') => None,
+      Some('(') => {
+        statement(&mut parser)?;
+        drain(&mut parser, &mut quads, None);
+        continue
+      },
+      _ if parser.keyword("GRAPH") => Some(parser.label()?),
+      _ => {
+        /* A graph's name, or the subject of a statement in the default graph */
+        if parser.peek() == Some('[') && !is_empty_brackets(&mut parser) {
+          statement(&mut parser)?;
+          drain(&mut parser, &mut quads, None);
+          continue
+        }
+        let term = parser.label()?;
+        if parser.peek() != Some('{') {
+          parser.predicate_objects(&term)?;
+          parser.expect(".")?;
+          drain(&mut parser, &mut quads, None);
+          continue
+        }
+        Some(term)
+      },
+    };
+    parser.expect("{")?;
+    while !parser.eat("}") {
+      if parser.at_end() {
+        return Err(parser.error("Unterminated graph block"))
+      }
+      parser.triples()?;
+      /* The last '.' in a block may be left off */
+      if !parser.eat(".") && parser.peek() != Some('}') {
+        return Err(parser.error("Expected '.' or '}'"))
+      }
+    }
+    drain(&mut parser, &mut quads, graph);
+  }
+  Ok(quads)
+}
+/* The default graph first, then each named graph by name */
+pub fn write(default: &TripleStore, named: &BTreeMap<&String, &TripleStore>) -> String {
+  let mut doc = Turtle::write(default);
+  for (name, store) in named.iter() {
+    if !doc.is_empty() {
+      doc.push('\n');
+    }
+    doc.push_str(&Term::to_ntriples(name));
+    doc.push_str(" {\n");
+    Turtle::write_triples(&mut doc, store, "  ");
+    doc.push_str("}\n");
+  }
+  doc
+}
+
+fn statement(parser: &mut Parser) -> Result<(), Error> {
+  parser.triples()?;
+  parser.expect(".")
+}
+fn drain(parser: &mut Parser, quads: &mut Vec<Quad>, graph: Option<String>) {
+  quads.extend(parser.triples.drain(..).map(|(s, p, o)| (s, p, o, graph.clone())));
+}
+/* "[]" names a graph when a block follows; "[ ... ]" never does */
+fn is_empty_brackets(parser: &mut Parser) -> bool {
+  parser.lookahead(|p| p.eat("[") && p.eat("]") && p.peek() == Some('{'))
+}
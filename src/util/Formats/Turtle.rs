@@ -0,0 +1,507 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use super::super::{
+  DataStores::{BlankNodes, TripleStore::TripleStore},
+  Error::Error,
+  Term::{self, rdf, xsd, RDF},
+};
+
+type Triple = (String, String, String);
+
+/*
+Turtle, as in the RDF 1.1 recommendation: @prefix/@base and their
+SPARQL spellings, prefixed names, 'a', every string form, numbers
+and booleans, ';' and ',' lists, [] blank nodes and () collections.
+The Parser is shared with TriG, which wraps the same statements
+in graph blocks, so it's driven a statement at a time and collects
+triples for its caller to drain.
+*/
+
+pub fn parse(doc: &str, base: Option<&str>) -> Result<Vec<Triple>, Error> {
+  let mut parser = Parser::new(doc, base);
+  while !parser.at_end() {
+    if parser.directive()? {
+      continue
+    }
+    parser.triples()?;
+    parser.expect(".")?;
+  }
+  Ok(parser.triples)
+}
+pub fn write(store: &TripleStore) -> String {
+  let mut doc = String::new();
+  write_triples(&mut doc, store, "");
+  doc
+}
+/* Each subject's triples as one statement, every line led by indent */
+pub fn write_triples(doc: &mut String, store: &TripleStore, indent: &str) {
+  let subjects: BTreeMap<&String, _> = store.0.iter().collect();
+  for (s, predicates) in subjects {
+    doc.push_str(indent);
+    doc.push_str(&Term::to_ntriples(s));
+    let predicates: BTreeMap<&String, _> = predicates.iter().collect();
+    for (i, (p, objects)) in predicates.into_iter().enumerate() {
+      if i > 0 {
+        doc.push_str(" ;\n");
+        doc.push_str(indent);
+        doc.push_str("   ");
+      }
+      match p.strip_prefix(RDF) {
+        Some("type") => doc.push_str(" a"),
+        _ => {
+          doc.push(' ');
+          doc.push_str(&Term::to_ntriples(p));
+        },
+      }
+      let objects: BTreeSet<&String> = objects.iter().collect();
+      for (j, o) in objects.into_iter().enumerate() {
+        if j > 0 {
+          doc.push(',');
+        }
+        doc.push(' ');
+        doc.push_str(&Term::to_ntriples(o));
+      }
+    }
+    doc.push_str(" .\n");
+  }
+}
+
+fn is_name_start(c: char) -> bool {
+  c.is_alphabetic() || c == '_'
+}
+fn is_name_char(c: char) -> bool {
+  is_name_start(c) || c.is_numeric() || c == '-' || c == '\u{B7}'
+}
+
+pub struct Parser<'a> {
+  doc: &'a str,
+  pos: usize,
+  base: Option<String>,
+  prefixes: HashMap<String, String>,
+  /* Every "_:label" in the document, so fresh ones can't clash */
+  reserved: HashSet<&'a str>,
+  next_blank: usize,
+  pub triples: Vec<Triple>,
+}
+impl<'a> Parser<'a> {
+  pub fn new(doc: &'a str, base: Option<&str>) -> Self {
+    let reserved = doc.match_indices("_:")
+                      .map(|(i, _)| {
+                        let rest = &doc[i..];
+                        let end = rest[2..].find(|c: char| !(is_name_char(c) || c == '.'))
+                                           .map_or(rest.len(), |e| e + 2);
+                        rest[..end].trim_end_matches('.')
+                      })
+                      .collect();
+    Parser {
+      doc,
+      pos: 0,
+      base: base.map(str::to_string),
+      prefixes: HashMap::new(),
+      reserved,
+      next_blank: 0,
+      triples: Vec::new(),
+    }
+  }
+  pub fn error(&self, msg: &str) -> Error {
+    let line = self.doc[..self.pos].matches('\n').count() + 1;
+    Error::Parse(format!("line {}: {}", line, msg))
+  }
+  fn rest(&self) -> &'a str {
+    &self.doc[self.pos..]
+  }
+  /* Whitespace and comments */
+  fn skip(&mut self) {
+    loop {
+      let rest = self.rest();
+      let trimmed = rest.trim_start();
+      self.pos += rest.len() - trimmed.len();
+      if !trimmed.starts_with('#') {
+        return
+      }
+      self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+    }
+  }
+  pub fn at_end(&mut self) -> bool {
+    self.skip();
+    self.pos == self.doc.len()
+  }
+  pub fn peek(&mut self) -> Option<char> {
+    self.skip();
+    self.rest().chars().next()
+  }
+  pub fn eat(&mut self, token: &str) -> bool {
+    self.skip();
+    if self.rest().starts_with(token) {
+      self.pos += token.len();
+      return true
+    }
+    false
+  }
+  pub fn expect(&mut self, token: &str) -> Result<(), Error> {
+    if self.eat(token) {
+      return Ok(())
+    }
+    Err(self.error(&format!("Expected '{}'", token)))
+  }
+  /* A case-insensitive bare word, such as PREFIX or GRAPH */
+  pub fn keyword(&mut self, word: &str) -> bool {
+    self.skip();
+    let rest = self.rest();
+    let matches = rest.get(..word.len()).is_some_and(|w| w.eq_ignore_ascii_case(word))
+                  && !rest[word.len()..].starts_with(|c: char| is_name_char(c) || c == ':');
+    if matches {
+      self.pos += word.len();
+    }
+    matches
+  }
+  /* Runs f and then rewinds, to look past the next few tokens */
+  pub fn lookahead<F: FnOnce(&mut Self) -> bool>(&mut self, f: F) -> bool {
+    let pos = self.pos;
+    let seen = f(self);
+    self.pos = pos;
+    seen
+  }
+  pub fn blank(&mut self) -> String {
+    let reserved = &self.reserved;
+    BlankNodes::fresh(&mut self.next_blank, |l| reserved.contains(l))
+  }
+
+  /* Directives */
+  pub fn directive(&mut self) -> Result<bool, Error> {
+    let (sparql, is_prefix) = if self.eat("@prefix") {
+      (false, true)
+    }
+    else if self.eat("@base") {
+      (false, false)
+    }
+    else if self.keyword("PREFIX") {
+      (true, true)
+    }
+    else if self.keyword("BASE") {
+      (true, false)
+    }
+    else {
+      return Ok(false)
+    };
+    if is_prefix {
+      self.skip();
+      let prefix = self.prefix()?;
+      self.expect(":")?;
+      let iri = self.iri_ref()?;
+      self.prefixes.insert(prefix, iri);
+    }
+    else {
+      self.base = Some(self.iri_ref()?);
+    }
+    if !sparql {
+      self.expect(".")?;
+    }
+    Ok(true)
+  }
+
+  /* Statements */
+  /* A subject and its predicates, without the closing '.' */
+  pub fn triples(&mut self) -> Result<(), Error> {
+    match self.peek() {
+      Some('[') => {
+        let subject = self.blank_node_property_list()?;
+        /* "[ ... ] ." stands alone */
+        if !matches!(self.peek(), Some('.') | Some('}') | None) {
+          self.predicate_objects(&subject)?;
+        }
+        Ok(())
+      },
+      _ => {
+        let subject = self.subject()?;
+        self.predicate_objects(&subject)
+      },
+    }
+  }
+  pub fn subject(&mut self) -> Result<String, Error> {
+    match self.peek() {
+      Some('(') => self.collection(),
+      Some('[') => self.blank_node_property_list(),
+      _ => self.label(),
+    }
+  }
+  /* An IRI or blank node, which is all a graph name can be */
+  pub fn label(&mut self) -> Result<String, Error> {
+    self.skip();
+    if self.rest().starts_with("_:") {
+      return self.blank_label()
+    }
+    if self.rest().starts_with('[') {
+      self.pos += 1;
+      self.expect("]")?;
+      return Ok(self.blank())
+    }
+    self.iri()
+  }
+  pub fn predicate_objects(&mut self, subject: &str) -> Result<(), Error> {
+    loop {
+      let predicate = if self.keyword("a") {
+        rdf("type")
+      }
+      else {
+        self.iri()?
+      };
+      loop {
+        let object = self.object()?;
+        self.triples.push((subject.to_string(), predicate.clone(), object));
+        if !self.eat(",") {
+          break
+        }
+      }
+      if !self.eat(";") {
+        return Ok(())
+      }
+      while self.eat(";") {}
+      /* A trailing ';' is allowed */
+      if matches!(self.peek(), Some('.') | Some(']') | Some('}') | None) {
+        return Ok(())
+      }
+    }
+  }
+  fn object(&mut self) -> Result<String, Error> {
+    match self.peek() {
+      Some('(') => self.collection(),
+      Some('[') => self.blank_node_property_list(),
+      Some('"') | Some('\'') => self.literal(),
+      Some(c) if c.is_ascii_digit() || c == '+' || c == '-' || c == '.' => self.number(),
+      _ if self.keyword("true") => Ok(Term::make_literal("true", None, Some(&xsd("boolean")))),
+      _ if self.keyword("false") => Ok(Term::make_literal("false", None, Some(&xsd("boolean")))),
+      _ => self.label(),
+    }
+  }
+  fn blank_node_property_list(&mut self) -> Result<String, Error> {
+    self.expect("[")?;
+    let node = self.blank();
+    if self.eat("]") {
+      return Ok(node)
+    }
+    self.predicate_objects(&node)?;
+    self.expect("]")?;
+    Ok(node)
+  }
+  fn collection(&mut self) -> Result<String, Error> {
+    self.expect("(")?;
+    let mut items = Vec::new();
+    while !self.eat(")") {
+      if self.at_end() {
+        return Err(self.error("Unterminated collection"))
+      }
+      items.push(self.object()?);
+    }
+    let mut head = rdf("nil");
+    for item in items.into_iter().rev() {
+      let node = self.blank();
+      self.triples.push((node.clone(), rdf("first"), item));
+      self.triples.push((node.clone(), rdf("rest"), head));
+      head = node;
+    }
+    Ok(head)
+  }
+
+  /* Terms */
+  fn iri(&mut self) -> Result<String, Error> {
+    self.skip();
+    if self.rest().starts_with('<') {
+      return self.iri_ref()
+    }
+    let prefix = self.prefix()?;
+    if !self.rest().starts_with(':') {
+      return Err(self.error("Expected an IRI or prefixed name"))
+    }
+    self.pos += 1;
+    let namespace = match self.prefixes.get(&prefix) {
+      Some(ns) => ns.clone(),
+      None => return Err(self.error(&format!("Undeclared prefix '{}:'", prefix))),
+    };
+    Ok(format!("{}{}", namespace, self.local_name()?))
+  }
+  fn iri_ref(&mut self) -> Result<String, Error> {
+    self.skip();
+    if !self.rest().starts_with('<') {
+      return Err(self.error("Expected an IRI"))
+    }
+    let end = match self.rest().find('>') {
+      Some(end) => end,
+      None => return Err(self.error("Unterminated IRI")),
+    };
+    let iri = Term::unescape(&self.rest()[1..end]);
+    if iri.contains(|c: char| c.is_whitespace() || "<>\"{}|^`".contains(c)) {
+      return Err(self.error(&format!("'{}' isn't a valid IRI", iri)))
+    }
+    self.pos += end + 1;
+    Ok(match &self.base {
+      Some(base) => Term::resolve(base, &iri),
+      None => iri,
+    })
+  }
+  /* The part of a prefixed name before ':', which may be empty */
+  fn prefix(&mut self) -> Result<String, Error> {
+    let rest = self.rest();
+    if !rest.starts_with(|c: char| c == ':' || c.is_alphabetic()) {
+      return Err(self.error("Expected a prefixed name"))
+    }
+    let len = rest.find(|c: char| !(is_name_char(c) || c == '.')).unwrap_or(rest.len());
+    let prefix = rest[..len].trim_end_matches('.');
+    self.pos += prefix.len();
+    Ok(prefix.to_string())
+  }
+  fn local_name(&mut self) -> Result<String, Error> {
+    let rest = self.rest();
+    let mut name = String::new();
+    let (mut len, mut chars) = (0, rest.chars());
+    /* Where the name ends if it's followed by unescaped dots, which end the statement instead */
+    let mut keep = (0, 0);
+    while let Some(c) = chars.next() {
+      match c {
+        '\\' => match chars.next() {
+          Some(e) if "_~.-!$&'()*+,;=/?#@%".contains(e) => {
+            name.push(e);
+            len += 1 + e.len_utf8();
+          },
+          _ => return Err(self.error("Invalid escape in a prefixed name")),
+        },
+        /* %XX stays encoded */
+        c if is_name_char(c) || c == ':' || c == '.' || c == '%' => {
+          name.push(c);
+          len += c.len_utf8();
+          if c == '.' {
+            continue
+          }
+        },
+        _ => break,
+      }
+      keep = (name.len(), len);
+    }
+    name.truncate(keep.0);
+    self.pos += keep.1;
+    Ok(name)
+  }
+  fn blank_label(&mut self) -> Result<String, Error> {
+    let rest = self.rest();
+    let len = rest[2..].find(|c: char| !(is_name_char(c) || c == '.'))
+                       .map_or(rest.len(), |e| e + 2);
+    let label = rest[..len].trim_end_matches('.');
+    if label.len() == 2 {
+      return Err(self.error("Empty blank node label"))
+    }
+    self.pos += label.len();
+    Ok(label.to_string())
+  }
+  fn literal(&mut self) -> Result<String, Error> {
+    let rest = self.rest();
+    let quote = &rest[..1];
+    let long = format!("{0}{0}{0}", quote);
+    let (delimiter, start) = match rest.starts_with(&long) {
+      true => (long.as_str(), 3),
+      false => (quote, 1),
+    };
+    let mut end = None;
+    let mut escaped = false;
+    for (i, c) in rest[start..].char_indices() {
+      if escaped {
+        escaped = false;
+        continue
+      }
+      match c {
+        '\\' => escaped = true,
+        '\n' | '\r' if start == 1 => return Err(self.error("Line break in a short string")),
+        _ if rest[start + i..].starts_with(delimiter) => {
+          /* """a"""" ends after the last possible quote */
+          let mut i = i;
+          while rest[start + i + delimiter.len()..].starts_with(quote) && start == 3 {
+            i += 1;
+          }
+          end = Some(start + i);
+          break
+        },
+        _ => {},
+      }
+    }
+    let end = match end {
+      Some(end) => end,
+      None => return Err(self.error("Unterminated string")),
+    };
+    let lexical = unescape(&rest[start..end]).map_err(|e| self.error(&e))?;
+    self.pos += end + delimiter.len();
+    if self.rest().starts_with('@') {
+      let lang = &self.rest()[1..];
+      let len = lang.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-')).unwrap_or(lang.len());
+      if len == 0 {
+        return Err(self.error("Empty language tag"))
+      }
+      let lang = lang[..len].to_string();
+      self.pos += len + 1;
+      return Ok(Term::make_literal(&lexical, Some(&lang), None))
+    }
+    if self.rest().starts_with("^^") {
+      self.pos += 2;
+      let datatype = self.iri()?;
+      return Ok(Term::make_literal(&lexical, None, Some(&datatype)))
+    }
+    Ok(Term::make_literal(&lexical, None, None))
+  }
+  fn number(&mut self) -> Result<String, Error> {
+    let rest = self.rest();
+    let bytes = rest.as_bytes();
+    let digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut len = if rest.starts_with(['+', '-']) { 1 } else { 0 };
+    let whole = digits(len);
+    len += whole;
+    let mut datatype = "integer";
+    if bytes.get(len) == Some(&b'.') && digits(len + 1) > 0 {
+      len += 1 + digits(len + 1);
+      datatype = "decimal";
+    }
+    if matches!(bytes.get(len), Some(b'e') | Some(b'E')) {
+      let mut exp = len + 1;
+      if matches!(bytes.get(exp), Some(b'+') | Some(b'-')) {
+        exp += 1;
+      }
+      if digits(exp) == 0 {
+        return Err(self.error("Expected an exponent"))
+      }
+      len = exp + digits(exp);
+      datatype = "double";
+    }
+    if whole == 0 && datatype == "integer" {
+      return Err(self.error("Expected a number"))
+    }
+    self.pos += len;
+    Ok(Term::make_literal(&rest[..len], None, Some(&xsd(datatype))))
+  }
+}
+
+/* String escapes, which unlike IRIs also allow \b, \f, \" and \' */
+fn unescape(s: &str) -> Result<String, String> {
+  let mut unescaped = String::with_capacity(s.len());
+  let mut chars = s.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      unescaped.push(c);
+      continue
+    }
+    let c = match chars.next() {
+      Some('t') => '\t',
+      Some('b') => '\u{8}',
+      Some('n') => '\n',
+      Some('r') => '\r',
+      Some('f') => '\u{C}',
+      Some(c @ '"') | Some(c @ '\'') | Some(c @ '\\') => c,
+      Some(u @ 'u') | Some(u @ 'U') => {
+        let hex: String = chars.by_ref().take(if u == 'u' { 4 } else { 8 }).collect();
+        match u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32) {
+          Some(c) => c,
+          None => return Err(format!("Invalid escape '\\{}{}'", u, hex)),
+        }
+      },
+      Some(c) => return Err(format!("Invalid escape '\\{}'", c)),
+      None => return Err("Dangling '\\'".into()),
+    };
+    unescaped.push(c);
+  }
+  Ok(unescaped)
+}
@@ -0,0 +1,11 @@
+#![allow(non_snake_case)]
+
+pub mod Ion;
+pub mod JsonLd;
+pub mod JsonStream;
+pub mod NTriples;
+pub mod RdfPatch;
+pub mod RdfXml;
+pub mod Snapshot;
+pub mod TriG;
+pub mod Turtle;
\ No newline at end of file
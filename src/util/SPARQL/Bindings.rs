@@ -0,0 +1,140 @@
+use std::str::FromStr;
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use super::Result::{Result, ResultCollection};
+use super::super::{Error::Error, Term::{self, TermKind}};
+
+/* Typed access to a single binding */
+impl Result {
+  pub fn get<T>(&self, var: &str) -> std::result::Result<T, Error>
+    where T: FromStr {
+    let term = self.get_var(var).ok_or_else(|| Error::Query(format!("Variable ?{} is not bound", var)))?;
+    let value = lexical(term);
+    value.parse().map_err(|_| invalid(&value, std::any::type_name::<T>()))
+  }
+}
+/*
+Map every Result onto a T by variable name,
+  e.g. ?name and ?age fill in struct Person { name: String, age: i64 }
+Unbound variables are left out, so they can be caught with Option fields.
+*/
+impl<'a> ResultCollection<'a> {
+  pub fn deserialize<T>(&self) -> std::result::Result<Vec<T>, Error>
+    where T: DeserializeOwned {
+    self.results.iter()
+                .map(|r| T::deserialize(ResultDeserializer { result: r }))
+                .collect()
+  }
+}
+
+/* Literals are read by their lexical form, so "42"^^xsd:int reads as 42 */
+fn lexical(term: String) -> String {
+  match Term::kind(&term) {
+    TermKind::Literal => Term::literal(&term).0,
+    _ => term,
+  }
+}
+fn invalid(value: &str, expected: &str) -> Error {
+  Error::Serialization(format!("Couldn't read '{}' as {}", value, expected))
+}
+
+struct ResultDeserializer<'r> {
+  result: &'r Result,
+}
+impl<'de, 'r> Deserializer<'de> for ResultDeserializer<'r> {
+  type Error = Error;
+  fn deserialize_any<V>(self, visitor: V) -> std::result::Result<V::Value, Error>
+    where V: Visitor<'de> {
+    let mut bound: Vec<(String, String)> = Vec::new();
+    for var in self.result.var_map.keys() {
+      if let Some(term) = self.result.get_var(var) {
+        bound.push((var.clone(), lexical(term)));
+      }
+    }
+    visitor.visit_map(BindingAccess { bound: bound.into_iter(), value: None })
+  }
+  serde::forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf option unit unit_struct newtype_struct seq tuple
+    tuple_struct map struct enum identifier ignored_any
+  }
+}
+
+struct BindingAccess {
+  bound: std::vec::IntoIter<(String, String)>,
+  value: Option<String>,
+}
+impl<'de> MapAccess<'de> for BindingAccess {
+  type Error = Error;
+  fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, Error>
+    where K: de::DeserializeSeed<'de> {
+    match self.bound.next() {
+      Some((var, value)) => {
+        self.value = Some(value);
+        seed.deserialize(var.into_deserializer()).map(Some)
+      },
+      None => Ok(None),
+    }
+  }
+  fn next_value_seed<V>(&mut self, seed: V) -> std::result::Result<V::Value, Error>
+    where V: de::DeserializeSeed<'de> {
+    match self.value.take() {
+      Some(value) => seed.deserialize(ValueDeserializer(value)),
+      None => Err(Error::Serialization("Value requested before key".into())),
+    }
+  }
+}
+
+/* A single bound value, parsed into whatever the visitor asks for */
+struct ValueDeserializer(String);
+macro_rules! parse_as {
+  ($($method:ident => $visit:ident: $t:ty,)*) => {
+    $(
+      fn $method<V>(self, visitor: V) -> std::result::Result<V::Value, Error>
+        where V: Visitor<'de> {
+        match self.0.parse::<$t>() {
+          Ok(v) => visitor.$visit(v),
+          Err(_) => Err(invalid(&self.0, stringify!($t))),
+        }
+      }
+    )*
+  }
+}
+impl<'de> Deserializer<'de> for ValueDeserializer {
+  type Error = Error;
+  fn deserialize_any<V>(self, visitor: V) -> std::result::Result<V::Value, Error>
+    where V: Visitor<'de> {
+    visitor.visit_string(self.0)
+  }
+  fn deserialize_option<V>(self, visitor: V) -> std::result::Result<V::Value, Error>
+    where V: Visitor<'de> {
+    visitor.visit_some(self)
+  }
+  fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> std::result::Result<V::Value, Error>
+    where V: Visitor<'de> {
+    visitor.visit_newtype_struct(self)
+  }
+  fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V)
+    -> std::result::Result<V::Value, Error>
+    where V: Visitor<'de> {
+    visitor.visit_enum(self.0.into_deserializer())
+  }
+  parse_as! {
+    deserialize_bool => visit_bool: bool,
+    deserialize_i8 => visit_i8: i8,
+    deserialize_i16 => visit_i16: i16,
+    deserialize_i32 => visit_i32: i32,
+    deserialize_i64 => visit_i64: i64,
+    deserialize_u8 => visit_u8: u8,
+    deserialize_u16 => visit_u16: u16,
+    deserialize_u32 => visit_u32: u32,
+    deserialize_u64 => visit_u64: u64,
+    deserialize_f32 => visit_f32: f32,
+    deserialize_f64 => visit_f64: f64,
+    deserialize_char => visit_char: char,
+  }
+  serde::forward_to_deserialize_any! {
+    i128 u128 str string bytes byte_buf unit unit_struct seq tuple
+    tuple_struct map struct identifier ignored_any
+  }
+}
+
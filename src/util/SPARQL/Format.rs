@@ -0,0 +1,210 @@
+use serde_json::{json, Map, Value};
+use super::Query::Query;
+use super::Result::{Result, ResultCollection};
+use super::super::{Error::Error, Term::{self, TermKind}, Xml};
+
+const RESULTS_NS: &str = "http://www.w3.org/2005/sparql-results#";
+
+/*
+W3C SPARQL 1.1 Query Results formats.
+The variables in the header come from the Query's select list,
+a Result with nothing bound to a variable leaves it unbound.
+*/
+impl<'a> ResultCollection<'a> {
+  pub fn json(&self) -> String {
+    let vars = self.query.vars();
+    let mut bindings: Vec<Value> = Vec::new();
+    for r in self.results.iter() {
+      let mut row = Map::new();
+      for var in vars.iter() {
+        if let Some(term) = r.get_var(var) {
+          row.insert(var.clone(), json_term(&term));
+        }
+      }
+      bindings.push(Value::Object(row));
+    }
+    json!({
+      "head": { "vars": vars },
+      "results": { "bindings": bindings },
+    }).to_string()
+  }
+  pub fn xml(&self) -> String {
+    let vars = self.query.vars();
+    let mut doc = String::from("<?xml version=\"1.0\"?>\n");
+    doc.push_str(&format!("<sparql xmlns=\"{}\">\n", RESULTS_NS));
+    doc.push_str("  <head>\n");
+    for var in vars.iter() {
+      doc.push_str(&format!("    <variable name=\"{}\"/>\n", Xml::escape(var)));
+    }
+    doc.push_str("  </head>\n  <results>\n");
+    for r in self.results.iter() {
+      doc.push_str("    <result>\n");
+      for var in vars.iter() {
+        if let Some(term) = r.get_var(var) {
+          doc.push_str(&format!("      <binding name=\"{}\">{}</binding>\n",
+                                Xml::escape(var), xml_term(&term)));
+        }
+      }
+      doc.push_str("    </result>\n");
+    }
+    doc.push_str("  </results>\n</sparql>\n");
+    doc
+  }
+  pub fn csv(&self) -> String {
+    let vars = self.query.vars();
+    let mut doc = vars.iter()
+                      .map(|v| csv_field(v))
+                      .collect::<Vec<String>>()
+                      .join(",");
+    doc.push_str("\r\n");
+    for r in self.results.iter() {
+      let row: Vec<String> = vars.iter()
+                                 .map(|v| match r.get_var(v) {
+                                   Some(term) => csv_field(&csv_term(&term)),
+                                   None => String::new(),
+                                 })
+                                 .collect();
+      doc.push_str(&row.join(","));
+      doc.push_str("\r\n");
+    }
+    doc
+  }
+  pub fn tsv(&self) -> String {
+    let vars = self.query.vars();
+    let mut doc = vars.iter()
+                      .map(|v| format!("?{}", v))
+                      .collect::<Vec<String>>()
+                      .join("\t");
+    doc.push('\n');
+    for r in self.results.iter() {
+      let row: Vec<String> = vars.iter()
+                                 .map(|v| match r.get_var(v) {
+                                   Some(term) => Term::to_ntriples(&term),
+                                   None => String::new(),
+                                 })
+                                 .collect();
+      doc.push_str(&row.join("\t"));
+      doc.push('\n');
+    }
+    doc
+  }
+  pub fn from_json(data: &str) -> std::result::Result<Self, Error> {
+    let doc: Value = serde_json::from_str(data)?;
+    let vars: Vec<String> = match doc.pointer("/head/vars").and_then(Value::as_array) {
+      Some(vars) => vars.iter()
+                        .map(|v| v.as_str().map(String::from).ok_or_else(|| malformed("head.vars holds a non-string")))
+                        .collect::<std::result::Result<_, _>>()?,
+      None => return Err(malformed("missing head.vars")),
+    };
+    let bindings = match doc.pointer("/results/bindings").and_then(Value::as_array) {
+      Some(bindings) => bindings,
+      None => return Err(malformed("missing results.bindings")),
+    };
+    let mut rc = ResultCollection::new();
+    rc.query = Query::with_vars(vars);
+    for row in bindings.iter() {
+      let row = match row.as_object() {
+        Some(row) => row,
+        None => return Err(malformed("binding is not an object")),
+      };
+      let mut r = Result::new();
+      for (var, term) in row.iter() {
+        /* Missing keys are None, but keys that aren't strings are an error */
+        let get = |key: &str| match term.get(key) {
+          None => Ok(None),
+          Some(v) => v.as_str().map(Some).ok_or_else(|| malformed(format!("{} of ?{} is not a string", key, var))),
+        };
+        let value = get("value")?.ok_or_else(|| malformed(format!("no value for ?{}", var)))?;
+        let term = match get("type")? {
+          Some("uri") => value.to_string(),
+          Some("bnode") => format!("_:{}", value),
+          Some("literal")
+          | Some("typed-literal") => Term::make_literal(value, get("xml:lang")?, get("datatype")?),
+          _ => return Err(malformed(format!("unknown term type for ?{}", var))),
+        };
+        r.add_var(var.clone(), term);
+      }
+      rc.results.push(r);
+    }
+    Ok(rc)
+  }
+  pub fn from_xml(data: &str) -> std::result::Result<Self, Error> {
+    let doc = Xml::parse(data).map_err(Error::Parse)?;
+    if doc.local_name() != "sparql" {
+      return Err(malformed(format!("unexpected root <{}>", doc.name)))
+    }
+    let head = doc.child("head").ok_or_else(|| malformed("missing <head>"))?;
+    let vars: Vec<String> = head.elements()
+                                .filter(|e| e.local_name() == "variable")
+                                .filter_map(|e| e.attr("name").map(String::from))
+                                .collect();
+    let results = doc.child("results").ok_or_else(|| malformed("missing <results>"))?;
+    let mut rc = ResultCollection::new();
+    rc.query = Query::with_vars(vars);
+    for result in results.elements().filter(|e| e.local_name() == "result") {
+      let mut r = Result::new();
+      for binding in result.elements().filter(|e| e.local_name() == "binding") {
+        let var = binding.attr("name")
+                         .ok_or_else(|| malformed("<binding> without a name"))?;
+        let term = binding.elements()
+                          .next()
+                          .ok_or_else(|| malformed(format!("empty binding for ?{}", var)))?;
+        let value = term.text();
+        let term = match term.local_name() {
+          "uri" => value,
+          "bnode" => format!("_:{}", value),
+          "literal" => Term::make_literal(&value, term.attr("xml:lang"), term.attr("datatype")),
+          other => return Err(malformed(format!("unknown term <{}>", other))),
+        };
+        r.add_var(var.to_string(), term);
+      }
+      rc.results.push(r);
+    }
+    Ok(rc)
+  }
+}
+
+fn malformed<S: AsRef<str>>(msg: S) -> Error {
+  Error::Parse(format!("Not a SPARQL results document, {}", msg.as_ref()))
+}
+fn json_term(term: &str) -> Value {
+  match Term::kind(term) {
+    TermKind::Iri       => json!({ "type": "uri", "value": term }),
+    TermKind::BlankNode => json!({ "type": "bnode", "value": &term[2..] }),
+    TermKind::Literal   => {
+      match Term::literal(term) {
+        (lexical, Some(lang), _) => json!({ "type": "literal", "value": lexical, "xml:lang": lang }),
+        (lexical, None, Some(dt)) => json!({ "type": "literal", "value": lexical, "datatype": dt }),
+        (lexical, None, None) => json!({ "type": "literal", "value": lexical }),
+      }
+    },
+  }
+}
+fn xml_term(term: &str) -> String {
+  match Term::kind(term) {
+    TermKind::Iri       => format!("<uri>{}</uri>", Xml::escape(term)),
+    TermKind::BlankNode => format!("<bnode>{}</bnode>", Xml::escape(&term[2..])),
+    TermKind::Literal   => {
+      match Term::literal(term) {
+        (lexical, Some(lang), _) => format!("<literal xml:lang=\"{}\">{}</literal>",
+                                            Xml::escape(&lang), Xml::escape(&lexical)),
+        (lexical, None, Some(dt)) => format!("<literal datatype=\"{}\">{}</literal>",
+                                             Xml::escape(&dt), Xml::escape(&lexical)),
+        (lexical, None, None) => format!("<literal>{}</literal>", Xml::escape(&lexical)),
+      }
+    },
+  }
+}
+/* CSV drops datatypes and languages, leaving just the lexical form */
+fn csv_term(term: &str) -> String {
+  match Term::kind(term) {
+    TermKind::Literal => Term::literal(term).0,
+    _ => term.to_string(),
+  }
+}
+fn csv_field(field: &str) -> String {
+  if field.contains([',', '"', '\n', '\r']) {
+    return format!("\"{}\"", field.replace('"', "\"\""))
+  }
+  field.to_string()
+}
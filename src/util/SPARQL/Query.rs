@@ -1,6 +1,13 @@
-use super::super::DataStores::Graph::Graph;
+use std::collections::{HashMap, HashSet};
+use super::super::{
+  DataStores::{BlankNodes, Graph::Graph, PatternMatch::PatternMatch},
+  Error::Error,
+  Term::{self, TermKind},
+};
 use super::Result::*;
 
+pub type Binding = HashMap<String, String>;
+
 /* Query Unit */
 #[derive(Clone, Debug, PartialEq)]
 pub enum QueryUnit {
@@ -21,7 +28,7 @@ impl<'a> From<&'a str> for QueryUnit {
 /* Query */
 #[derive(Clone, Debug)]
 pub struct Query<'a> {
-  graph: Option<&'a Graph>,
+  graph: Option<&'a dyn PatternMatch>,
   vars: Vec<QueryUnit>,
   conds: Vec<(QueryUnit, QueryUnit, QueryUnit)>,
 }
@@ -32,9 +39,13 @@ impl<'a> Query<'a> {
   pub fn fetch(self) -> ResultCollection<'a> {
     use QueryUnit::{Val, Var, Nil};
     let mut rc = ResultCollection::new();
+    rc.query = self.clone();
     if let None = self.graph {
       return rc
     }
+    if self.conds.is_empty() {
+      return rc
+    }
     /* Actually start processing now */
     let q1: Option<String>;
     let q2: Option<String>;
@@ -79,11 +90,178 @@ impl<'a> Query<'a> {
     rc
   }
 }
+/* Accessors */
+impl<'a> Query<'a> {
+  pub fn with_vars(vars: Vec<String>) -> Self {
+    Query {
+      graph: None,
+      vars: vars.into_iter().map(QueryUnit::Var).collect(),
+      conds: Vec::new(),
+    }
+  }
+  pub fn vars(&self) -> Vec<String> {
+    self.vars.iter()
+             .filter_map(|v| match v {
+               QueryUnit::Var(name) => Some(name.clone()),
+               _ => None,
+             })
+             .collect()
+  }
+}
+/* Boolean query form */
+impl<'a> Query<'a> {
+  pub fn ask(self) -> bool {
+    match self.graph {
+      Some(graph) => exists(graph, &self.conds, &HashMap::new()),
+      None => false,
+    }
+  }
+}
+/* Depth-first search for a single solution, giving up on
+     a branch as soon as one of its conditions can't match */
+fn exists(graph: &dyn PatternMatch, conds: &[(QueryUnit, QueryUnit, QueryUnit)], binding: &Binding) -> bool {
+  let (x, y, z) = match conds.first() {
+    Some(cond) => cond,
+    None => return true,
+  };
+  let qt = (resolve(x, binding), resolve(y, binding), resolve(z, binding));
+  /* The indexes can say no without listing any matches */
+  if !graph.contains_pattern(&qt) {
+    return false
+  }
+  let rest = &conds[1..];
+  if !repeats_var(x, y, z) && !binds_for(x, y, z, binding, rest) {
+    /* Nothing this condition binds is needed later, so any match will do */
+    return exists(graph, rest, binding)
+  }
+  for (s, p, o) in graph.get_triple(&qt) {
+    let mut b = binding.clone();
+    if bind(&mut b, x, s) && bind(&mut b, y, p) && bind(&mut b, z, o)
+       && exists(graph, rest, &b) {
+      return true
+    }
+  }
+  false
+}
+/* Whether the condition binds a variable that a later one uses */
+fn binds_for(x: &QueryUnit, y: &QueryUnit, z: &QueryUnit, binding: &Binding,
+             rest: &[(QueryUnit, QueryUnit, QueryUnit)]) -> bool {
+  [x, y, z].iter().any(|unit| match unit {
+    QueryUnit::Var(a) if !binding.contains_key(a) => {
+      rest.iter().any(|(x, y, z)| [x, y, z].iter().any(|u| matches!(u, QueryUnit::Var(b) if b == a)))
+    },
+    _ => false,
+  })
+}
+fn repeats_var(x: &QueryUnit, y: &QueryUnit, z: &QueryUnit) -> bool {
+  match (x, y, z) {
+    (QueryUnit::Var(a), QueryUnit::Var(b), _) if a == b => true,
+    (QueryUnit::Var(a), _, QueryUnit::Var(c)) if a == c => true,
+    (_, QueryUnit::Var(b), QueryUnit::Var(c)) if b == c => true,
+    _ => false,
+  }
+}
+/* Graph-producing query forms */
+impl<'a> Query<'a> {
+  pub fn construct(self, template: &[(&str, &str, &str)]) -> Graph {
+    let template: Vec<(QueryUnit, QueryUnit, QueryUnit)>
+      = template.iter()
+                .map(|&(x, y, z)| (QueryUnit::from(x), QueryUnit::from(y), QueryUnit::from(z)))
+                .collect();
+    let solutions = self.solutions();
+    /* Each solution gets its own copy of the template's blank nodes,
+         labelled apart from any blank node a solution binds */
+    let bound: HashSet<&str> = solutions.iter()
+                                        .flat_map(|binding| binding.values().map(String::as_str))
+                                        .filter(|v| Term::kind(v) == TermKind::BlankNode)
+                                        .collect();
+    let mut next = 0;
+    let mut g = Graph::new();
+    for binding in solutions.iter() {
+      let mut fresh: HashMap<String, String> = HashMap::new();
+      let mut instantiate = |unit: &QueryUnit| match unit {
+        QueryUnit::Val(v) if Term::kind(v) == TermKind::BlankNode => {
+          Some(fresh.entry(v.clone()).or_insert_with(|| BlankNodes::fresh(&mut next, |l| bound.contains(l))).clone())
+        },
+        _ => resolve(unit, binding),
+      };
+      for (s, p, o) in template.iter() {
+        /* Template triples with an unbound variable are skipped */
+        if let (Some(s), Some(p), Some(o)) = (instantiate(s), instantiate(p), instantiate(o)) {
+          g.insert((s, p, o));
+        }
+      }
+    }
+    g
+  }
+  pub fn describe(self) -> Graph {
+    let mut g = Graph::new();
+    let graph = match self.graph {
+      Some(graph) => graph,
+      None => return g,
+    };
+    for binding in self.solutions() {
+      for var in self.vars.iter() {
+        if let QueryUnit::Var(name) = var {
+          if let Some(resource) = binding.get(name) {
+            g.extend(graph.describe(resource));
+          }
+        }
+      }
+    }
+    g
+  }
+  fn solutions(&self) -> Vec<Binding> {
+    match self.graph {
+      Some(graph) => solve(graph, &self.conds),
+      None => Vec::new(),
+    }
+  }
+}
+/* Join every condition against the Graph, returning one
+     variable binding per solution */
+pub fn solve(graph: &dyn PatternMatch, conds: &[(QueryUnit, QueryUnit, QueryUnit)]) -> Vec<Binding> {
+  let mut solutions: Vec<Binding> = vec![HashMap::new()];
+  for (x, y, z) in conds.iter() {
+    let mut next_solutions: Vec<Binding> = Vec::new();
+    for binding in solutions.iter() {
+      let qt = (resolve(x, binding), resolve(y, binding), resolve(z, binding));
+      for (s, p, o) in graph.get_triple(&qt) {
+        let mut b = binding.clone();
+        if bind(&mut b, x, s) && bind(&mut b, y, p) && bind(&mut b, z, o) {
+          next_solutions.push(b);
+        }
+      }
+    }
+    solutions = next_solutions;
+    if solutions.is_empty() {
+      break
+    }
+  }
+  solutions
+}
+pub fn resolve(unit: &QueryUnit, binding: &Binding) -> Option<String> {
+  match unit {
+    QueryUnit::Val(a) => Some(a.clone()),
+    QueryUnit::Var(a) => binding.get(a).cloned(),
+    QueryUnit::Nil    => None,
+  }
+}
+fn bind(binding: &mut Binding, unit: &QueryUnit, val: String) -> bool {
+  if let QueryUnit::Var(a) = unit {
+    /* A variable used twice must match the same value both times */
+    match binding.get(a) {
+      Some(bound) => return bound == &val,
+      None => { binding.insert(a.clone(), val); },
+    }
+  }
+  true
+}
 
 /* Query Builders */
 pub struct QueryBase;
 impl<'a> QueryBase {
-  pub fn from(self, g: &Graph) -> QueryFrom {
+  pub fn from<G: PatternMatch>(self, g: &G) -> QueryFrom<'_> {
     QueryFrom {
       graph: g,
     }
@@ -100,7 +278,7 @@ impl<'a> QueryBase {
   }
 }
 pub struct QueryFrom<'a> {
-  graph: &'a Graph,
+  graph: &'a dyn PatternMatch,
 }
 impl<'a> QueryFrom<'a> {
   pub fn select(self, vars: &'a[&str]) -> QuerySelect<'a> {
@@ -123,33 +301,46 @@ impl<'a> QueryFrom<'a> {
   pub fn fetch(self) -> ResultCollection<'a> {
     self.compile().fetch()
   }
+  pub fn ask(self, conds: &[(&str, &str, &str)]) -> bool {
+    let qconds: Vec<(QueryUnit, QueryUnit, QueryUnit)>
+      = conds.iter()
+             .map(|&(x, y, z)| (QueryUnit::from(x), QueryUnit::from(y), QueryUnit::from(z)))
+             .collect();
+    exists(self.graph, &qconds, &HashMap::new())
+  }
+  pub fn describe(self, resources: &[&str]) -> Graph {
+    let mut g = Graph::new();
+    for resource in resources {
+      g.extend(self.graph.describe(resource));
+    }
+    g
+  }
 }
 pub struct QuerySelect<'a> {
-  graph: &'a Graph,
+  graph: &'a dyn PatternMatch,
   vars: Vec<QueryUnit>,
 }
 impl<'a> QuerySelect<'a> {
-  pub fn filter(self, conds: &[(&str, &str, &str)]) -> Query<'a> {
+  pub fn filter(self, conds: &[(&str, &str, &str)]) -> std::result::Result<Query<'a>, Error> {
     let qconds: Vec<(QueryUnit, QueryUnit, QueryUnit)>
       = conds.to_vec()
              .into_iter()
              .map(|(x, y, z)| (QueryUnit::from(x), QueryUnit::from(y), QueryUnit::from(z)))
-             .filter(|(x, y, z)| {
-               for a in [x, y, z].iter() {
-                 if let QueryUnit::Var(_) = a {
-                   if !self.vars.contains(a) {
-                     panic!("Undeclared variable in query!");
-                   }
-                 }
-               }
-               true
-             })
              .collect();
-    Query {
+    for (x, y, z) in qconds.iter() {
+      for a in [x, y, z].iter() {
+        if let QueryUnit::Var(name) = a {
+          if !self.vars.contains(a) {
+            return Err(Error::Query(format!("Undeclared variable ${} in query", name)))
+          }
+        }
+      }
+    }
+    Ok(Query {
       graph: Some(self.graph),
       vars: self.vars,
       conds: qconds,
-    }
+    })
   }
   pub fn compile(self) -> Query<'a> {
     Query {
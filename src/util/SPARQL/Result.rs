@@ -35,7 +35,7 @@ impl Result {
     self.var_map.insert(var, self.values.len()-1);
   }
   pub fn get_val(&self, pos: usize) -> Option<String> {
-    if self.values.len() <= pos {
+    if pos < self.values.len() {
       match &self.values[pos] {
         ResultUnit::Val(a) => return Some(a.clone()),
         _ => return None
@@ -0,0 +1,608 @@
+use super::super::DataStores::{BlankNodes, Graph::Graph, Observers::GraphChange, TripleStore::TripleStore};
+use std::collections::HashMap;
+use super::super::Error::Error;
+use super::super::Term::{self, rdf};
+use super::Query::{QueryUnit, solve, resolve};
+
+type Triple = (String, String, String);
+type Pattern = (QueryUnit, QueryUnit, QueryUnit);
+
+/* Update Operations */
+#[derive(Clone, Debug, PartialEq)]
+pub enum UpdateOp {
+  InsertData(Vec<Triple>),
+  DeleteData(Vec<Triple>),
+  Modify {
+    delete: Vec<Pattern>,
+    insert: Vec<Pattern>,
+    pattern: Vec<Pattern>,
+  },
+  Clear {
+    target: ClearTarget,
+    silent: bool,
+  },
+  Load {
+    path: String,
+    /* Only the default graph exists, so loading into any other fails */
+    graph: Option<String>,
+    silent: bool,
+  },
+}
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClearTarget {
+  Default,
+  Named,
+  All,
+  Graph(String),
+}
+
+/*
+A parsed SPARQL 1.1 Update request, a sequence of operations
+separated by ';', each of which may declare PREFIXes first.
+Terms are <iri>s, prefixed names, _:blank nodes, quoted literals
+with an @lang or ^^datatype, ?variables, and "a" for rdf:type;
+other bare words, like numbers, are kept as plain literals.
+Every operation in the request is applied, or none of them are.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Update {
+  pub ops: Vec<UpdateOp>,
+}
+impl Update {
+  pub fn parse(request: &str) -> Result<Self, Error> {
+    let tokens = tokenise(request)?;
+    let mut parser = Parser { tokens, pos: 0, prefixes: HashMap::new() };
+    let mut ops = Vec::new();
+    while !parser.at_end() {
+      parser.prologue()?;
+      ops.push(parser.operation()?);
+      if !parser.eat(&Token::Semicolon) {
+        break
+      }
+    }
+    if !parser.at_end() {
+      return Err(parser.unexpected())
+    }
+    Ok(Update { ops })
+  }
+  pub fn execute(&self, graph: &mut Graph) -> Result<(), Error> {
+    self.execute_journaled(graph).map(|_| ())
+  }
+  /* As execute, handing back every effective change in the order it was made */
+  pub(crate) fn execute_journaled(&self, graph: &mut Graph) -> Result<Vec<GraphChange>, Error> {
+    /* Record every effective change so a failing operation
+         can put the Graph back the way it was */
+    let mut journal: Vec<GraphChange> = Vec::new();
+    for op in self.ops.iter() {
+      if let Err(e) = apply(op, graph, &mut journal) {
+        for change in journal.into_iter().rev() {
+          match change {
+            GraphChange::Inserted(t) => graph.remove(&t),
+            GraphChange::Removed(t)  => graph.insert(t),
+          }
+        }
+        return Err(e)
+      }
+    }
+    Ok(journal)
+  }
+}
+
+fn insert(graph: &mut Graph, journal: &mut Vec<GraphChange>, (s, p, o): Triple) {
+  let qt = (Some(s.clone()), Some(p.clone()), Some(o.clone()));
+  if !graph.contains_pattern(&qt) {
+    graph.insert((s.clone(), p.clone(), o.clone()));
+    journal.push(GraphChange::Inserted((s, p, o)));
+  }
+}
+fn remove(graph: &mut Graph, journal: &mut Vec<GraphChange>, (s, p, o): Triple) {
+  let qt = (Some(s.clone()), Some(p.clone()), Some(o.clone()));
+  if graph.contains_pattern(&qt) {
+    graph.remove(&(s.clone(), p.clone(), o.clone()));
+    journal.push(GraphChange::Removed((s, p, o)));
+  }
+}
+fn apply(op: &UpdateOp, graph: &mut Graph, journal: &mut Vec<GraphChange>) -> Result<(), Error> {
+  match op {
+    UpdateOp::InsertData(triples) => {
+      for triple in triples.iter() {
+        insert(graph, journal, triple.clone());
+      }
+    },
+    UpdateOp::DeleteData(triples) => {
+      for triple in triples.iter() {
+        remove(graph, journal, triple.clone());
+      }
+    },
+    UpdateOp::Modify { delete, insert: ins, pattern } => {
+      /* Both templates are instantiated against the Graph
+           as it was before this operation touched it */
+      let mut deletions: Vec<Triple> = Vec::new();
+      let mut insertions: Vec<Triple> = Vec::new();
+      for binding in solve(graph, pattern) {
+        for (s, p, o) in delete.iter() {
+          if let (Some(s), Some(p), Some(o)) = (resolve(s, &binding),
+                                                resolve(p, &binding),
+                                                resolve(o, &binding)) {
+            deletions.push((s, p, o));
+          }
+        }
+        for (s, p, o) in ins.iter() {
+          if let (Some(s), Some(p), Some(o)) = (resolve(s, &binding),
+                                                resolve(p, &binding),
+                                                resolve(o, &binding)) {
+            insertions.push((s, p, o));
+          }
+        }
+      }
+      for triple in deletions {
+        remove(graph, journal, triple);
+      }
+      for triple in insertions {
+        insert(graph, journal, triple);
+      }
+    },
+    UpdateOp::Clear { target, silent } => {
+      /* Only the default graph exists, so there are never
+           any named graphs to clear */
+      match target {
+        ClearTarget::Default
+        | ClearTarget::All => {
+          for triple in graph.get_triple(&(None, None, None)) {
+            remove(graph, journal, triple);
+          }
+        },
+        ClearTarget::Named => {},
+        ClearTarget::Graph(iri) => {
+          if !silent {
+            return Err(no_such_graph(iri))
+          }
+        },
+      }
+    },
+    UpdateOp::Load { path, graph: into, silent } => {
+      let loaded = match into {
+        Some(iri) => Err(no_such_graph(iri)),
+        None => local_path(path)
+          .and_then(|path| std::fs::read_to_string(path).map_err(Error::from))
+          .and_then(|data| TripleStore::from_json(&data)),
+      };
+      match loaded {
+        Ok(triples) => {
+          /* Blank nodes are kept apart, as Graph::insert_document does */
+          for triple in BlankNodes::relabel_apart(&graph.blank_nodes(), triples.into_iter().collect()) {
+            insert(graph, journal, triple);
+          }
+        },
+        Err(_) if *silent => {},
+        Err(e) => return Err(e),
+      }
+    },
+  }
+  Ok(())
+}
+fn no_such_graph(iri: &str) -> Error {
+  Error::Constraint(format!("No graph named <{}>", iri))
+}
+/* LOAD only reads local files, so only file: IRIs are accepted */
+fn local_path(iri: &str) -> Result<&str, Error> {
+  match iri.strip_prefix("file://") {
+    Some(rest) => Ok(rest.strip_prefix("localhost").unwrap_or(rest)),
+    None => match iri.strip_prefix("file:") {
+      Some(path) if path.starts_with('/') => Ok(path),
+      _ => Err(Error::Constraint(format!("Can only LOAD file: IRIs, not <{}>", iri))),
+    },
+  }
+}
+
+/* Tokeniser */
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Iri(String),
+  Literal(String),
+  /* "@en" straight after a literal */
+  LangTag(String),
+  /* "^^" between a literal and its datatype */
+  Carets,
+  Var(String),
+  Word(String),
+  LBrace,
+  RBrace,
+  Dot,
+  Semicolon,
+  Comma,
+}
+fn tokenise(request: &str) -> Result<Vec<Token>, Error> {
+  let mut tokens = Vec::new();
+  let mut chars = request.chars().peekable();
+  while let Some(&c) = chars.peek() {
+    match c {
+      _ if c.is_whitespace() => { chars.next(); },
+      '#' => {
+        for c in chars.by_ref() {
+          if c == '\n' {
+            break
+          }
+        }
+      },
+      '{' => { chars.next(); tokens.push(Token::LBrace); },
+      '}' => { chars.next(); tokens.push(Token::RBrace); },
+      _ if starts_number(&chars) => tokens.push(Token::Word(number(&mut chars))),
+      '.' => { chars.next(); tokens.push(Token::Dot); },
+      ';' => { chars.next(); tokens.push(Token::Semicolon); },
+      ',' => { chars.next(); tokens.push(Token::Comma); },
+      '<' => {
+        chars.next();
+        let mut iri = String::new();
+        loop {
+          match chars.next() {
+            Some('>') => break,
+            Some(c) => iri.push(c),
+            None => return Err(Error::Parse("Unterminated IRI".into())),
+          }
+        }
+        tokens.push(Token::Iri(iri));
+      },
+      '"' | '\'' => {
+        chars.next();
+        let mut literal = String::new();
+        loop {
+          match chars.next() {
+            Some('\\') => match chars.next() {
+              Some('n') => literal.push('\n'),
+              Some('t') => literal.push('\t'),
+              Some('r') => literal.push('\r'),
+              Some(c) => literal.push(c),
+              None => return Err(Error::Parse("Unterminated literal".into())),
+            },
+            Some(q) if q == c => break,
+            Some(c) => literal.push(c),
+            None => return Err(Error::Parse("Unterminated literal".into())),
+          }
+        }
+        tokens.push(Token::Literal(literal));
+      },
+      '@' => {
+        chars.next();
+        let mut tag = String::new();
+        while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '-') {
+          tag.push(c);
+          chars.next();
+        }
+        if tag.is_empty() {
+          return Err(Error::Parse("Language tag without a name".into()))
+        }
+        tokens.push(Token::LangTag(tag));
+      },
+      '^' => {
+        chars.next();
+        if chars.next() != Some('^') {
+          return Err(Error::Parse("Expected '^^' before a datatype".into()))
+        }
+        tokens.push(Token::Carets);
+      },
+      '?' | '$' => {
+        chars.next();
+        let name = word(&mut chars);
+        if name.is_empty() {
+          return Err(Error::Parse("Variable without a name".into()))
+        }
+        tokens.push(Token::Var(name));
+      },
+      _ => {
+        let w = word(&mut chars);
+        if w.is_empty() {
+          return Err(Error::Parse(format!("Unexpected character '{}'", c)))
+        }
+        tokens.push(Token::Word(w));
+      },
+    }
+  }
+  Ok(tokens)
+}
+fn word<I>(chars: &mut std::iter::Peekable<I>) -> String
+  where I: Iterator<Item=char> {
+  let mut w = String::new();
+  while let Some(&c) = chars.peek() {
+    if c.is_alphanumeric() || c == '_' || c == '-' || c == ':' {
+      w.push(c);
+      chars.next();
+    }
+    else {
+      break
+    }
+  }
+  w
+}
+
+/*
+Numbers are read before punctuation, so the '.' in 1.5 or .5
+isn't taken for the end of a triple. A '.' straight after
+an integer with no digit following still ends the triple.
+*/
+fn starts_number<I>(chars: &std::iter::Peekable<I>) -> bool
+  where I: Iterator<Item=char> + Clone {
+  let mut ahead = chars.clone();
+  let c = match ahead.next() {
+    Some(c) => c,
+    None => return false,
+  };
+  let c = match c {
+    '+' | '-' => match ahead.next() {
+      Some(c) => c,
+      None => return false,
+    },
+    c => c,
+  };
+  match c {
+    '.' => ahead.next().is_some_and(|c| c.is_ascii_digit()),
+    c => c.is_ascii_digit(),
+  }
+}
+fn number<I>(chars: &mut std::iter::Peekable<I>) -> String
+  where I: Iterator<Item=char> + Clone {
+  let mut n = String::new();
+  if let Some(&c) = chars.peek() {
+    if c == '+' || c == '-' {
+      n.push(c);
+      chars.next();
+    }
+  }
+  digits(chars, &mut n);
+  let mut ahead = chars.clone();
+  if ahead.next() == Some('.') && ahead.next().is_some_and(|c| c.is_ascii_digit()) {
+    n.push('.');
+    chars.next();
+    digits(chars, &mut n);
+  }
+  let mut ahead = chars.clone();
+  if let Some(e) = ahead.next().filter(|&c| c == 'e' || c == 'E') {
+    let mut exponent = e.to_string();
+    if let Some(sign) = ahead.peek().copied().filter(|&c| c == '+' || c == '-') {
+      exponent.push(sign);
+      ahead.next();
+    }
+    if ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+      n.push_str(&exponent);
+      for _ in 0..exponent.len() {
+        chars.next();
+      }
+      digits(chars, &mut n);
+    }
+  }
+  n
+}
+fn digits<I>(chars: &mut std::iter::Peekable<I>, n: &mut String)
+  where I: Iterator<Item=char> {
+  while let Some(&c) = chars.peek() {
+    if !c.is_ascii_digit() {
+      break
+    }
+    n.push(c);
+    chars.next();
+  }
+}
+
+/* Parser */
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+  /* From PREFIX declarations, which hold for the rest of the request */
+  prefixes: HashMap<String, String>,
+}
+impl Parser {
+  fn at_end(&self) -> bool {
+    self.pos >= self.tokens.len()
+  }
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos)
+  }
+  fn eat(&mut self, token: &Token) -> bool {
+    if self.peek() == Some(token) {
+      self.pos += 1;
+      return true
+    }
+    false
+  }
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    if let Some(Token::Word(w)) = self.peek() {
+      if w.eq_ignore_ascii_case(keyword) {
+        self.pos += 1;
+        return true
+      }
+    }
+    false
+  }
+  fn expect(&mut self, token: &Token) -> Result<(), Error> {
+    if self.eat(token) {
+      return Ok(())
+    }
+    Err(self.unexpected())
+  }
+  fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+    if self.eat_keyword(keyword) {
+      return Ok(())
+    }
+    Err(self.unexpected())
+  }
+  fn unexpected(&self) -> Error {
+    match self.peek() {
+      Some(token) => Error::Parse(format!("Unexpected {:?}", token)),
+      None => Error::Parse("Unexpected end of request".into()),
+    }
+  }
+  /* PREFIX ex: <iri> ... ahead of an operation */
+  fn prologue(&mut self) -> Result<(), Error> {
+    while self.eat_keyword("PREFIX") {
+      let prefix = match self.peek() {
+        Some(Token::Word(w)) if w.ends_with(':') && w.matches(':').count() == 1 => w.trim_end_matches(':').to_string(),
+        _ => return Err(self.unexpected()),
+      };
+      self.pos += 1;
+      let iri = self.iri()?;
+      self.prefixes.insert(prefix, iri);
+    }
+    Ok(())
+  }
+  fn operation(&mut self) -> Result<UpdateOp, Error> {
+    if self.eat_keyword("INSERT") {
+      if self.eat_keyword("DATA") {
+        return Ok(UpdateOp::InsertData(self.data_block()?))
+      }
+      let insert = self.pattern_block()?;
+      self.expect_keyword("WHERE")?;
+      let pattern = self.pattern_block()?;
+      return Ok(UpdateOp::Modify { delete: Vec::new(), insert, pattern })
+    }
+    if self.eat_keyword("DELETE") {
+      if self.eat_keyword("DATA") {
+        return Ok(UpdateOp::DeleteData(self.data_block()?))
+      }
+      if self.eat_keyword("WHERE") {
+        let pattern = self.pattern_block()?;
+        return Ok(UpdateOp::Modify { delete: pattern.clone(), insert: Vec::new(), pattern })
+      }
+      let delete = self.pattern_block()?;
+      let insert = if self.eat_keyword("INSERT") {
+        self.pattern_block()?
+      }
+      else {
+        Vec::new()
+      };
+      self.expect_keyword("WHERE")?;
+      let pattern = self.pattern_block()?;
+      return Ok(UpdateOp::Modify { delete, insert, pattern })
+    }
+    if self.eat_keyword("CLEAR") {
+      let silent = self.eat_keyword("SILENT");
+      let target = if self.eat_keyword("DEFAULT") {
+        ClearTarget::Default
+      }
+      else if self.eat_keyword("NAMED") {
+        ClearTarget::Named
+      }
+      else if self.eat_keyword("ALL") {
+        ClearTarget::All
+      }
+      else {
+        self.expect_keyword("GRAPH")?;
+        ClearTarget::Graph(self.iri()?)
+      };
+      return Ok(UpdateOp::Clear { target, silent })
+    }
+    if self.eat_keyword("LOAD") {
+      let silent = self.eat_keyword("SILENT");
+      let path = self.iri()?;
+      let graph = if self.eat_keyword("INTO") {
+        self.expect_keyword("GRAPH")?;
+        Some(self.iri()?)
+      }
+      else {
+        None
+      };
+      return Ok(UpdateOp::Load { path, graph, silent })
+    }
+    Err(self.unexpected())
+  }
+  fn iri(&mut self) -> Result<String, Error> {
+    match self.peek().cloned() {
+      Some(Token::Iri(iri)) => {
+        self.pos += 1;
+        Ok(iri)
+      },
+      _ => Err(self.unexpected()),
+    }
+  }
+  fn data_block(&mut self) -> Result<Vec<Triple>, Error> {
+    let mut triples = Vec::new();
+    for (s, p, o) in self.pattern_block()? {
+      match (s, p, o) {
+        (QueryUnit::Val(s), QueryUnit::Val(p), QueryUnit::Val(o)) => triples.push((s, p, o)),
+        _ => return Err(Error::Parse("Variables are not allowed in DATA blocks".into())),
+      }
+    }
+    Ok(triples)
+  }
+  /* '{' s p o [; p o] [, o] ... [.] '}' */
+  fn pattern_block(&mut self) -> Result<Vec<Pattern>, Error> {
+    let mut patterns = Vec::new();
+    self.expect(&Token::LBrace)?;
+    while !self.eat(&Token::RBrace) {
+      let s = self.term()?;
+      loop {
+        let p = match self.peek() {
+          Some(Token::Word(w)) if w == "a" => {
+            self.pos += 1;
+            QueryUnit::Val(rdf("type"))
+          },
+          _ => self.term()?,
+        };
+        loop {
+          let o = self.term()?;
+          patterns.push((s.clone(), p.clone(), o));
+          if !self.eat(&Token::Comma) {
+            break
+          }
+        }
+        if !self.eat(&Token::Semicolon) {
+          break
+        }
+      }
+      if !self.eat(&Token::Dot) {
+        self.expect(&Token::RBrace)?;
+        break
+      }
+    }
+    Ok(patterns)
+  }
+  fn term(&mut self) -> Result<QueryUnit, Error> {
+    let unit = match self.peek().cloned() {
+      Some(Token::Iri(a))     => QueryUnit::Val(a),
+      Some(Token::Word(a))    => QueryUnit::Val(self.expand(a)?),
+      Some(Token::Var(a))     => QueryUnit::Var(a),
+      Some(Token::Literal(a)) => {
+        self.pos += 1;
+        return Ok(QueryUnit::Val(self.literal(&a)?))
+      },
+      _ => return Err(self.unexpected()),
+    };
+    self.pos += 1;
+    Ok(unit)
+  }
+  /* A quoted literal's lexical form, then any language tag or datatype */
+  fn literal(&mut self, lexical: &str) -> Result<String, Error> {
+    match self.peek().cloned() {
+      Some(Token::LangTag(lang)) => {
+        self.pos += 1;
+        Ok(Term::make_literal(lexical, Some(&lang), None))
+      },
+      Some(Token::Carets) => {
+        self.pos += 1;
+        let datatype = match self.peek().cloned() {
+          Some(Token::Iri(iri)) => iri,
+          Some(Token::Word(w)) if w.contains(':') => self.expand(w)?,
+          _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(Term::make_literal(lexical, None, Some(&datatype)))
+      },
+      _ => Ok(Term::make_literal(lexical, None, None)),
+    }
+  }
+  /*
+    Prefixed names are expanded; other bare words, like numbers,
+    are kept as they are, as are blank nodes
+  */
+  fn expand(&self, word: String) -> Result<String, Error> {
+    if word.starts_with("_:") {
+      return Ok(word)
+    }
+    match word.split_once(':') {
+      Some((prefix, local)) => match self.prefixes.get(prefix) {
+        Some(iri) => Ok(format!("{}{}", iri, local)),
+        None => Err(Error::Parse(format!("Unknown prefix '{}:'", prefix))),
+      },
+      None => Ok(word),
+    }
+  }
+}
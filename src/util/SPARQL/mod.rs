@@ -1,2 +1,5 @@
+pub mod Bindings;
+pub mod Format;
 pub mod Query;
-pub mod Result;
\ No newline at end of file
+pub mod Result;
+pub mod Update;
\ No newline at end of file
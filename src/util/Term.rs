@@ -0,0 +1,253 @@
+/*
+Graphs store every term as a plain String, so the kind of
+term is worked out from how the String looks:
+  "_:b0"                   -> Blank Node
+  "http://ex.org/Gabe"     -> IRI (a scheme then "//", or urn:, mailto: ...)
+  "rdf:type"               -> Plain Literal, prefixed names aren't expanded
+  "\"chat\"@fr"            -> Literal with a language tag
+  "\"1\"^^<http://..#int>" -> Literal with a datatype
+  "Gabe"                   -> Plain Literal
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum TermKind {
+  Iri,
+  BlankNode,
+  Literal,
+}
+
+pub const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+pub const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+pub fn rdf(name: &str) -> String {
+  format!("{}{}", RDF, name)
+}
+pub fn xsd(name: &str) -> String {
+  format!("{}{}", XSD, name)
+}
+
+pub fn kind(term: &str) -> TermKind {
+  if term.starts_with("_:") {
+    return TermKind::BlankNode
+  }
+  if is_iri(term) {
+    return TermKind::Iri
+  }
+  TermKind::Literal
+}
+/* Schemes whose IRIs don't start with "//", so can't be told from prefixed names by shape */
+const OPAQUE_SCHEMES: [&str; 8] = ["urn", "mailto", "tag", "tel", "data", "file", "geo", "did"];
+fn is_iri(term: &str) -> bool {
+  match scheme(term) {
+    Some(scheme) => term[scheme.len() + 1..].starts_with("//")
+                      || OPAQUE_SCHEMES.iter().any(|s| scheme.eq_ignore_ascii_case(s)),
+    None => false,
+  }
+}
+/* scheme ":" followed by no whitespace */
+fn scheme(term: &str) -> Option<&str> {
+  let scheme_len = match term.find(':') {
+    Some(0) | None => return None,
+    Some(i) => i,
+  };
+  let scheme = &term[..scheme_len];
+  let valid = scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+    && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+    && !term.chars().any(char::is_whitespace);
+  valid.then_some(scheme)
+}
+
+/* Split a Literal into its (lexical form, language, datatype) */
+pub fn literal(term: &str) -> (String, Option<String>, Option<String>) {
+  if term.starts_with('"') {
+    if let Some(end) = term.rfind('"') {
+      if end > 0 {
+        let lexical = unescape(&term[1..end]);
+        let rest = &term[end+1..];
+        if let Some(lang) = rest.strip_prefix('@') {
+          return (lexical, Some(lang.to_string()), None)
+        }
+        if let Some(datatype) = rest.strip_prefix("^^") {
+          return (lexical, None, Some(datatype.trim_start_matches('<')
+                                              .trim_end_matches('>')
+                                              .to_string()))
+        }
+        if rest.is_empty() {
+          return (lexical, None, None)
+        }
+      }
+    }
+  }
+  (term.to_string(), None, None)
+}
+/*
+The inverse of literal(), plain literals are stored as-is
+unless they'd be mistaken for an IRI or Blank Node
+*/
+pub fn make_literal(lexical: &str, lang: Option<&str>, datatype: Option<&str>) -> String {
+  match (lang, datatype) {
+    (Some(lang), _)  => format!("\"{}\"@{}", escape(lexical), lang),
+    (None, Some(dt)) => format!("\"{}\"^^<{}>", escape(lexical), dt),
+    (None, None)     => {
+      if kind(lexical) != TermKind::Literal || lexical.starts_with('"') {
+        return format!("\"{}\"", escape(lexical))
+      }
+      lexical.to_string()
+    },
+  }
+}
+
+/* N-Triples/Turtle spelling of a term */
+pub fn to_ntriples(term: &str) -> String {
+  match kind(term) {
+    TermKind::Iri       => format!("<{}>", term),
+    TermKind::BlankNode => term.to_string(),
+    TermKind::Literal   => {
+      match literal(term) {
+        (lexical, None, None) => format!("\"{}\"", escape(&lexical)),
+        _ => term.to_string(),
+      }
+    },
+  }
+}
+
+pub fn escape(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '"'  => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      '\r' => escaped.push_str("\\r"),
+      '\t' => escaped.push_str("\\t"),
+      _    => escaped.push(c),
+    }
+  }
+  escaped
+}
+pub fn unescape(s: &str) -> String {
+  let mut unescaped = String::with_capacity(s.len());
+  let mut chars = s.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      unescaped.push(c);
+      continue
+    }
+    match chars.next() {
+      Some('n') => unescaped.push('\n'),
+      Some('r') => unescaped.push('\r'),
+      Some('t') => unescaped.push('\t'),
+      Some('u') => push_codepoint(&mut unescaped, &mut chars, 4),
+      Some('U') => push_codepoint(&mut unescaped, &mut chars, 8),
+      Some(c)   => unescaped.push(c),
+      None      => unescaped.push('\\'),
+    }
+  }
+  unescaped
+}
+fn push_codepoint(s: &mut String, chars: &mut std::str::Chars, len: usize) {
+  let hex: String = chars.take(len).collect();
+  match u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32) {
+    Some(c) => s.push(c),
+    None => s.push(std::char::REPLACEMENT_CHARACTER),
+  }
+}
+
+/* Resolve reference against base, as in RFC 3986 section 5.2 */
+pub fn resolve(base: &str, reference: &str) -> String {
+  let b = parts(base);
+  let r = parts(reference);
+  if r.scheme.is_some() {
+    return join(&Parts { path: &remove_dot_segments(r.path), ..r })
+  }
+  let (authority, path, query) = if r.authority.is_some() {
+    (r.authority, remove_dot_segments(r.path), r.query)
+  }
+  else if r.path.is_empty() {
+    (b.authority, b.path.to_string(), r.query.or(b.query))
+  }
+  else if r.path.starts_with('/') {
+    (b.authority, remove_dot_segments(r.path), r.query)
+  }
+  else {
+    let merged = match (b.authority, b.path.rfind('/')) {
+      (Some(_), None) if b.path.is_empty() => format!("/{}", r.path),
+      (_, Some(i)) => format!("{}{}", &b.path[..=i], r.path),
+      (_, None) => r.path.to_string(),
+    };
+    (b.authority, remove_dot_segments(&merged), r.query)
+  };
+  join(&Parts { scheme: b.scheme, authority, path: &path, query, fragment: r.fragment })
+}
+struct Parts<'a> {
+  scheme: Option<&'a str>,
+  authority: Option<&'a str>,
+  path: &'a str,
+  query: Option<&'a str>,
+  fragment: Option<&'a str>,
+}
+fn parts(iri: &str) -> Parts<'_> {
+  let (rest, fragment) = match iri.split_once('#') {
+    Some((rest, f)) => (rest, Some(f)),
+    None => (iri, None),
+  };
+  let (rest, query) = match rest.split_once('?') {
+    Some((rest, q)) => (rest, Some(q)),
+    None => (rest, None),
+  };
+  let (scheme, rest) = match rest.find(':') {
+    Some(i) if scheme(&rest[..=i]).is_some() => (Some(&rest[..i]), &rest[i+1..]),
+    _ => (None, rest),
+  };
+  let (authority, path) = match rest.strip_prefix("//") {
+    Some(rest) => {
+      let end = rest.find('/').unwrap_or(rest.len());
+      (Some(&rest[..end]), &rest[end..])
+    },
+    None => (None, rest),
+  };
+  Parts { scheme, authority, path, query, fragment }
+}
+fn join(p: &Parts) -> String {
+  let mut iri = String::new();
+  if let Some(scheme) = p.scheme {
+    iri.push_str(scheme);
+    iri.push(':');
+  }
+  if let Some(authority) = p.authority {
+    iri.push_str("//");
+    iri.push_str(authority);
+  }
+  iri.push_str(p.path);
+  if let Some(query) = p.query {
+    iri.push('?');
+    iri.push_str(query);
+  }
+  if let Some(fragment) = p.fragment {
+    iri.push('#');
+    iri.push_str(fragment);
+  }
+  iri
+}
+fn remove_dot_segments(path: &str) -> String {
+  let mut output: Vec<&str> = Vec::new();
+  let segments: Vec<&str> = path.split('/').collect();
+  for (i, segment) in segments.iter().enumerate() {
+    let last = i == segments.len() - 1;
+    match *segment {
+      "." => if last { output.push("") },
+      ".." => {
+        if output.len() > 1 || output.first().is_some_and(|s| !s.is_empty()) {
+          output.pop();
+        }
+        if last {
+          output.push("");
+        }
+      },
+      s => output.push(s),
+    }
+  }
+  let joined = output.join("/");
+  if path.starts_with('/') && !joined.starts_with('/') {
+    return format!("/{}", joined)
+  }
+  joined
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use super::{
+  DataStores::Graph::Graph,
+  Error::Error,
+  SPARQL::Query::Query,
+};
+
+type Triple = (String, String, String);
+
+/*
+The JavaScript face of Oji.
+Triples go in and come out as [s, p, o] arrays, query results
+as plain objects keyed by variable name, and any OjiError is
+thrown as a JS Error carrying the same message.
+*/
+#[wasm_bindgen(js_name = Graph)]
+pub struct JsGraph {
+  graph: Graph,
+}
+#[wasm_bindgen(js_class = Graph)]
+impl JsGraph {
+  #[wasm_bindgen(constructor)]
+  pub fn new() -> Self {
+    JsGraph { graph: Graph::new() }
+  }
+  #[wasm_bindgen(js_name = fromJson)]
+  pub fn from_json(data: &str) -> Result<JsGraph, JsError> {
+    Ok(JsGraph { graph: Graph::from_json(data).map_err(thrown)? })
+  }
+  pub fn insert(&mut self, triple: JsValue) -> Result<(), JsError> {
+    self.graph.insert(triple_from(triple)?);
+    Ok(())
+  }
+  pub fn remove(&mut self, triple: JsValue) -> Result<(), JsError> {
+    self.graph.remove(&triple_from(triple)?);
+    Ok(())
+  }
+  pub fn replace(&mut self, old_triple: JsValue, new_triple: JsValue) -> Result<(), JsError> {
+    self.graph.replace(&triple_from(old_triple)?, triple_from(new_triple)?);
+    Ok(())
+  }
+  #[wasm_bindgen(getter)]
+  pub fn size(&self) -> usize {
+    self.graph.iter().count()
+  }
+  pub fn triples(&self) -> Result<JsValue, JsError> {
+    let triples: Vec<Triple> = self.graph.iter().collect();
+    to_js(&triples)
+  }
+  pub fn json(&self) -> Result<String, JsError> {
+    self.graph.json().map_err(thrown)
+  }
+  /*
+    Takes whatever import_json does: json() documents or arrays of
+    triples. Blank nodes are scoped to each call, so _:b in two
+    documents is two nodes, and a bad document inserts nothing
+  */
+  #[wasm_bindgen(js_name = insertJson)]
+  pub fn insert_json(&mut self, data: &str) -> Result<(), JsError> {
+    self.graph.import_json(data).map_err(thrown)?;
+    Ok(())
+  }
+  /* Takes the TripleStore documents json() produces */
+  #[wasm_bindgen(js_name = removeJson)]
+  pub fn remove_json(&mut self, data: &str) -> Result<(), JsError> {
+    for triple in Graph::from_json(data).map_err(thrown)? {
+      self.graph.remove(&triple);
+    }
+    Ok(())
+  }
+  /*
+    graph.query(["$name"], [["$name", "likes", "Rust"]])
+      => [{ name: "Gabe" }, ...]
+  */
+  pub fn query(&self, vars: JsValue, conds: JsValue) -> Result<JsValue, JsError> {
+    let vars: Vec<String> = from_js(vars)?;
+    let conds: Vec<Triple> = from_js(conds)?;
+    let vars: Vec<&str> = vars.iter().map(String::as_str).collect();
+    let results = Query::new().from(&self.graph)
+                              .select(&vars)
+                              .filter(&borrowed(&conds))
+                              .map_err(thrown)?
+                              .fetch();
+    let rows: Vec<BTreeMap<String, String>> = results.results.iter()
+      .map(|r| r.var_map.keys()
+                        .filter_map(|var| r.get_var(var).map(|val| (var.clone(), val)))
+                        .collect())
+      .collect();
+    to_js(&rows)
+  }
+  pub fn ask(&self, conds: JsValue) -> Result<bool, JsError> {
+    let conds: Vec<Triple> = from_js(conds)?;
+    Ok(Query::new().from(&self.graph).ask(&borrowed(&conds)))
+  }
+  pub fn update(&mut self, request: &str) -> Result<(), JsError> {
+    self.graph.update(request).map_err(thrown)
+  }
+}
+impl Default for JsGraph {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn thrown(e: Error) -> JsError {
+  JsError::new(&e.to_string())
+}
+fn from_js<T: serde::de::DeserializeOwned>(value: JsValue) -> Result<T, JsError> {
+  serde_wasm_bindgen::from_value(value)
+    .map_err(|e| thrown(Error::Serialization(e.to_string())))
+}
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsError> {
+  /* Plain objects rather than ES Maps for results */
+  let serializer = serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true);
+  value.serialize(&serializer)
+       .map_err(|e| thrown(Error::Serialization(e.to_string())))
+}
+fn triple_from(value: JsValue) -> Result<Triple, JsError> {
+  from_js(value)
+}
+fn borrowed(conds: &[Triple]) -> Vec<(&str, &str, &str)> {
+  conds.iter()
+       .map(|(s, p, o)| (s.as_str(), p.as_str(), o.as_str()))
+       .collect()
+}
@@ -0,0 +1,245 @@
+/*
+Just enough XML to read the documents Oji deals in:
+elements, attributes, text, CDATA and the predefined/numeric
+entities. Prolog, comments, processing instructions and
+DOCTYPEs are skipped.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmlNode {
+  Element(XmlElement),
+  Text(String),
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlElement {
+  pub name: String,
+  pub attrs: Vec<(String, String)>,
+  pub children: Vec<XmlNode>,
+}
+impl XmlElement {
+  pub fn attr(&self, name: &str) -> Option<&str> {
+    self.attrs.iter()
+              .find(|(n, _)| n == name)
+              .map(|(_, v)| v.as_str())
+  }
+  pub fn local_name(&self) -> &str {
+    match self.name.find(':') {
+      Some(i) => &self.name[i+1..],
+      None => &self.name,
+    }
+  }
+  pub fn elements(&self) -> impl Iterator<Item=&XmlElement> {
+    self.children.iter().filter_map(|c| match c {
+      XmlNode::Element(e) => Some(e),
+      XmlNode::Text(_) => None,
+    })
+  }
+  pub fn child(&self, local_name: &str) -> Option<&XmlElement> {
+    self.elements().find(|e| e.local_name() == local_name)
+  }
+  pub fn text(&self) -> String {
+    let mut text = String::new();
+    for c in self.children.iter() {
+      match c {
+        XmlNode::Text(t) => text.push_str(t),
+        XmlNode::Element(e) => text.push_str(&e.text()),
+      }
+    }
+    text
+  }
+}
+
+pub fn parse(doc: &str) -> Result<XmlElement, String> {
+  let mut reader = Reader { doc, pos: 0 };
+  reader.skip_misc()?;
+  let root = reader.element()?;
+  reader.skip_misc()?;
+  if reader.pos < doc.len() {
+    return Err(format!("Content after the root element at byte {}", reader.pos))
+  }
+  Ok(root)
+}
+
+struct Reader<'a> {
+  doc: &'a str,
+  pos: usize,
+}
+impl<'a> Reader<'a> {
+  fn rest(&self) -> &'a str {
+    &self.doc[self.pos..]
+  }
+  fn skip_whitespace(&mut self) {
+    let rest = self.rest();
+    self.pos += rest.len() - rest.trim_start().len();
+  }
+  fn skip_past(&mut self, end: &str) -> Result<(), String> {
+    match self.rest().find(end) {
+      Some(i) => {
+        self.pos += i + end.len();
+        Ok(())
+      },
+      None => Err(format!("Expected '{}' before end of document", end)),
+    }
+  }
+  /* Prolog, comments, PIs and DOCTYPEs */
+  fn skip_misc(&mut self) -> Result<(), String> {
+    loop {
+      self.skip_whitespace();
+      let rest = self.rest();
+      if rest.starts_with("<?") {
+        self.skip_past("?>")?;
+      }
+      else if rest.starts_with("<!--") {
+        self.skip_past("-->")?;
+      }
+      else if rest.starts_with("<!DOCTYPE") {
+        self.skip_past(">")?;
+      }
+      else {
+        return Ok(())
+      }
+    }
+  }
+  fn name(&mut self) -> Result<String, String> {
+    let rest = self.rest();
+    let len = rest.find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+                  .unwrap_or(rest.len());
+    if len == 0 {
+      return Err(format!("Expected a name at byte {}", self.pos))
+    }
+    self.pos += len;
+    Ok(rest[..len].to_string())
+  }
+  fn expect(&mut self, s: &str) -> Result<(), String> {
+    if self.rest().starts_with(s) {
+      self.pos += s.len();
+      return Ok(())
+    }
+    Err(format!("Expected '{}' at byte {}", s, self.pos))
+  }
+  fn element(&mut self) -> Result<XmlElement, String> {
+    self.expect("<")?;
+    let name = self.name()?;
+    let mut attrs = Vec::new();
+    loop {
+      self.skip_whitespace();
+      if self.rest().starts_with("/>") {
+        self.pos += 2;
+        return Ok(XmlElement { name, attrs, children: Vec::new() })
+      }
+      if self.rest().starts_with('>') {
+        self.pos += 1;
+        break
+      }
+      let attr = self.name()?;
+      self.skip_whitespace();
+      self.expect("=")?;
+      self.skip_whitespace();
+      let quote = match self.rest().chars().next() {
+        Some(q) if q == '"' || q == '\'' => q,
+        _ => return Err(format!("Expected a quoted value for '{}'", attr)),
+      };
+      self.pos += 1;
+      let len = match self.rest().find(quote) {
+        Some(len) => len,
+        None => return Err(format!("Unterminated value for '{}'", attr)),
+      };
+      let value = unescape(&self.rest()[..len])?;
+      self.pos += len + 1;
+      attrs.push((attr, value));
+    }
+    let mut children = Vec::new();
+    loop {
+      let rest = self.rest();
+      if rest.starts_with("</") {
+        self.pos += 2;
+        let end = self.name()?;
+        if end != name {
+          return Err(format!("Expected </{}> but found </{}>", name, end))
+        }
+        self.skip_whitespace();
+        self.expect(">")?;
+        return Ok(XmlElement { name, attrs, children })
+      }
+      else if rest.starts_with("<!--") {
+        self.skip_past("-->")?;
+      }
+      else if rest.starts_with("<![CDATA[") {
+        self.pos += 9;
+        let len = match self.rest().find("]]>") {
+          Some(len) => len,
+          None => return Err("Unterminated CDATA section".into()),
+        };
+        push_text(&mut children, &self.rest()[..len]);
+        self.pos += len + 3;
+      }
+      else if rest.starts_with("<?") {
+        self.skip_past("?>")?;
+      }
+      else if rest.starts_with('<') {
+        children.push(XmlNode::Element(self.element()?));
+      }
+      else if rest.is_empty() {
+        return Err(format!("Unclosed element <{}>", name))
+      }
+      else {
+        let len = rest.find('<').unwrap_or(rest.len());
+        push_text(&mut children, &unescape(&rest[..len])?);
+        self.pos += len;
+      }
+    }
+  }
+}
+fn push_text(children: &mut Vec<XmlNode>, text: &str) {
+  if let Some(XmlNode::Text(t)) = children.last_mut() {
+    t.push_str(text);
+    return
+  }
+  children.push(XmlNode::Text(text.to_string()));
+}
+
+pub fn escape(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '<'  => escaped.push_str("&lt;"),
+      '>'  => escaped.push_str("&gt;"),
+      '&'  => escaped.push_str("&amp;"),
+      '"'  => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&apos;"),
+      _    => escaped.push(c),
+    }
+  }
+  escaped
+}
+pub fn unescape(s: &str) -> Result<String, String> {
+  let mut unescaped = String::with_capacity(s.len());
+  let mut rest = s;
+  while let Some(i) = rest.find('&') {
+    unescaped.push_str(&rest[..i]);
+    rest = &rest[i+1..];
+    let end = match rest.find(';') {
+      Some(end) => end,
+      None => return Err("Unterminated entity reference".into()),
+    };
+    let entity = &rest[..end];
+    let c = match entity {
+      "lt"   => Some('<'),
+      "gt"   => Some('>'),
+      "amp"  => Some('&'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok()
+                                                                           .and_then(std::char::from_u32),
+      _ if entity.starts_with('#') => entity[1..].parse().ok()
+                                                      .and_then(std::char::from_u32),
+      _ => None,
+    };
+    match c {
+      Some(c) => unescaped.push(c),
+      None => return Err(format!("Unknown entity '&{};'", entity)),
+    }
+    rest = &rest[end+1..];
+  }
+  unescaped.push_str(rest);
+  Ok(unescaped)
+}
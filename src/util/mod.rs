@@ -1,11 +1,29 @@
 #![allow(non_snake_case)]
 
 mod DataStores;
+mod Error;
+pub(crate) mod Ffi;
+mod Formats;
 mod SPARQL;
+mod Term;
+#[cfg(feature = "wasm")]
+mod Wasm;
+mod Xml;
 
+pub use DataStores::Dataset::Dataset as Dataset;
+pub use DataStores::Diff::GraphDiff as GraphDiff;
 pub use DataStores::Graph::Graph as Graph;
 pub use DataStores::Graph::GraphIterator as GraphIterator;
 pub use DataStores::Graph::GraphRefIterator as GraphRefIterator;
+pub use DataStores::MappedGraph::MappedGraph as MappedGraph;
+pub use DataStores::ObservedGraph::ObservedGraph as ObservedGraph;
+pub use DataStores::Observers::GraphChange as GraphChange;
+pub use DataStores::Observers::SubscriptionId as SubscriptionId;
+pub use DataStores::PatternMatch::PatternMatch as PatternMatch;
+pub use DataStores::SharedGraph::SharedGraph as SharedGraph;
+pub use DataStores::SharedGraph::GraphSnapshot as GraphSnapshot;
+pub use DataStores::Maintenance::CompactionStats as CompactionStats;
+pub use DataStores::Maintenance::MaintenanceHandle as MaintenanceHandle;
 pub use DataStores::TripleStore::TripleStore as TripleStore;
 pub use DataStores::TripleStore::TripleStoreIterator as TripleStoreIterator;
 pub use DataStores::TripleStore::TripleStoreRefIterator as TripleStoreRefIterator;
@@ -14,6 +32,11 @@ pub use SPARQL::Query::QueryUnit as OjiQueryUnit;
 pub use SPARQL::Result::ResultUnit as OjiResultUnit;
 pub use SPARQL::Result::Result as OjiResult;
 pub use SPARQL::Result::ResultCollection as OjiResultCollection;
+pub use SPARQL::Update::Update as OjiUpdate;
+pub use Error::Error as OjiError;
+pub use Formats::Ion::IonFormat as IonFormat;
+#[cfg(feature = "wasm")]
+pub use Wasm::JsGraph as JsGraph;
 
 //Delcare common resources for nested modules
 #[allow(dead_code)]
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Vendors the W3C RDF 1.1 XML Syntax test suite into tests/rdf-xml/w3c,
+# manifest and all, for the rdf_xml::w3c_suite test to run.
+set -e
+dest="$(cd "$(dirname "$0")" && pwd)/w3c"
+tmp="$(mktemp -d)"
+trap 'rm -rf "$tmp"' EXIT
+git clone --quiet --depth 1 --filter=blob:none --sparse https://github.com/w3c/rdf-tests "$tmp"
+git -C "$tmp" sparse-checkout set rdf/rdf11/rdf-xml
+rm -rf "$dest"
+cp -R "$tmp/rdf/rdf11/rdf-xml" "$dest"
+echo "vendored $(grep -c 'mf:action' "$dest/manifest.ttl") cases into $dest"